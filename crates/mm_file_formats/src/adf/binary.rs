@@ -1,10 +1,11 @@
 use std::{
-    ops::{Deref, DerefMut},
-    sync::{Arc, Mutex},
+    collections::HashSet,
+    ops::{Deref, DerefMut, Range},
+    sync::Arc,
 };
 
 use aligned_vec::{avec_rt, AVec, RuntimeAlign};
-use binrw::{binrw, BinRead, BinWrite};
+use binrw::{binrw, BinRead, BinWrite, Endian};
 use bitflags::bitflags;
 use modular_bitfield::{
     bitfield,
@@ -14,16 +15,17 @@ use thiserror::Error;
 
 use mm_hashing::{hash_little32, HashString};
 
-use crate::common::{LengthVec, NullString, WriterExt};
+use crate::common::{read_pod, LengthVec, NullString, WriterExt};
 
-use super::{AdfRead, AdfReadWriteError, AdfTypeInfo, AdfWrite};
+use super::{built_in_types, AdfRead, AdfReadWriteError, AdfTypeInfo, AdfWrite};
 
 #[derive(Clone, Debug, Default)]
 pub struct AdfFile {
     pub version: AdfVersion,
     pub instances: Vec<Arc<AdfInstance>>,
     pub types: Vec<AdfType>,
-    pub hashes: Vec<HashString>,
+    // `None` rebuilds the table from the instances when written, an empty table isn't written
+    pub hashes: Option<Vec<HashString>>,
     pub description: NullString,
 }
 
@@ -75,32 +77,59 @@ impl AdfFile {
             .cloned()
     }
 
+    #[inline]
+    pub fn get_instance_mut_by_type(
+        &mut self,
+        name: impl AsRef<str>,
+        type_def: &AdfType,
+    ) -> Option<&mut AdfInstance> {
+        self.get_instance_mut_by_hash(name, type_def.type_hash)
+    }
+
+    #[inline]
+    pub fn get_instance_mut_by_info<T: AdfTypeInfo>(
+        &mut self,
+        name: impl AsRef<str>,
+    ) -> Option<&mut AdfInstance> {
+        self.get_instance_mut_by_hash(name, T::HASH)
+    }
+
+    // Instances shared elsewhere are cloned before being handed out, their buffers are
+    // copied lazily when written to
+    pub fn get_instance_mut_by_hash(
+        &mut self,
+        name: impl AsRef<str>,
+        type_hash: u32,
+    ) -> Option<&mut AdfInstance> {
+        let name = name.as_ref();
+        self.instances
+            .iter_mut()
+            .find(|inst| inst.type_hash == type_hash && inst.name.as_ref() == name)
+            .map(Arc::make_mut)
+    }
+
     #[inline]
     pub fn new_instance_from_type(
         &mut self,
         name: impl AsRef<str>,
         type_def: &AdfType,
-    ) -> Option<Arc<AdfInstance>> {
+    ) -> Option<&mut AdfInstance> {
         let name = name.as_ref();
-        if let Some(instance) = self.get_instance_by_hash(name, type_def.type_hash) {
-            Some(instance)
-        } else {
-            self.instances.push(Arc::new(AdfInstance {
-                name: NullString::from(name).into(),
-                type_hash: type_def.type_hash,
-                buffer: Mutex::new(
-                    avec_rt!([type_def.alignment as usize]| 0u8; type_def.size as usize).into(),
-                ),
-            }));
-            self.instances.last().cloned()
+        if self
+            .get_instance_by_hash(name, type_def.type_hash)
+            .is_none()
+        {
+            let instance = self.default_instance(name, type_def);
+            self.instances.push(Arc::new(instance));
         }
+        self.get_instance_mut_by_hash(name, type_def.type_hash)
     }
 
     #[inline]
     pub fn new_instance_from_info<T: AdfTypeInfo>(
         &mut self,
         name: impl AsRef<str>,
-    ) -> Option<Arc<AdfInstance>> {
+    ) -> Option<&mut AdfInstance> {
         self.new_instance_from_hash(name, T::HASH)
     }
 
@@ -108,20 +137,84 @@ impl AdfFile {
         &mut self,
         name: impl AsRef<str>,
         type_hash: u32,
-    ) -> Option<Arc<AdfInstance>> {
+    ) -> Option<&mut AdfInstance> {
         let name = name.as_ref();
-        if let Some(instance) = self.get_instance_by_hash(name, type_hash) {
-            Some(instance)
-        } else {
+        if self.get_instance_by_hash(name, type_hash).is_none() {
             let type_def = self.get_type_by_hash(type_hash)?;
-            self.instances.push(Arc::new(AdfInstance {
-                name: NullString::from(name).into(),
-                type_hash: type_def.type_hash,
-                buffer: Mutex::new(
-                    avec_rt!([type_def.alignment as usize]| 0u8; type_def.size as usize).into(),
-                ),
-            }));
-            self.instances.last().cloned()
+            let instance = self.default_instance(name, type_def);
+            self.instances.push(Arc::new(instance));
+        }
+        self.get_instance_mut_by_hash(name, type_hash)
+    }
+
+    // New instances start out with the defaults of their members; only plain data can be
+    // written this way, anything needing storage of its own is left zeroed
+    fn default_instance(&self, name: &str, type_def: &AdfType) -> AdfInstance {
+        let mut instance = AdfInstance::from_type(name, type_def);
+        self.write_defaults(type_def, instance.buffer.to_mut(), 0);
+        instance
+    }
+
+    fn write_defaults(&self, type_def: &AdfType, buffer: &mut [u8], offset: usize) {
+        if type_def.primitive == AdfPrimitive::InlineArray {
+            if let Some(element_type) = self.resolve_type_by_hash(type_def.element_type_hash) {
+                for index in 0..type_def.element_length as usize {
+                    let offset = offset + index * element_type.size as usize;
+                    self.write_defaults(element_type, buffer, offset);
+                }
+            }
+            return;
+        }
+
+        for member in type_def.members.iter() {
+            let Some(member_type) = self.resolve_type_by_hash(member.type_hash) else {
+                continue;
+            };
+            let offset = offset + member.offsets.byte() as usize;
+            let size = member_type.size as usize;
+            let Some(slice) = buffer.get_mut(offset..offset + size) else {
+                continue;
+            };
+            match &member.value {
+                AdfMemberValue::InlineValue(value)
+                    if member_type.primitive == AdfPrimitive::Bitfield && size <= 8 =>
+                {
+                    let shift = u32::from(member.offsets.bit());
+                    let mask = 1u64
+                        .checked_shl(member_type.element_length)
+                        .map_or(u64::MAX, |x| x - 1)
+                        << shift;
+                    let mut bytes = [0u8; 8];
+                    bytes[..size].copy_from_slice(slice);
+                    let storage = (u64::from_le_bytes(bytes) & !mask) | ((value << shift) & mask);
+                    slice.copy_from_slice(&storage.to_le_bytes()[..size]);
+                }
+                AdfMemberValue::InlineValue(value)
+                    if matches!(
+                        member_type.primitive,
+                        AdfPrimitive::Scalar | AdfPrimitive::Enumeration | AdfPrimitive::StringHash
+                    ) && size <= 8 =>
+                {
+                    slice.copy_from_slice(&value.to_le_bytes()[..size]);
+                }
+                AdfMemberValue::InstanceValue(instance) => {
+                    if let Some(bytes) = self.plain_instance_bytes(instance, size) {
+                        slice.copy_from_slice(&bytes[..size]);
+                    }
+                }
+                _ => self.write_defaults(member_type, buffer, offset),
+            }
+        }
+    }
+
+    fn plain_instance_bytes(&self, instance: &AdfInstance, size: usize) -> Option<Vec<u8>> {
+        let type_def = self.resolve_type_by_hash(instance.type_hash)?;
+        if !type_def.flags.contains(AdfTypeFlags::POD_READ) || instance.buffer.len() < size {
+            return None;
+        }
+        match instance.endian {
+            Endian::Little => Some(instance.buffer.to_vec()),
+            Endian::Big => self.swap_instance(instance).ok(),
         }
     }
 
@@ -138,6 +231,414 @@ impl AdfFile {
             false
         }
     }
+
+    #[inline]
+    pub fn get_alignment_by_hash(&self, type_hash: u32) -> Option<u64> {
+        self.resolve_type_by_hash(type_hash)
+            .map(|type_info| type_info.alignment as u64)
+    }
+
+    // Built-in types are rarely embedded, so fall back to them when a type is missing
+    pub fn resolve_type_by_hash(&self, type_hash: u32) -> Option<&AdfType> {
+        self.get_type_by_hash(type_hash).or_else(|| {
+            built_in_types()
+                .iter()
+                .find(|type_def| type_def.type_hash == type_hash)
+        })
+    }
+
+    pub fn detect_endian<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+    ) -> binrw::BinResult<Endian> {
+        let pos = reader.stream_position()?;
+        let magic = <[u8; 4]>::read_options(reader, Endian::Little, ())?;
+        reader.seek(std::io::SeekFrom::Start(pos))?;
+        match &magic {
+            b" FDA" => Ok(Endian::Little),
+            b"ADF " => Ok(Endian::Big),
+            _ => Err(binrw::Error::BadMagic {
+                pos,
+                found: Box::new(magic),
+            }),
+        }
+    }
+
+    // Instance buffers borrow from the given bytes, and are only copied once mutated
+    pub fn from_bytes(bytes: impl Into<AdfBytes>) -> binrw::BinResult<Self> {
+        let bytes = bytes.into();
+        let mut reader = std::io::Cursor::new(&*bytes);
+        let endian = Self::detect_endian(&mut reader)?;
+        Self::read_from(&mut reader, endian, Some(&bytes))
+    }
+
+    pub fn swap_instance(&self, instance: &AdfInstance) -> Result<Vec<u8>, AdfInstanceError> {
+        let mut swapper = AdfSwapper {
+            file: self,
+            buffer: instance.buffer.to_vec(),
+            from: instance.endian,
+            swapped: vec![false; instance.buffer.len()],
+            visited: HashSet::default(),
+            pending: vec![],
+        };
+        swapper.swap_value_by_hash(instance.type_hash, 0)?;
+        Ok(swapper.buffer)
+    }
+
+    pub fn collect_hashes(&self) -> Vec<HashString> {
+        let mut hashes = Vec::<HashString>::default();
+        for instance in &self.instances {
+            self.walk_instance(instance, &mut |type_info, offset| {
+                if type_info.primitive == AdfPrimitive::StringHash {
+                    hashes.extend(
+                        read_offset(
+                            &instance.buffer,
+                            offset,
+                            type_info.size as usize,
+                            instance.endian,
+                        )
+                        .map(|x| HashString::new(x as u32)),
+                    );
+                }
+            });
+        }
+
+        // Keep the order in which hashes were first encountered
+        let mut unique = HashSet::<HashString>::default();
+        hashes.retain(|hash| unique.insert(*hash));
+        hashes
+    }
+
+    // Calls `visit` with the type and offset of every value reachable from the instance,
+    // including the targets of pointers, arrays and deferred values, in depth first order
+    pub(crate) fn walk_instance(
+        &self,
+        instance: &AdfInstance,
+        visit: &mut impl FnMut(&AdfType, usize),
+    ) {
+        let mut walker = AdfWalker {
+            file: self,
+            buffer: &instance.buffer,
+            endian: instance.endian,
+            visited: HashSet::default(),
+            pending: vec![AdfPendingValues::one(instance.type_hash, 0)],
+        };
+        while let Some(values) = walker.pending.pop() {
+            walker.pending.extend(values.rest());
+            walker.walk_value(values.type_hash, values.offset, visit);
+        }
+    }
+}
+
+struct AdfWalker<'a> {
+    file: &'a AdfFile,
+    buffer: &'a [u8],
+    endian: Endian,
+    visited: HashSet<(u32, usize)>,
+    pending: Vec<AdfPendingValues>,
+}
+
+impl AdfWalker<'_> {
+    // Visits the value at `offset`, queueing up the values it contains or points to
+    fn walk_value(
+        &mut self,
+        type_hash: u32,
+        offset: usize,
+        visit: &mut impl FnMut(&AdfType, usize),
+    ) {
+        let file = self.file;
+        // Types we don't know about can't be traversed, so they're skipped
+        let Some(type_info) = file.resolve_type_by_hash(type_hash) else {
+            return;
+        };
+        visit(type_info, offset);
+
+        // Offsets come from the buffer, those out of range fail to read rather than overflow
+        let (buffer, endian) = (self.buffer, self.endian);
+        let read = |offset: usize, size: usize| read_offset(buffer, offset, size, endian);
+        match type_info.primitive {
+            AdfPrimitive::Structure => {
+                // Queued in reverse, so members are visited in order
+                for member in type_info.members.iter().rev() {
+                    self.pending.push(AdfPendingValues::one(
+                        member.type_hash,
+                        offset.saturating_add(member.offsets.byte() as usize),
+                    ));
+                }
+            }
+            AdfPrimitive::Pointer | AdfPrimitive::Recursive | AdfPrimitive::Deferred => {
+                // Deferred values store the type of their target after the pointer
+                let element_type_hash = match type_info.primitive {
+                    AdfPrimitive::Deferred => match read(offset.saturating_add(8), 4) {
+                        Some(type_hash) => type_hash as u32,
+                        None => return,
+                    },
+                    _ => type_info.element_type_hash,
+                };
+                if let Some(pointer) = read(offset, 8).filter(|&x| x != 0) {
+                    // Pointers may be shared or cyclic, so only visit each target once
+                    let pointer = pointer as usize;
+                    if self.visited.insert((element_type_hash, pointer)) {
+                        self.pending
+                            .push(AdfPendingValues::one(element_type_hash, pointer));
+                    }
+                }
+            }
+            AdfPrimitive::Array => {
+                let (Some(pointer), Some(count)) =
+                    (read(offset, 8), read(offset.saturating_add(8), 8))
+                else {
+                    return;
+                };
+                let Some(element_info) = file.resolve_type_by_hash(type_info.element_type_hash)
+                else {
+                    return;
+                };
+                let pointer = pointer as usize;
+                if pointer != 0 && self.visited.insert((type_info.type_hash, pointer)) {
+                    self.pending.extend(AdfPendingValues::elements(
+                        element_info.type_hash,
+                        pointer,
+                        count as usize,
+                        element_info.size as usize,
+                        buffer.len(),
+                    ));
+                }
+            }
+            AdfPrimitive::InlineArray => {
+                let Some(element_info) = file.resolve_type_by_hash(type_info.element_type_hash)
+                else {
+                    return;
+                };
+                self.pending.extend(AdfPendingValues::elements(
+                    element_info.type_hash,
+                    offset,
+                    type_info.element_length as usize,
+                    element_info.size as usize,
+                    buffer.len(),
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn read_offset(buffer: &[u8], offset: usize, size: usize, endian: Endian) -> Option<u64> {
+    buffer
+        .get(offset..offset.checked_add(size)?)
+        .and_then(|bytes| match size {
+            4 => Some(read_pod::<u32>(bytes, endian) as u64),
+            8 => Some(read_pod::<u64>(bytes, endian)),
+            _ => None,
+        })
+}
+
+// Consecutive values of one type that are still to be visited; values are visited from a stack
+// of these rather than recursively, so deeply nested or long linked buffers can't overflow it
+struct AdfPendingValues {
+    type_hash: u32,
+    offset: usize,
+    count: usize,
+    stride: usize,
+}
+
+impl AdfPendingValues {
+    #[inline]
+    fn one(type_hash: u32, offset: usize) -> Self {
+        Self {
+            type_hash,
+            offset,
+            count: 1,
+            stride: 0,
+        }
+    }
+
+    // The values after the first, if there are any
+    #[inline]
+    fn rest(&self) -> Option<Self> {
+        (self.count > 1).then(|| Self {
+            type_hash: self.type_hash,
+            offset: self.offset.saturating_add(self.stride),
+            count: self.count - 1,
+            stride: self.stride,
+        })
+    }
+
+    // Elements of an array, checking that their range doesn't overflow. Counts come from the
+    // buffer, so they're capped to its length, and elements without a size are only visited once
+    fn elements(
+        type_hash: u32,
+        offset: usize,
+        count: usize,
+        size: usize,
+        buffer_len: usize,
+    ) -> Option<Self> {
+        let count = match size {
+            0 => count.min(1),
+            _ => count.min(buffer_len),
+        };
+        offset.checked_add(count.checked_mul(size)?)?;
+        Some(Self {
+            type_hash,
+            offset,
+            count,
+            stride: size,
+        })
+    }
+}
+
+struct AdfSwapper<'a> {
+    file: &'a AdfFile,
+    buffer: Vec<u8>,
+    from: Endian,
+    swapped: Vec<bool>,
+    visited: HashSet<(u32, usize)>,
+    pending: Vec<AdfPendingValues>,
+}
+
+impl AdfSwapper<'_> {
+    fn swap_value_by_hash(
+        &mut self,
+        type_hash: u32,
+        offset: usize,
+    ) -> Result<(), AdfInstanceError> {
+        self.pending.push(AdfPendingValues::one(type_hash, offset));
+        while let Some(values) = self.pending.pop() {
+            self.pending.extend(values.rest());
+            self.swap_value(values.type_hash, values.offset)?;
+        }
+        Ok(())
+    }
+
+    // Swaps the value at `offset`, queueing up the values it contains or points to
+    fn swap_value(&mut self, type_hash: u32, offset: usize) -> Result<(), AdfInstanceError> {
+        let file = self.file;
+        let Some(type_info) = file.resolve_type_by_hash(type_hash) else {
+            return Err(AdfInstanceError::UnknownType(type_hash));
+        };
+
+        match type_info.primitive {
+            AdfPrimitive::Scalar
+            | AdfPrimitive::Bitfield
+            | AdfPrimitive::Enumeration
+            | AdfPrimitive::StringHash => self.swap(offset, type_info.size as usize)?,
+            AdfPrimitive::Structure => {
+                // Queued in reverse, so members are swapped in order
+                for member in type_info.members.iter().rev() {
+                    let offset = offset
+                        .checked_add(member.offsets.byte() as usize)
+                        .ok_or(AdfInstanceError::OutOfBounds(offset))?;
+                    self.pending
+                        .push(AdfPendingValues::one(member.type_hash, offset));
+                }
+            }
+            AdfPrimitive::Pointer | AdfPrimitive::Recursive => {
+                let pointer = self.swap_offset(offset)?;
+                self.swap_target(type_info.element_type_hash, pointer);
+            }
+            AdfPrimitive::Array => {
+                let pointer = self.swap_offset(offset)?;
+                let count = self.swap_offset(Self::field(offset, 8)?)?;
+                let Some(element_info) = file.resolve_type_by_hash(type_info.element_type_hash)
+                else {
+                    return Err(AdfInstanceError::UnknownType(type_info.element_type_hash));
+                };
+                if pointer != 0 && self.visited.insert((type_info.type_hash, pointer)) {
+                    self.pending.push(
+                        AdfPendingValues::elements(
+                            element_info.type_hash,
+                            pointer,
+                            count,
+                            element_info.size as usize,
+                            self.buffer.len(),
+                        )
+                        .ok_or(AdfInstanceError::OutOfBounds(pointer))?,
+                    );
+                }
+            }
+            AdfPrimitive::InlineArray => {
+                let Some(element_info) = file.resolve_type_by_hash(type_info.element_type_hash)
+                else {
+                    return Err(AdfInstanceError::UnknownType(type_info.element_type_hash));
+                };
+                self.pending.push(
+                    AdfPendingValues::elements(
+                        element_info.type_hash,
+                        offset,
+                        type_info.element_length as usize,
+                        element_info.size as usize,
+                        self.buffer.len(),
+                    )
+                    .ok_or(AdfInstanceError::OutOfBounds(offset))?,
+                );
+            }
+            AdfPrimitive::String => {
+                self.swap_offset(offset)?;
+            }
+            AdfPrimitive::Deferred => {
+                let pointer = self.swap_offset(offset)?;
+                let type_offset = Self::field(offset, 8)?;
+                let type_hash = self.read(type_offset, 4)? as u32;
+                self.swap(type_offset, 4)?;
+                self.swap_target(type_hash, pointer);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn swap_target(&mut self, type_hash: u32, pointer: usize) {
+        // Pointers may be shared or cyclic, so only visit each target once
+        if pointer != 0 && self.visited.insert((type_hash, pointer)) {
+            self.pending.push(AdfPendingValues::one(type_hash, pointer));
+        }
+    }
+
+    #[inline]
+    fn field(offset: usize, field_offset: usize) -> Result<usize, AdfInstanceError> {
+        offset
+            .checked_add(field_offset)
+            .ok_or(AdfInstanceError::OutOfBounds(offset))
+    }
+
+    fn swap_offset(&mut self, offset: usize) -> Result<usize, AdfInstanceError> {
+        let value = self.read(offset, 8)? as usize;
+        self.swap(offset, 8)?;
+        Ok(value)
+    }
+
+    fn read(&self, offset: usize, size: usize) -> Result<u64, AdfInstanceError> {
+        let Some(bytes) = offset
+            .checked_add(size)
+            .and_then(|end| self.buffer.get(offset..end))
+        else {
+            return Err(AdfInstanceError::OutOfBounds(offset));
+        };
+        // Bytes that were already swapped (through aliasing) are in the opposite order
+        let endian = match (self.swapped[offset], self.from) {
+            (false, endian) => endian,
+            (true, Endian::Little) => Endian::Big,
+            (true, Endian::Big) => Endian::Little,
+        };
+        Ok(match size {
+            4 => read_pod::<u32>(bytes, endian) as u64,
+            _ => read_pod::<u64>(bytes, endian),
+        })
+    }
+
+    fn swap(&mut self, offset: usize, size: usize) -> Result<(), AdfInstanceError> {
+        let Some(bytes) = offset
+            .checked_add(size)
+            .and_then(|end| self.buffer.get_mut(offset..end))
+        else {
+            return Err(AdfInstanceError::OutOfBounds(offset));
+        };
+        // Bitfields share storage, and pointers may alias, so only swap each value once
+        if size > 1 && !self.swapped[offset] {
+            bytes.reverse();
+            self.swapped[offset] = true;
+        }
+        Ok(())
+    }
 }
 
 impl BinRead for AdfFile {
@@ -148,6 +649,16 @@ impl BinRead for AdfFile {
         reader: &mut R,
         endian: binrw::Endian,
         _args: Self::Args<'_>,
+    ) -> binrw::BinResult<Self> {
+        Self::read_from(reader, endian, None)
+    }
+}
+
+impl AdfFile {
+    fn read_from<R: std::io::Read + std::io::Seek>(
+        reader: &mut R,
+        endian: binrw::Endian,
+        source: Option<&AdfBytes>,
     ) -> binrw::BinResult<Self> {
         use std::io::SeekFrom::Start;
 
@@ -183,7 +694,8 @@ impl BinRead for AdfFile {
             reader.seek(Start((header.instance_offset) as u64))?;
 
             for _ in 0..header.instance_count {
-                instances.push(AdfInstance::read_options(reader, endian, (&strings,))?.into());
+                instances
+                    .push(AdfInstance::read_options(reader, endian, (&strings, source))?.into());
             }
         }
         let instances = AdfReferenceCollector::<Arc<AdfInstance>>::new(instances.into());
@@ -216,21 +728,21 @@ impl BinRead for AdfFile {
             version: header.version,
             types,
             instances: instances.take(),
-            hashes,
+            hashes: Some(hashes),
             description: header.description,
         })
     }
 }
 
 impl BinWrite for AdfFile {
-    type Args<'a> = ();
+    type Args<'a> = (AdfLayout,);
 
     #[inline]
     fn write_options<W: std::io::Write + std::io::Seek>(
         &self,
         writer: &mut W,
         endian: binrw::Endian,
-        _args: Self::Args<'_>,
+        args: Self::Args<'_>,
     ) -> binrw::BinResult<()> {
         use std::io::SeekFrom::Start;
 
@@ -262,15 +774,49 @@ impl BinWrite for AdfFile {
                 [0u64; 3].write_options(writer, endian, ())?;
             }
 
-            // TODO: instances are 128 byte aligned, needs corrected before writing, requires &impl Iter<Item = &AdfFile> to be passed with *all referenced* types
             let mut buffer_offset = writer.stream_position()?;
             writer.seek(Start(header.instance_offset as u64))?;
             for adf_instance in instances.borrow().iter() {
-                adf_instance.write_options(writer, endian, (&strings, &mut buffer_offset))?;
+                let alignment = match args.0 {
+                    // Buffers keep the alignment they were given in memory (128 when read)
+                    AdfLayout::Aligned => None,
+                    // The compiler packs buffers using the alignment of their type
+                    AdfLayout::Faithful => self.get_alignment_by_hash(adf_instance.type_hash),
+                };
+                // Buffers stored in another byte order must be swapped using their types
+                let swapped = if adf_instance.endian != endian {
+                    Some(
+                        self.swap_instance(adf_instance)
+                            .map_err(|err| binrw::Error::Custom {
+                                pos: header.instance_offset as u64,
+                                err: Box::new(err),
+                            })?,
+                    )
+                } else {
+                    None
+                };
+                adf_instance.write_options(
+                    writer,
+                    endian,
+                    (&strings, &mut buffer_offset, alignment, swapped.as_deref()),
+                )?;
             }
             writer.seek(Start(buffer_offset))?;
         }
 
+        // Write hashes, rebuilding them from instances when none were supplied
+        let hashes = match &self.hashes {
+            Some(hashes) => hashes.clone(),
+            None => self.collect_hashes(),
+        };
+        header.hash_count = hashes.len() as u32;
+        if header.hash_count > 0 {
+            header.hash_offset = writer.align(16)? as u32;
+            for hash in &hashes {
+                hash.write_options(writer, endian, ())?;
+            }
+        }
+
         // Write strings
         header.string_count = strings.borrow().len() as u32;
         if header.string_count > 0 {
@@ -283,8 +829,6 @@ impl BinWrite for AdfFile {
             }
         }
 
-        // TODO: write hashes?
-
         // Write final header
         header.file_size = writer.stream_position()? as u32;
         writer.seek(Start(header_offset))?;
@@ -293,6 +837,28 @@ impl BinWrite for AdfFile {
     }
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AdfLayout {
+    // Sections are written in order, and instance buffers keep their in-memory alignment.
+    #[default]
+    Aligned,
+    // Sections, padding and buffers are laid out as the game's compiler emits them, so an
+    // unedited file is written back byte for byte up to its header's `file_size`. Anything a
+    // file carries past that size (like the zero padding of `game_effect_adf.adf`) isn't kept.
+    Faithful,
+}
+
+impl AdfLayout {
+    #[inline]
+    pub const fn from_faithful(faithful: bool) -> Self {
+        if faithful {
+            Self::Faithful
+        } else {
+            Self::Aligned
+        }
+    }
+}
+
 #[binrw]
 #[brw(repr = u32)]
 #[derive(Clone, Copy, Debug, Default)]
@@ -302,7 +868,7 @@ pub enum AdfVersion {
 }
 
 #[binrw]
-#[brw(magic = b" FDA")]
+#[brw(magic = 0x41444620u32)]
 #[derive(Clone, Debug, Default)]
 struct AdfHeader {
     pub version: AdfVersion,
@@ -319,14 +885,26 @@ struct AdfHeader {
     pub description: NullString,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct AdfInstance {
     pub name: AdfReference<NullString>,
     pub type_hash: u32,
-    pub buffer: Mutex<AVec<u8, RuntimeAlign>>,
+    pub endian: Endian,
+    pub buffer: AdfBuffer,
 }
 
 impl AdfInstance {
+    pub fn from_type(name: impl AsRef<str>, type_def: &AdfType) -> Self {
+        Self {
+            name: NullString::from(name.as_ref()).into(),
+            type_hash: type_def.type_hash,
+            endian: Endian::Little,
+            buffer: AdfBuffer::Owned(Arc::new(
+                avec_rt!([type_def.alignment as usize]| 0u8; type_def.size as usize),
+            )),
+        }
+    }
+
     pub fn read<T: AdfRead + AdfTypeInfo>(&self) -> Result<T, AdfInstanceReadWriteError> {
         if self.type_hash != T::HASH {
             return Err(AdfInstanceReadWriteError::Hash {
@@ -335,18 +913,15 @@ impl AdfInstance {
             });
         }
 
-        let Ok(buffer) = self.buffer.lock() else {
-            return Err(AdfInstanceReadWriteError::Lock);
-        };
-
         Ok(T::read(
-            &mut std::io::BufReader::new(std::io::Cursor::new(buffer.as_ref())),
+            &mut std::io::BufReader::new(std::io::Cursor::new(&*self.buffer)),
+            self.endian,
             &mut Default::default(),
         )?)
     }
 
     pub fn write<T: AdfWrite + AdfTypeInfo>(
-        &self,
+        &mut self,
         value: &T,
     ) -> Result<(), AdfInstanceReadWriteError> {
         if self.type_hash != T::HASH {
@@ -356,16 +931,16 @@ impl AdfInstance {
             });
         }
 
-        let Ok(mut buffer) = self.buffer.lock() else {
-            return Err(AdfInstanceReadWriteError::Lock);
-        };
-
         let mut instance_buffer = vec![];
         value.write(
             &mut std::io::BufWriter::new(std::io::Cursor::new(&mut instance_buffer)),
+            self.endian,
             &mut (T::SIZE, Default::default()),
         )?;
-        *buffer = AVec::from_iter(T::ALIGN as usize, instance_buffer.into_iter());
+        self.buffer = AdfBuffer::Owned(Arc::new(AVec::from_iter(
+            T::ALIGN as usize,
+            instance_buffer,
+        )));
 
         Ok(())
     }
@@ -375,8 +950,6 @@ impl AdfInstance {
 pub enum AdfInstanceReadWriteError {
     #[error("adf error: {0}")]
     Adf(#[from] AdfReadWriteError),
-    #[error("failed to get lock")]
-    Lock,
     #[error("invalid hash, expected: {expected}, found: {found}")]
     Hash { expected: u32, found: u32 },
 }
@@ -386,7 +959,8 @@ impl Default for AdfInstance {
         Self {
             name: Default::default(),
             type_hash: Default::default(),
-            buffer: Mutex::from(AVec::new(0)),
+            endian: Endian::Little,
+            buffer: AdfBuffer::default(),
         }
     }
 }
@@ -395,12 +969,13 @@ impl PartialEq for AdfInstance {
     fn eq(&self, other: &Self) -> bool {
         self.name == other.name
             && self.type_hash == other.type_hash
-            && (&raw const self.buffer == &raw const other.buffer)
+            && self.endian == other.endian
+            && *self.buffer == *other.buffer
     }
 }
 
 impl BinRead for AdfInstance {
-    type Args<'a> = (&'a AdfReferenceCollector<NullString>,);
+    type Args<'a> = (&'a AdfReferenceCollector<NullString>, Option<&'a AdfBytes>);
 
     #[inline]
     fn read_options<R: std::io::prelude::Read + std::io::prelude::Seek>(
@@ -417,10 +992,16 @@ impl BinRead for AdfInstance {
         let position = reader.stream_position()?;
         reader.seek(std::io::SeekFrom::Start(buffer_offset))?;
 
-        let mut buffer = avec_rt!([128]| 0u8; buffer_size);
-        for byte in buffer.iter_mut() {
-            *byte = u8::read_options(reader, endian, ())?;
-        }
+        // Borrow the buffer when reading from shared bytes, otherwise copy it out
+        let range = buffer_offset as usize..buffer_offset as usize + buffer_size;
+        let buffer = match args.1 {
+            Some(bytes) if range.end <= bytes.len() => AdfBuffer::Shared(bytes.clone(), range),
+            _ => {
+                let mut buffer = avec_rt!([ADF_BUFFER_ALIGNMENT]| 0u8; buffer_size);
+                reader.read_exact(buffer.as_mut_slice())?;
+                AdfBuffer::Owned(Arc::new(buffer))
+            }
+        };
 
         reader.seek(std::io::SeekFrom::Start(position))?;
 
@@ -428,7 +1009,8 @@ impl BinRead for AdfInstance {
             Ok(Self {
                 name,
                 type_hash,
-                buffer: buffer.into(),
+                endian,
+                buffer,
             })
         } else {
             Err(binrw::Error::Custom {
@@ -440,7 +1022,12 @@ impl BinRead for AdfInstance {
 }
 
 impl BinWrite for AdfInstance {
-    type Args<'a> = (&'a AdfReferenceCollector<NullString>, &'a mut u64);
+    type Args<'a> = (
+        &'a AdfReferenceCollector<NullString>,
+        &'a mut u64,
+        Option<u64>,
+        Option<&'a [u8]>,
+    );
 
     #[inline]
     fn write_options<W: std::io::Write + std::io::Seek>(
@@ -454,36 +1041,114 @@ impl BinWrite for AdfInstance {
         // Remember our instance offset
         let instance_offset = writer.stream_position()?;
 
-        if let Ok(buffer) = self.buffer.lock() {
-            // Seek to aligned buffer offset + write buffer
-            writer.seek(Start(*args.1))?;
-            let buffer_offset = writer.align(buffer.alignment() as u64)? as u32;
-            writer.write(&buffer)?;
-            *args.1 = writer.stream_position()?;
-
-            // Return to instance offset, and write instance data
-            writer.seek(Start(instance_offset))?;
-            hash_little32(self.name.as_bytes()).write_options(writer, endian, ())?;
-            self.type_hash.write_options(writer, endian, ())?;
-            buffer_offset.write_options(writer, endian, ())?;
-            (buffer.len() as u32).write_options(writer, endian, ())?;
-            self.name.write_options(writer, endian, (args.0,))?;
-            Ok(())
-        } else {
-            Err(binrw::Error::Custom {
-                pos: writer.stream_position()?,
-                err: Box::new(AdfInstanceError::MutexFailure),
-            })
+        // Seek to aligned buffer offset + write buffer
+        writer.seek(Start(*args.1))?;
+        let alignment = args.2.unwrap_or(self.buffer.alignment() as u64);
+        let buffer_offset = writer.align(alignment)? as u32;
+        writer.write_all(args.3.unwrap_or(&self.buffer))?;
+        *args.1 = writer.stream_position()?;
+
+        // Return to instance offset, and write instance data
+        writer.seek(Start(instance_offset))?;
+        hash_little32(self.name.as_bytes()).write_options(writer, endian, ())?;
+        self.type_hash.write_options(writer, endian, ())?;
+        buffer_offset.write_options(writer, endian, ())?;
+        (self.buffer.len() as u32).write_options(writer, endian, ())?;
+        self.name.write_options(writer, endian, (args.0,))?;
+        Ok(())
+    }
+}
+
+// Buffers read from disk are kept 128 byte aligned, matching the game's allocator
+const ADF_BUFFER_ALIGNMENT: usize = 128;
+
+#[derive(Clone)]
+pub struct AdfBytes(Arc<dyn AsRef<[u8]> + Send + Sync>);
+
+impl<T: AsRef<[u8]> + Send + Sync + 'static> From<T> for AdfBytes {
+    #[inline]
+    fn from(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+}
+
+impl Deref for AdfBytes {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        (*self.0).as_ref()
+    }
+}
+
+#[derive(Clone)]
+pub enum AdfBuffer {
+    // A buffer that has been created or mutated, shared between clones until written to.
+    Owned(Arc<AVec<u8, RuntimeAlign>>),
+    // A buffer that borrows a range of the bytes it was read from.
+    Shared(AdfBytes, Range<usize>),
+}
+
+impl AdfBuffer {
+    #[inline]
+    pub fn alignment(&self) -> usize {
+        match self {
+            Self::Owned(buffer) => buffer.alignment(),
+            Self::Shared(..) => ADF_BUFFER_ALIGNMENT,
+        }
+    }
+
+    #[inline]
+    pub fn is_shared(&self) -> bool {
+        matches!(self, Self::Shared(..))
+    }
+
+    pub fn to_mut(&mut self) -> &mut AVec<u8, RuntimeAlign> {
+        if let Self::Shared(bytes, range) = self {
+            let mut buffer = avec_rt!([ADF_BUFFER_ALIGNMENT]| 0u8; range.len());
+            buffer.copy_from_slice(&bytes[range.clone()]);
+            *self = Self::Owned(Arc::new(buffer));
+        }
+        match self {
+            Self::Owned(buffer) => Arc::make_mut(buffer),
+            Self::Shared(..) => unreachable!(),
+        }
+    }
+}
+
+impl Default for AdfBuffer {
+    #[inline]
+    fn default() -> Self {
+        Self::Owned(Arc::new(AVec::new(0)))
+    }
+}
+
+impl Deref for AdfBuffer {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Owned(buffer) => buffer.as_slice(),
+            Self::Shared(bytes, range) => &bytes[range.clone()],
         }
     }
 }
 
+impl std::fmt::Debug for AdfBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AdfInstanceError {
     #[error("invalid hash")]
     InvalidNameHash(u32),
-    #[error("failed to get mutex")]
-    MutexFailure,
+    #[error("unknown type {0}")]
+    UnknownType(u32),
+    #[error("offset {0} is outside of buffer")]
+    OutOfBounds(usize),
 }
 
 #[binrw]
@@ -564,8 +1229,8 @@ pub struct AdfMember {
 
 #[bitfield]
 #[binrw]
-#[br(map = Self::from_bytes)]
-#[bw(map = |x: &Self| x.bytes)]
+#[br(map = |x: u32| Self::from_bytes(x.to_le_bytes()))]
+#[bw(map = |x: &Self| u32::from_le_bytes(x.bytes))]
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub struct AdfMemberOffsets {
     pub byte: B24,
@@ -584,6 +1249,24 @@ pub enum AdfMemberValue {
     InlineValue(u64),
     #[brw(magic = 2u32)]
     InstanceValue(#[brw(args(instances))] AdfReference<Arc<AdfInstance>>),
+    // Some ADFs were saved without their defaults, so keep the name hash to write it back as is
+    #[brw(magic = 2u32)]
+    UnresolvedInstanceValue(
+        #[br(map = |x: u64| HashString::new(x as u32))]
+        #[bw(map = |x: &HashString| x.hash() as u64)]
+        HashString,
+    ),
+}
+
+impl AdfMemberValue {
+    // The name hash of the referenced instance, whether or not it could be resolved
+    pub fn instance_hash(&self) -> Option<HashString> {
+        match self {
+            Self::InstanceValue(instance) => Some(HashString::from_str(instance.name.as_ref())),
+            Self::UnresolvedInstanceValue(hash) => Some(*hash),
+            _ => None,
+        }
+    }
 }
 
 impl Default for AdfMemberValue {
@@ -702,9 +1385,10 @@ impl AdfReferenceEq for NullString {
 }
 
 impl AdfReferenceEq for Arc<AdfInstance> {
+    // Instances are referenced by name, and may have been copied on write since
     #[inline]
     fn eq(&self, other: &Self) -> bool {
-        Arc::ptr_eq(self, other)
+        Arc::ptr_eq(self, other) || (self.type_hash == other.type_hash && self.name == other.name)
     }
 }
 
@@ -739,10 +1423,6 @@ impl AdfReferenceIdentity<Arc<AdfInstance>> for Arc<AdfInstance> {
         pool.iter()
             .find(|x| hash_little32(x.name.as_bytes()) == identity)
             .cloned()
-            .or_else(|| {
-                // TODO: Some ADFs were saved without defaults... not sure if we care?
-                Some(AdfInstance::default().into())
-            })
     }
 
     #[inline]
@@ -760,3 +1440,291 @@ pub trait AdfReferenceInner {
 impl<T> AdfReferenceInner for AdfReferenceCollector<T> {
     type Inner = T;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{AdfReflectionContext, BUILT_IN_TYPE_LIBRARY, TYPE_LIBRARIES};
+
+    fn string_hash() -> AdfType {
+        AdfType {
+            primitive: AdfPrimitive::StringHash,
+            size: 4,
+            alignment: 4,
+            type_hash: HashString::HASH,
+            name: NullString::from(HashString::NAME).into(),
+            ..Default::default()
+        }
+    }
+
+    fn string_hash_file(hashes: Option<Vec<HashString>>) -> AdfFile {
+        let string_hash = string_hash();
+        let type_def = AdfType::structure("Test")
+            .member("Hash", &string_hash)
+            .build();
+        let mut file = AdfFile {
+            types: vec![string_hash, type_def.clone()],
+            hashes,
+            ..Default::default()
+        };
+        let instance = file.new_instance_from_type("Test", &type_def).unwrap();
+        instance.buffer.to_mut()[0..4].copy_from_slice(&hash_little32(b"Test").to_le_bytes());
+        file
+    }
+
+    fn round_trip(file: &AdfFile) -> AdfFile {
+        round_trip_endian(file, Endian::Little)
+    }
+
+    fn round_trip_endian(file: &AdfFile, endian: Endian) -> AdfFile {
+        let mut writer = std::io::Cursor::new(vec![]);
+        file.write_options(&mut writer, endian, (AdfLayout::Aligned,))
+            .unwrap();
+        AdfFile::from_bytes(writer.into_inner()).unwrap()
+    }
+
+    fn uint32() -> AdfType {
+        built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap()
+            .clone()
+    }
+
+    // A file with one instance of the type, with the given buffer
+    fn instance_file(types: Vec<AdfType>, buffer: Vec<u8>) -> AdfFile {
+        let type_def = types.last().unwrap();
+        let mut instance = AdfInstance::from_type(type_def.name.as_str(), type_def);
+        *instance.buffer.to_mut() = AVec::from_iter(ADF_BUFFER_ALIGNMENT, buffer);
+        AdfFile {
+            instances: vec![Arc::new(instance)],
+            types,
+            hashes: Some(vec![]),
+            ..Default::default()
+        }
+    }
+
+    // `Node`s are linked through their `Next` member, `length` of them one after another
+    fn chain(length: usize) -> AdfFile {
+        let mut pointer = AdfType::pointer(&AdfType::structure("Node").build());
+        let node = AdfType::structure("Node")
+            .member("Hash", &string_hash())
+            .member("Next", &pointer)
+            .build();
+        pointer.element_type_hash = node.type_hash;
+
+        let mut buffer = vec![0u8; length * 16];
+        for (index, bytes) in buffer.chunks_mut(16).enumerate() {
+            bytes[0..4].copy_from_slice(&(index as u32).to_le_bytes());
+            if index + 1 < length {
+                bytes[8..16].copy_from_slice(&(((index + 1) * 16) as u64).to_le_bytes());
+            }
+        }
+        instance_file(vec![string_hash(), pointer, node], buffer)
+    }
+
+    #[test]
+    fn hashes_are_rebuilt_when_missing() {
+        let file = round_trip(&string_hash_file(None));
+        assert_eq!(
+            file.hashes,
+            Some(vec![HashString::new(hash_little32(b"Test"))])
+        );
+    }
+
+    #[test]
+    fn empty_hashes_are_kept() {
+        let file = round_trip(&string_hash_file(Some(vec![])));
+        assert_eq!(file.hashes, Some(vec![]));
+    }
+
+    #[test]
+    fn type_libraries_round_trip() {
+        for library in TYPE_LIBRARIES.iter().chain([BUILT_IN_TYPE_LIBRARY]) {
+            let file = library.load().unwrap();
+            let mut writer = std::io::Cursor::new(vec![]);
+            file.write_options(&mut writer, Endian::Little, (AdfLayout::Faithful,))
+                .unwrap();
+            // Some libraries carry padding past their size, which isn't part of the file
+            let file_size = read_pod::<u32>(&library.library[40..44], Endian::Little) as usize;
+            assert!(
+                writer.get_ref().as_slice() == &library.library[..file_size],
+                "{} doesn't round trip",
+                library.extension
+            );
+        }
+    }
+
+    #[test]
+    fn big_endian_libraries_round_trip() {
+        for library in TYPE_LIBRARIES.iter().chain([BUILT_IN_TYPE_LIBRARY]) {
+            let file = library.load().unwrap();
+            let swapped = round_trip_endian(&file, Endian::Big);
+            let mut context = AdfReflectionContext::from_extension(library.extension).unwrap();
+            context.load_types_from_file(&file);
+
+            assert_eq!(swapped.instances.len(), file.instances.len());
+            for (instance, swapped) in file.instances.iter().zip(&swapped.instances) {
+                assert_eq!(swapped.endian, Endian::Big);
+                assert_eq!(
+                    context.read_instance(swapped).unwrap(),
+                    context.read_instance(instance).unwrap(),
+                    "{} doesn't round trip big endian",
+                    library.extension
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_size_elements_are_swapped_once() {
+        let empty = AdfType::structure("Empty").build();
+        let array = AdfType::array(&empty);
+        let holder = AdfType::structure("Holder").member("Items", &array).build();
+        let mut buffer = vec![0u8; 16];
+        buffer[0..8].copy_from_slice(&16u64.to_le_bytes());
+        buffer[8..16].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let file = instance_file(vec![empty, array, holder], buffer);
+
+        let swapped = round_trip_endian(&file, Endian::Big);
+        let buffer = &swapped.instances[0].buffer;
+        assert_eq!(read_pod::<u64>(&buffer[8..16], Endian::Big), 1 << 40);
+    }
+
+    #[test]
+    fn overflowing_offsets_are_out_of_bounds() {
+        let uint32 = uint32();
+        let pointer = AdfType::pointer(&uint32);
+        let array = AdfType::array(&uint32);
+        let holder = AdfType::structure("Holder")
+            .member("Pointer", &pointer)
+            .member("Array", &array)
+            .build();
+
+        let mut buffer = vec![0u8; 24];
+        buffer[0..8].copy_from_slice(&(u64::MAX - 3).to_le_bytes());
+        let file = instance_file(
+            vec![
+                uint32.clone(),
+                pointer.clone(),
+                array.clone(),
+                holder.clone(),
+            ],
+            buffer,
+        );
+        assert!(matches!(
+            file.swap_instance(&file.instances[0]),
+            Err(AdfInstanceError::OutOfBounds(_))
+        ));
+
+        let mut buffer = vec![0u8; 24];
+        buffer[8..16].copy_from_slice(&(u64::MAX - 3).to_le_bytes());
+        buffer[16..24].copy_from_slice(&2u64.to_le_bytes());
+        let file = instance_file(vec![uint32, pointer, array, holder], buffer);
+        assert!(matches!(
+            file.swap_instance(&file.instances[0]),
+            Err(AdfInstanceError::OutOfBounds(_))
+        ));
+    }
+
+    #[test]
+    fn long_chains_are_swapped() {
+        let file = chain(100_000);
+        let swapped = file.swap_instance(&file.instances[0]).unwrap();
+        let last = &swapped[(100_000 - 1) * 16..];
+        assert_eq!(read_pod::<u32>(&last[0..4], Endian::Big), 100_000 - 1);
+        assert_eq!(
+            read_pod::<u64>(&swapped[8..16], Endian::Big),
+            16,
+            "pointers are swapped"
+        );
+    }
+
+    #[test]
+    fn long_chains_are_walked() {
+        let mut file = chain(100_000);
+        file.hashes = None;
+        let hashes = round_trip(&file).hashes.unwrap();
+        assert_eq!(hashes.len(), 100_000);
+        assert_eq!(
+            hashes[..3],
+            [HashString::new(0), HashString::new(1), HashString::new(2)]
+        );
+
+        let extracted = file.extract(["Node"]).unwrap();
+        assert_eq!(extracted.types.len(), 3);
+    }
+
+    fn write_bytes(file: &AdfFile) -> Vec<u8> {
+        let mut writer = std::io::Cursor::new(vec![]);
+        file.write_options(&mut writer, Endian::Little, (AdfLayout::Aligned,))
+            .unwrap();
+        writer.into_inner()
+    }
+
+    // A default naming an instance which isn't in the file is kept as the hash of its name
+    #[test]
+    fn unresolved_defaults_keep_their_hash() {
+        let hash = HashString::from_str("Missing");
+        let type_def = AdfType::structure("Holder")
+            .member("Value", &uint32())
+            .default_value(AdfMemberValue::UnresolvedInstanceValue(hash))
+            .build();
+        let file = instance_file(vec![uint32(), type_def], vec![0; 4]);
+
+        let bytes = write_bytes(&file);
+        let read = AdfFile::from_bytes(bytes.clone()).unwrap();
+        let member = &read.types.last().unwrap().members[0];
+        assert!(matches!(
+            member.value,
+            AdfMemberValue::UnresolvedInstanceValue(x) if x == hash
+        ));
+        assert_eq!(member.value.instance_hash(), Some(hash));
+
+        // Written back as the same kind of value, holding the same hash
+        let mut record = 2u32.to_le_bytes().to_vec();
+        record.extend(u64::from(hash.hash()).to_le_bytes());
+        let rewritten = write_bytes(&read);
+        assert!(rewritten.windows(record.len()).any(|x| x == record));
+        assert_eq!(rewritten, bytes);
+
+        // Reflection falls back to the type's own default
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&read);
+        assert_eq!(
+            context.member_default_value(member),
+            context.default_value(uint32().type_hash)
+        );
+        assert!(context.read_instance(&read.instances[0]).is_ok());
+    }
+
+    #[test]
+    fn shared_and_owned_buffers_compare_by_bytes() {
+        let file = instance_file(vec![uint32()], 7u32.to_le_bytes().to_vec());
+        let read = AdfFile::from_bytes(write_bytes(&file)).unwrap();
+        let shared = &read.instances[0];
+        assert!(shared.buffer.is_shared());
+        assert!(!file.instances[0].buffer.is_shared());
+        assert_eq!(**shared, *file.instances[0]);
+
+        // Taking the buffer to modify it copies it, which is still equal until it's changed
+        let mut owned = shared.as_ref().clone();
+        owned.buffer.to_mut();
+        assert!(!owned.buffer.is_shared());
+        assert_eq!(owned, **shared);
+        owned.buffer.to_mut()[0] = 8;
+        assert_ne!(owned, **shared);
+        assert_eq!(shared.buffer[..], 7u32.to_le_bytes());
+    }
+
+    #[test]
+    fn owned_buffers_are_copied_when_modified() {
+        let file = instance_file(vec![uint32()], 7u32.to_le_bytes().to_vec());
+        let original = file.instances[0].as_ref();
+        let mut copy = original.clone();
+        copy.buffer.to_mut()[0] = 8;
+        assert_eq!(original.buffer[..], 7u32.to_le_bytes());
+        assert_eq!(copy.buffer[..], 8u32.to_le_bytes());
+        assert_ne!(copy, *original);
+    }
+}
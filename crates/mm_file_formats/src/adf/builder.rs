@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+
+use mm_hashing::hash_little32;
+
+use crate::common::NullString;
+
+use super::{
+    AdfEnum, AdfMember, AdfMemberOffsets, AdfMemberValue, AdfPrimitive, AdfType, AdfTypeFlags,
+    AdfTypeLib,
+};
+
+const POD_FLAGS: AdfTypeFlags = AdfTypeFlags::POD_READ.union(AdfTypeFlags::POD_WRITE);
+
+pub struct AdfStructureBuilder {
+    type_def: AdfType,
+    // Offset of the storage, next free bit, and size in bits of the last bitfield member
+    bitfield: Option<(u32, u32, u32)>,
+    end: u32,
+}
+
+impl AdfStructureBuilder {
+    pub fn new(name: impl AsRef<str>) -> Self {
+        Self {
+            type_def: AdfType {
+                primitive: AdfPrimitive::Structure,
+                name: NullString::from(name.as_ref()).into(),
+                flags: AdfTypeFlags::FINALIZE | POD_FLAGS,
+                ..Default::default()
+            },
+            bitfield: None,
+            end: 0,
+        }
+    }
+
+    pub fn member(self, name: impl AsRef<str>, member_type: &AdfType) -> Self {
+        self.aligned_member(name, member_type, member_type.alignment)
+    }
+
+    // Members are placed at the next offset matching `alignment`, except for bitfields, which
+    // share storage with the previous bitfield member while their bits fit
+    pub fn aligned_member(
+        mut self,
+        name: impl AsRef<str>,
+        member_type: &AdfType,
+        alignment: u32,
+    ) -> Self {
+        let mut offsets = AdfMemberOffsets::new();
+        let storage_bits = member_type.size * 8;
+        match self.bitfield {
+            Some((offset, bit, bits))
+                if member_type.primitive == AdfPrimitive::Bitfield
+                    && bits == storage_bits
+                    && bit + member_type.element_length <= bits =>
+            {
+                offsets.set_byte(offset);
+                offsets.set_bit(bit as u8);
+                self.bitfield = Some((offset, bit + member_type.element_length, bits));
+            }
+            _ => {
+                let offset = align(self.end, alignment);
+                self.pad(offset);
+                offsets.set_byte(offset);
+                self.end = offset + member_type.size;
+                self.bitfield = (member_type.primitive == AdfPrimitive::Bitfield).then_some((
+                    offset,
+                    member_type.element_length,
+                    storage_bits,
+                ));
+            }
+        }
+
+        self.type_def.alignment = self.type_def.alignment.max(alignment);
+        self.type_def.flags &= member_type.flags | AdfTypeFlags::FINALIZE;
+        self.type_def.members.push(AdfMember {
+            name: NullString::from(name.as_ref()).into(),
+            type_hash: member_type.type_hash,
+            alignment,
+            offsets,
+            value: AdfMemberValue::UninitializedValue(()),
+        });
+        self
+    }
+
+    // Structures with padding can't be written out as plain data
+    fn pad(&mut self, offset: u32) {
+        if offset != self.end {
+            self.type_def.flags.remove(AdfTypeFlags::POD_WRITE);
+        }
+    }
+
+    // Sets the default of the last member added
+    pub fn default_value(mut self, value: AdfMemberValue) -> Self {
+        if let Some(member) = self.type_def.members.last_mut() {
+            member.value = value;
+        }
+        self
+    }
+
+    pub fn alignment(mut self, alignment: u32) -> Self {
+        self.type_def.alignment = self.type_def.alignment.max(alignment);
+        self
+    }
+
+    pub fn build(mut self) -> AdfType {
+        self.type_def.alignment = self.type_def.alignment.max(1);
+        self.type_def.size = align(self.end, self.type_def.alignment);
+        self.pad(self.type_def.size);
+        self.type_def.type_hash = self.type_def.compute_type_hash();
+        self.type_def
+    }
+}
+
+impl AdfType {
+    pub fn structure(name: impl AsRef<str>) -> AdfStructureBuilder {
+        AdfStructureBuilder::new(name)
+    }
+
+    pub fn enumeration<'a>(
+        name: impl AsRef<str>,
+        values: impl IntoIterator<Item = (&'a str, i32)>,
+    ) -> AdfType {
+        Self::composite(AdfType {
+            primitive: AdfPrimitive::Enumeration,
+            size: 4,
+            alignment: 4,
+            name: NullString::from(name.as_ref()).into(),
+            flags: AdfTypeFlags::FINALIZE | POD_FLAGS,
+            enumerations: values
+                .into_iter()
+                .map(|(name, value)| AdfEnum {
+                    name: NullString::from(name).into(),
+                    value,
+                })
+                .collect::<Vec<_>>()
+                .into(),
+            ..Default::default()
+        })
+    }
+
+    pub fn bitfield(storage: &AdfType, bits: u32) -> AdfType {
+        Self::composite(AdfType {
+            primitive: AdfPrimitive::Bitfield,
+            size: storage.size,
+            alignment: storage.alignment,
+            name: NullString::from(format!("{}: {bits}", storage.name.as_str()).as_str()).into(),
+            flags: AdfTypeFlags::FINALIZE | AdfTypeFlags::POD_READ,
+            scalar_type: storage.scalar_type,
+            element_length: bits,
+            ..Default::default()
+        })
+    }
+
+    pub fn pointer(element: &AdfType) -> AdfType {
+        Self::composite(AdfType {
+            primitive: AdfPrimitive::Pointer,
+            size: 8,
+            alignment: 8,
+            name: NullString::from(format!("{}*", element.name.as_str()).as_str()).into(),
+            flags: AdfTypeFlags::FINALIZE,
+            element_type_hash: element.type_hash,
+            ..Default::default()
+        })
+    }
+
+    pub fn array(element: &AdfType) -> AdfType {
+        Self::composite(AdfType {
+            primitive: AdfPrimitive::Array,
+            size: 16,
+            alignment: 8,
+            name: NullString::from(format!("A[{}]", element.name.as_str()).as_str()).into(),
+            flags: AdfTypeFlags::FINALIZE,
+            element_type_hash: element.type_hash,
+            ..Default::default()
+        })
+    }
+
+    pub fn inline_array(element: &AdfType, length: u32) -> AdfType {
+        Self::composite(AdfType {
+            primitive: AdfPrimitive::InlineArray,
+            size: element.size * length,
+            alignment: element.alignment,
+            name: NullString::from(format!("IA[{}]", element.name.as_str()).as_str()).into(),
+            flags: AdfTypeFlags::FINALIZE | (element.flags & POD_FLAGS),
+            element_type_hash: element.type_hash,
+            element_length: length,
+            ..Default::default()
+        })
+    }
+
+    fn composite(mut type_def: AdfType) -> AdfType {
+        type_def.type_hash = type_def.compute_type_hash();
+        type_def
+    }
+
+    // CommonHash is computed from the name and layout, which is then combined with whatever
+    // else distinguishes the type; the same scheme as `type_hash!` in derive.rs
+    pub fn compute_type_hash(&self) -> u32 {
+        let common = hash_little32(
+            format!(
+                "{}{}{}{}",
+                self.name.as_str(),
+                self.primitive.clone() as u32,
+                self.size,
+                self.alignment
+            )
+            .as_bytes(),
+        );
+        let suffix: String = match self.primitive {
+            AdfPrimitive::Structure => self
+                .members
+                .iter()
+                .map(|member| format!("{}{}", member.type_hash, member.offsets.byte()))
+                .collect(),
+            AdfPrimitive::Enumeration => self
+                .enumerations
+                .iter()
+                .map(|value| format!("{}{}", value.name.as_str(), value.value))
+                .collect(),
+            AdfPrimitive::Pointer | AdfPrimitive::Array | AdfPrimitive::Recursive => {
+                self.element_type_hash.to_string()
+            }
+            AdfPrimitive::InlineArray => {
+                format!("{}{}", self.element_type_hash, self.element_length)
+            }
+            _ => return common,
+        };
+        hash_little32(format!("{common}{suffix}").as_bytes())
+    }
+}
+
+impl AdfTypeLib {
+    // Returns the types whose hash doesn't match the one computed from their definition.
+    // Structures which contain themselves can't be hashed from their definition, so are skipped
+    pub fn verify_type_hashes(&self) -> binrw::BinResult<Vec<AdfType>> {
+        let library = self.load()?;
+        let types: HashMap<u32, &AdfType> = library
+            .types
+            .iter()
+            .map(|type_def| (type_def.type_hash, type_def))
+            .collect();
+        Ok(library
+            .types
+            .iter()
+            .filter(|type_def| type_def.compute_type_hash() != type_def.type_hash)
+            .filter(|type_def| !contains_type(&types, type_def, type_def.type_hash, 0))
+            .cloned()
+            .collect())
+    }
+}
+
+fn contains_type(
+    types: &HashMap<u32, &AdfType>,
+    type_def: &AdfType,
+    type_hash: u32,
+    depth: usize,
+) -> bool {
+    if depth > types.len() {
+        return false;
+    }
+    let children = type_def
+        .members
+        .iter()
+        .map(|member| member.type_hash)
+        .chain((type_def.element_type_hash != 0).then_some(type_def.element_type_hash));
+    for child in children {
+        if child == type_hash {
+            return true;
+        }
+        if let Some(child) = types.get(&child) {
+            if contains_type(types, child, type_hash, depth + 1) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[inline]
+fn align(value: u32, alignment: u32) -> u32 {
+    value.next_multiple_of(alignment.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{built_in_types, AdfFile, BUILT_IN_TYPE_LIBRARY, TYPE_LIBRARIES};
+
+    fn libraries() -> impl Iterator<Item = &'static AdfTypeLib> {
+        TYPE_LIBRARIES.iter().chain([BUILT_IN_TYPE_LIBRARY])
+    }
+
+    #[test]
+    fn type_hashes() {
+        for library in libraries() {
+            let mismatched = library.verify_type_hashes().unwrap();
+            assert!(
+                mismatched.is_empty(),
+                "{} has mismatched types: {:?}",
+                library.extension,
+                mismatched
+                    .iter()
+                    .map(|x| x.name.as_ref())
+                    .collect::<Vec<&str>>()
+            );
+        }
+    }
+
+    #[test]
+    fn structure_layouts() {
+        // Members may use types from other libraries
+        let libraries: Vec<AdfFile> = libraries().map(|x| x.load().unwrap()).collect();
+        let types: HashMap<u32, &AdfType> = libraries
+            .iter()
+            .flat_map(|library| library.types.iter())
+            .chain(built_in_types())
+            .map(|type_def| (type_def.type_hash, type_def))
+            .collect();
+
+        for library in &libraries {
+            for expected in library
+                .types
+                .iter()
+                .filter(|x| x.primitive == AdfPrimitive::Structure)
+            {
+                let built = expected
+                    .members
+                    .iter()
+                    .fold(
+                        AdfType::structure(expected.name.as_ref()),
+                        |builder, member| {
+                            builder
+                                .aligned_member(
+                                    member.name.as_ref(),
+                                    types[&member.type_hash],
+                                    member.alignment,
+                                )
+                                .default_value(member.value.clone())
+                        },
+                    )
+                    // Some structures are aligned beyond their members
+                    .alignment(expected.alignment)
+                    .build();
+
+                let name = expected.name.as_ref();
+                // Structures which contain themselves can't be hashed from their definition
+                if !contains_type(&types, expected, expected.type_hash, 0) {
+                    assert_eq!(built.type_hash, expected.type_hash, "hash of {name}");
+                }
+                assert_eq!(built.size, expected.size, "size of {name}");
+                assert_eq!(built.flags, expected.flags, "flags of {name}");
+                for (built, member) in built.members.iter().zip(expected.members.iter()) {
+                    assert_eq!(
+                        built.offsets,
+                        member.offsets,
+                        "offset of {name}.{}",
+                        member.name.as_ref()
+                    );
+                }
+            }
+        }
+    }
+}
@@ -5,12 +5,69 @@ use std::{
     sync::Arc,
 };
 
+use binrw::Endian;
 use const_format::concatcp;
 use thiserror::Error;
 
 use mm_hashing::{hash_little32, HashString};
 
-use crate::common::{ReaderExt, WriterExt};
+use crate::common::{align, read_pod, write_pod, ReaderExt, WriterExt};
+
+/// Derives the layout of a structure from its fields, checking it against a known hash:
+///
+/// ```
+/// use mm_file_formats::adf::{AdfRead, AdfTypeInfo, AdfWrite};
+///
+/// #[derive(AdfTypeInfo, AdfRead, AdfWrite)]
+/// #[adf(name = "XLSAttribute", hash = 2397202994)]
+/// struct XlsAttribute {
+///     fg_color_index: u8,
+///     bg_color_index: u8,
+/// }
+/// ```
+///
+/// A hash that doesn't match the fields fails to compile:
+///
+/// ```compile_fail,E0080
+/// use mm_file_formats::adf::AdfTypeInfo;
+///
+/// #[derive(AdfTypeInfo)]
+/// #[adf(name = "XLSAttribute", hash = 2397202995)]
+/// struct XlsAttribute {
+///     fg_color_index: u8,
+///     bg_color_index: u8,
+/// }
+/// ```
+///
+/// As do generic structures, since their hash can't depend on the parameters:
+///
+/// ```compile_fail
+/// use mm_file_formats::adf::AdfTypeInfo;
+///
+/// #[derive(AdfTypeInfo)]
+/// struct Wrapper<T> {
+///     value: T,
+/// }
+/// ```
+///
+/// And tuple structures, since members need names:
+///
+/// ```compile_fail
+/// use mm_file_formats::adf::AdfTypeInfo;
+///
+/// #[derive(AdfTypeInfo)]
+/// struct XlsAttribute(u8, u8);
+/// ```
+pub use mm_file_formats_derive::AdfTypeInfo;
+pub use mm_file_formats_derive::{AdfRead, AdfWrite};
+
+// Used by the derive macros, so crates using them don't need to depend on these directly
+#[doc(hidden)]
+pub mod __private {
+    pub use binrw::Endian;
+    pub use const_format::concatcp;
+    pub use mm_hashing::hash_little32;
+}
 
 pub trait AdfTypeInfo {
     const NAME: &str;
@@ -148,12 +205,48 @@ const_assert!(<f32 as AdfTypeInfo>::HASH == 0x7515A207);
 const_assert!(<[f32; 3] as AdfTypeInfo>::HASH == 0xE8541F6E);
 const_assert!(<Arc<Vec<f32>> as AdfTypeInfo>::HASH == 0x168B4EB8);
 
+// Layout of a structure from the size and alignment of its members, matching
+// `AdfStructureBuilder`; bitfields aren't packed, since typed structures don't have them
+pub const fn structure_offset(members: &[(u64, u64)], index: usize) -> u64 {
+    let mut end = 0;
+    let mut i = 0;
+    while i < index {
+        end = align(end, members[i].1) + members[i].0;
+        i += 1;
+    }
+    align(end, members[index].1)
+}
+
+pub const fn structure_alignment(members: &[(u64, u64)]) -> u64 {
+    let mut alignment = 1;
+    let mut i = 0;
+    while i < members.len() {
+        if members[i].1 > alignment {
+            alignment = members[i].1;
+        }
+        i += 1;
+    }
+    alignment
+}
+
+pub const fn structure_size(members: &[(u64, u64)]) -> u64 {
+    if members.is_empty() {
+        return 0;
+    }
+    let last = members.len() - 1;
+    align(
+        structure_offset(members, last) + members[last].0,
+        structure_alignment(members),
+    )
+}
+
 pub type AdfReaderReferences = HashMap<u64, Box<dyn Any>>;
 pub type AdfWriterReferences = (u64, HashMap<usize, (u64, TypeId)>);
 
 pub trait AdfRead: Sized {
     fn read<R: Read + Seek>(
         reader: &mut R,
+        endian: Endian,
         references: &mut AdfReaderReferences,
     ) -> Result<Self, AdfReadWriteError>;
 }
@@ -162,6 +255,7 @@ pub trait AdfWrite: Sized {
     fn write<W: Write + Seek>(
         &self,
         writer: &mut W,
+        endian: Endian,
         references: &mut AdfWriterReferences,
     ) -> Result<(), AdfReadWriteError>;
 }
@@ -170,12 +264,13 @@ impl<T: AdfRead + AdfTypeInfo + Default + Copy + 'static, const S: usize> AdfRea
     #[inline]
     fn read<R: Read + Seek>(
         reader: &mut R,
+        endian: Endian,
         references: &mut AdfReaderReferences,
     ) -> Result<Self, AdfReadWriteError> {
         let mut result = [Default::default(); S];
         reader.align(T::ALIGN)?;
         for i in 0..S {
-            result[i] = T::read(reader, references)?;
+            result[i] = T::read(reader, endian, references)?;
         }
         Ok(result)
     }
@@ -186,11 +281,12 @@ impl<T: AdfWrite + AdfTypeInfo + 'static, const S: usize> AdfWrite for [T; S] {
     fn write<W: Write + Seek>(
         &self,
         writer: &mut W,
+        endian: Endian,
         references: &mut AdfWriterReferences,
     ) -> Result<(), AdfReadWriteError> {
         writer.align(T::ALIGN)?;
         for value in self.iter() {
-            value.write(writer, references)?;
+            value.write(writer, endian, references)?;
         }
         Ok(())
     }
@@ -200,9 +296,10 @@ impl<T: AdfRead + AdfTypeInfo + 'static> AdfRead for Option<Arc<T>> {
     #[inline]
     fn read<R: Read + Seek>(
         reader: &mut R,
+        endian: Endian,
         references: &mut AdfReaderReferences,
     ) -> Result<Self, AdfReadWriteError> {
-        let offset = u64::read(reader, references)?;
+        let offset = u64::read(reader, endian, references)?;
         let position = reader.stream_position()?;
         match offset {
             0 => Ok(None),
@@ -222,7 +319,7 @@ impl<T: AdfRead + AdfTypeInfo + 'static> AdfRead for Option<Arc<T>> {
                     reader.seek_absolute(offset)?;
 
                     // Read back one instance of `T`, and store a reference
-                    let result = Arc::new(T::read(reader, references)?);
+                    let result = Arc::new(T::read(reader, endian, references)?);
                     references.insert(position, Box::from(result.clone()));
 
                     // Return to position
@@ -239,6 +336,7 @@ impl<T: AdfWrite + AdfTypeInfo + 'static> AdfWrite for Option<Arc<T>> {
     fn write<W: Write + Seek>(
         &self,
         writer: &mut W,
+        endian: Endian,
         references: &mut AdfWriterReferences,
     ) -> Result<(), AdfReadWriteError> {
         match self {
@@ -248,7 +346,7 @@ impl<T: AdfWrite + AdfTypeInfo + 'static> AdfWrite for Option<Arc<T>> {
                 if let Some(reference) = references.1.get(&key).cloned() {
                     if reference.1 == type_id {
                         // If the reference type is correct, write it's offset
-                        reference.0.write(writer, references)
+                        reference.0.write(writer, endian, references)
                     } else {
                         // Otherwise throw a reference error
                         Err(AdfReadWriteError::ReferenceError {
@@ -272,16 +370,16 @@ impl<T: AdfWrite + AdfTypeInfo + 'static> AdfWrite for Option<Arc<T>> {
 
                     // Restore offset, and write value
                     writer.seek_absolute(offset)?;
-                    value.write(writer, references)?;
+                    value.write(writer, endian, references)?;
 
                     // Restore position, and write offset
                     writer.seek_absolute(position)?;
-                    offset.write(writer, references)?;
+                    offset.write(writer, endian, references)?;
                     Ok(())
                 }
             }
             None => {
-                0u64.write(writer, references)?;
+                0u64.write(writer, endian, references)?;
                 Ok(())
             }
         }
@@ -292,10 +390,11 @@ impl<T: AdfRead + AdfTypeInfo + 'static> AdfRead for Arc<Vec<T>> {
     #[inline]
     fn read<R: Read + Seek>(
         reader: &mut R,
+        endian: Endian,
         references: &mut AdfReaderReferences,
     ) -> Result<Self, AdfReadWriteError> {
-        let offset = u64::read(reader, references)?;
-        let count = u64::read(reader, references)? as usize;
+        let offset = u64::read(reader, endian, references)?;
+        let count = u64::read(reader, endian, references)? as usize;
         let position = reader.stream_position()?;
         match offset {
             0 => Ok(Arc::new(Vec::default())),
@@ -317,7 +416,7 @@ impl<T: AdfRead + AdfTypeInfo + 'static> AdfRead for Arc<Vec<T>> {
                     // Read back `count` number of `T`
                     let mut result = Vec::with_capacity(count);
                     for _ in 0..count {
-                        result.push(T::read(reader, references)?);
+                        result.push(T::read(reader, endian, references)?);
                     }
 
                     // Store a reference
@@ -338,6 +437,7 @@ impl<T: AdfWrite + AdfTypeInfo + 'static> AdfWrite for Arc<Vec<T>> {
     fn write<W: Write + Seek>(
         &self,
         writer: &mut W,
+        endian: Endian,
         references: &mut AdfWriterReferences,
     ) -> Result<(), AdfReadWriteError> {
         match self.is_empty() {
@@ -347,7 +447,7 @@ impl<T: AdfWrite + AdfTypeInfo + 'static> AdfWrite for Arc<Vec<T>> {
                 if let Some(reference) = references.1.get(&key).cloned() {
                     if reference.1 == type_id {
                         // If the reference type is correct, write it's offset
-                        reference.0.write(writer, references)
+                        reference.0.write(writer, endian, references)
                     } else {
                         // Otherwise throw a reference error
                         Err(AdfReadWriteError::ReferenceError {
@@ -372,19 +472,19 @@ impl<T: AdfWrite + AdfTypeInfo + 'static> AdfWrite for Arc<Vec<T>> {
                     // Restore offset, and write values
                     writer.seek_absolute(offset)?;
                     for value in self.iter() {
-                        value.write(writer, references)?;
+                        value.write(writer, endian, references)?;
                     }
 
                     // Restore position, and write offset + count
                     writer.seek_absolute(position)?;
-                    offset.write(writer, references)?;
-                    count.write(writer, references)?;
+                    offset.write(writer, endian, references)?;
+                    count.write(writer, endian, references)?;
                     Ok(())
                 }
             }
             true => {
-                0u64.write(writer, references)?;
-                0u64.write(writer, references)?;
+                0u64.write(writer, endian, references)?;
+                0u64.write(writer, endian, references)?;
                 Ok(())
             }
         }
@@ -395,9 +495,10 @@ impl AdfRead for Arc<String> {
     #[inline]
     fn read<R: Read + Seek>(
         reader: &mut R,
+        endian: Endian,
         references: &mut AdfReaderReferences,
     ) -> Result<Self, AdfReadWriteError> {
-        let offset = u64::read(reader, references)?;
+        let offset = u64::read(reader, endian, references)?;
         let position = reader.stream_position()?;
         if let Some(reference) = references.get(&offset) {
             reference
@@ -416,7 +517,7 @@ impl AdfRead for Arc<String> {
             // Read back the string
             let mut buffer = Vec::with_capacity(128);
             loop {
-                let char = u8::read(reader, references)?;
+                let char = u8::read(reader, endian, references)?;
                 if char == 0 {
                     break;
                 }
@@ -439,6 +540,7 @@ impl AdfWrite for Arc<String> {
     fn write<W: Write + Seek>(
         &self,
         writer: &mut W,
+        endian: Endian,
         references: &mut AdfWriterReferences,
     ) -> Result<(), AdfReadWriteError> {
         let key = Arc::as_ptr(self) as usize;
@@ -446,7 +548,7 @@ impl AdfWrite for Arc<String> {
         if let Some(reference) = references.1.get(&key).cloned() {
             if reference.1 == type_id {
                 // If the reference type is correct, write it's offset
-                reference.0.write(writer, references)
+                reference.0.write(writer, endian, references)
             } else {
                 // Otherwise throw a reference error
                 Err(AdfReadWriteError::ReferenceError {
@@ -457,7 +559,7 @@ impl AdfWrite for Arc<String> {
         } else {
             // Write tail position, take note of offset + position
             let offset = references.0;
-            offset.write(writer, references)?;
+            offset.write(writer, endian, references)?;
             let position = writer.stream_position()?;
 
             // Seek to tail position, write data, update tail
@@ -479,12 +581,13 @@ macro_rules! read_write_scalar {
             #[inline]
             fn read<R: Read + Seek>(
                 reader: &mut R,
+                endian: Endian,
                 _references: &mut AdfReaderReferences,
             ) -> Result<Self, AdfReadWriteError> {
-                let mut result = Self::default();
+                let mut bytes = [0u8; Self::SIZE as usize];
                 reader.align(Self::ALIGN)?;
-                reader.read_exact(bytemuck::bytes_of_mut(&mut result))?;
-                Ok(result)
+                reader.read_exact(&mut bytes)?;
+                Ok(read_pod(&bytes, endian))
             }
         }
 
@@ -493,10 +596,13 @@ macro_rules! read_write_scalar {
             fn write<W: Write + Seek>(
                 &self,
                 writer: &mut W,
+                endian: Endian,
                 _references: &mut AdfWriterReferences,
             ) -> Result<(), AdfReadWriteError> {
+                let mut bytes = [0u8; Self::SIZE as usize];
+                write_pod(&mut bytes, *self, endian);
                 writer.align(Self::ALIGN)?;
-                writer.write(bytemuck::bytes_of(self))?;
+                writer.write_all(&bytes)?;
                 Ok(())
             }
         }
@@ -525,12 +631,13 @@ impl AdfRead for HashString {
     #[inline]
     fn read<R: Read + Seek>(
         reader: &mut R,
+        endian: Endian,
         _references: &mut AdfReaderReferences,
     ) -> Result<Self, AdfReadWriteError> {
-        let mut result = Self::default();
+        let mut bytes = [0u8; Self::SIZE as usize];
         reader.align(Self::ALIGN)?;
-        reader.read_exact(bytemuck::bytes_of_mut(result.hash_mut()))?;
-        Ok(result)
+        reader.read_exact(&mut bytes)?;
+        Ok(Self::new(read_pod(&bytes, endian)))
     }
 }
 
@@ -539,10 +646,13 @@ impl AdfWrite for HashString {
     fn write<W: Write + Seek>(
         &self,
         writer: &mut W,
+        endian: Endian,
         _references: &mut AdfWriterReferences,
     ) -> Result<(), AdfReadWriteError> {
+        let mut bytes = [0u8; Self::SIZE as usize];
+        write_pod(&mut bytes, self.hash(), endian);
         writer.align(Self::ALIGN)?;
-        writer.write(bytemuck::bytes_of(&self.hash()))?;
+        writer.write_all(&bytes)?;
         Ok(())
     }
 }
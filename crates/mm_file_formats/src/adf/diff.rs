@@ -0,0 +1,557 @@
+use std::sync::Arc;
+
+use super::{
+    AdfFile, AdfPathSegment, AdfReflectedPrimitive, AdfReflectedScalar, AdfReflectedValue,
+    AdfReflectionContext, AdfReflectionError, AdfValuePath,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AdfDiffOptions {
+    // Floats closer than this are considered equal
+    pub tolerance: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdfChange {
+    AddedInstance {
+        type_name: String,
+    },
+    RemovedInstance {
+        type_name: String,
+    },
+    Scalar {
+        old: AdfReflectedScalar,
+        new: AdfReflectedScalar,
+    },
+    String {
+        old: String,
+        new: String,
+    },
+    ArrayLength {
+        old: usize,
+        new: usize,
+    },
+    // Only the presence of a value is compared when pointers are null on one side
+    Presence {
+        old: bool,
+        new: bool,
+    },
+    Type {
+        old: String,
+        new: String,
+    },
+}
+
+// Paths of instance changes start with the instance's name
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdfDifference {
+    pub path: AdfValuePath,
+    pub change: AdfChange,
+}
+
+impl std::fmt::Display for AdfDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.path)?;
+        match &self.change {
+            AdfChange::AddedInstance { type_name } => write!(f, "added {type_name}"),
+            AdfChange::RemovedInstance { type_name } => write!(f, "removed {type_name}"),
+            AdfChange::Scalar { old, new } => write!(f, "{old} -> {new}"),
+            AdfChange::String { old, new } => write!(f, "{old:?} -> {new:?}"),
+            AdfChange::ArrayLength { old, new } => write!(f, "{old} -> {new} elements"),
+            AdfChange::Presence { old, new } => {
+                let presence = |x: bool| if x { "value" } else { "null" };
+                write!(f, "{} -> {}", presence(*old), presence(*new))
+            }
+            AdfChange::Type { old, new } => write!(f, "{old} -> {new}"),
+        }
+    }
+}
+
+impl AdfReflectionContext {
+    // Elements beyond the shorter of two arrays aren't compared, only the change in length is
+    pub fn diff_values(
+        &self,
+        old: &AdfReflectedValue,
+        new: &AdfReflectedValue,
+        options: &AdfDiffOptions,
+    ) -> Vec<AdfDifference> {
+        let mut differences = vec![];
+        self.diff_value(
+            old,
+            new,
+            options,
+            &mut AdfValuePath::default(),
+            &mut differences,
+        );
+        differences
+    }
+
+    // Instances are matched by name, in order, so the types of both files must be loaded
+    pub fn diff_files(
+        &self,
+        old: &AdfFile,
+        new: &AdfFile,
+        options: &AdfDiffOptions,
+    ) -> Result<Vec<AdfDifference>, AdfReflectionError> {
+        let mut differences = vec![];
+        let mut matched = vec![false; new.instances.len()];
+        for old_instance in &old.instances {
+            let name = old_instance.name.as_ref();
+            let mut path = AdfValuePath(vec![AdfPathSegment::Member(name.to_owned())]);
+            let found = new
+                .instances
+                .iter()
+                .enumerate()
+                .find(|(index, x)| !matched[*index] && x.name.as_ref() == name);
+            let Some((index, new_instance)) = found else {
+                differences.push(AdfDifference {
+                    path,
+                    change: AdfChange::RemovedInstance {
+                        type_name: self.type_name(old_instance.type_hash),
+                    },
+                });
+                continue;
+            };
+            matched[index] = true;
+
+            let old_value = self.read_instance(old_instance)?;
+            let new_value = self.read_instance(new_instance)?;
+            self.diff_value(&old_value, &new_value, options, &mut path, &mut differences);
+        }
+
+        for (instance, _) in new
+            .instances
+            .iter()
+            .zip(matched)
+            .filter(|(_, matched)| !matched)
+        {
+            differences.push(AdfDifference {
+                path: AdfValuePath(vec![AdfPathSegment::Member(instance.name.to_string())]),
+                change: AdfChange::AddedInstance {
+                    type_name: self.type_name(instance.type_hash),
+                },
+            });
+        }
+        Ok(differences)
+    }
+
+    fn diff_value(
+        &self,
+        old: &AdfReflectedValue,
+        new: &AdfReflectedValue,
+        options: &AdfDiffOptions,
+        path: &mut AdfValuePath,
+        differences: &mut Vec<AdfDifference>,
+    ) {
+        let mut push = |change| {
+            differences.push(AdfDifference {
+                path: path.clone(),
+                change,
+            });
+        };
+        let type_hash = old.0;
+        if old.0 != new.0 {
+            push(AdfChange::Type {
+                old: self.type_name(old.0),
+                new: self.type_name(new.0),
+            });
+            return;
+        }
+
+        match (&old.1, &new.1) {
+            (AdfReflectedPrimitive::Scalar(old), AdfReflectedPrimitive::Scalar(new))
+            | (AdfReflectedPrimitive::Bitfield(old), AdfReflectedPrimitive::Bitfield(new))
+            | (AdfReflectedPrimitive::Enumeration(old), AdfReflectedPrimitive::Enumeration(new))
+            | (AdfReflectedPrimitive::StringHash(old), AdfReflectedPrimitive::StringHash(new)) => {
+                if !scalar_eq(old, new, options.tolerance) {
+                    push(AdfChange::Scalar {
+                        old: old.clone(),
+                        new: new.clone(),
+                    });
+                }
+            }
+            (AdfReflectedPrimitive::String(old), AdfReflectedPrimitive::String(new)) => {
+                if old != new {
+                    push(AdfChange::String {
+                        old: old.to_string(),
+                        new: new.to_string(),
+                    });
+                }
+            }
+            (AdfReflectedPrimitive::Structure(old), AdfReflectedPrimitive::Structure(new)) => {
+                let Some(type_info) = self.get_type_by_hash(type_hash) else {
+                    return;
+                };
+                for ((member, old), new) in type_info.members.iter().zip(old).zip(new) {
+                    path.0.push(AdfPathSegment::Member(member.name.to_string()));
+                    self.diff_value(old, new, options, path, differences);
+                    path.0.pop();
+                }
+            }
+            (AdfReflectedPrimitive::Array(old), AdfReflectedPrimitive::Array(new)) => {
+                if !Arc::ptr_eq(old, new) {
+                    self.diff_elements(old, new, options, path, differences);
+                }
+            }
+            (AdfReflectedPrimitive::InlineArray(old), AdfReflectedPrimitive::InlineArray(new)) => {
+                self.diff_elements(old, new, options, path, differences);
+            }
+            (
+                AdfReflectedPrimitive::Pointer(Some(old))
+                | AdfReflectedPrimitive::Recursive(Some(old))
+                | AdfReflectedPrimitive::Deferred(Some(old)),
+                AdfReflectedPrimitive::Pointer(Some(new))
+                | AdfReflectedPrimitive::Recursive(Some(new))
+                | AdfReflectedPrimitive::Deferred(Some(new)),
+            ) => {
+                if !Arc::ptr_eq(old, new) {
+                    self.diff_value(old, new, options, path, differences);
+                }
+            }
+            (
+                AdfReflectedPrimitive::Pointer(old)
+                | AdfReflectedPrimitive::Recursive(old)
+                | AdfReflectedPrimitive::Deferred(old),
+                AdfReflectedPrimitive::Pointer(new)
+                | AdfReflectedPrimitive::Recursive(new)
+                | AdfReflectedPrimitive::Deferred(new),
+            ) => {
+                if old.is_some() != new.is_some() {
+                    push(AdfChange::Presence {
+                        old: old.is_some(),
+                        new: new.is_some(),
+                    });
+                }
+            }
+            // Values of the same type can only differ in primitive if they were built by hand
+            _ => push(AdfChange::Type {
+                old: format!("{:?}", old.1.primitive()),
+                new: format!("{:?}", new.1.primitive()),
+            }),
+        }
+    }
+
+    fn diff_elements(
+        &self,
+        old: &[AdfReflectedValue],
+        new: &[AdfReflectedValue],
+        options: &AdfDiffOptions,
+        path: &mut AdfValuePath,
+        differences: &mut Vec<AdfDifference>,
+    ) {
+        if old.len() != new.len() {
+            differences.push(AdfDifference {
+                path: path.clone(),
+                change: AdfChange::ArrayLength {
+                    old: old.len(),
+                    new: new.len(),
+                },
+            });
+        }
+        for (index, (old, new)) in old.iter().zip(new).enumerate() {
+            path.0.push(AdfPathSegment::Element(index));
+            self.diff_value(old, new, options, path, differences);
+            path.0.pop();
+        }
+    }
+
+    pub(crate) fn type_name(&self, type_hash: u32) -> String {
+        self.get_type_by_hash(type_hash)
+            .map_or_else(|| format!("{type_hash:#010x}"), |x| x.name.to_string())
+    }
+}
+
+fn scalar_eq(old: &AdfReflectedScalar, new: &AdfReflectedScalar, tolerance: f64) -> bool {
+    match (old, new) {
+        (AdfReflectedScalar::F32(_), AdfReflectedScalar::F32(_))
+        | (AdfReflectedScalar::F64(_), AdfReflectedScalar::F64(_)) => {
+            let (old, new) = (old.to_float(), new.to_float());
+            old == new || (old - new).abs() <= tolerance || (old.is_nan() && new.is_nan())
+        }
+        _ => old == new,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{built_in_types, AdfPrimitive, AdfType, AdfTypeInfo, TYPE_LIBRARIES};
+
+    fn built_in(predicate: impl Fn(&AdfType) -> bool) -> AdfType {
+        built_in_types()
+            .iter()
+            .find(|x| predicate(x))
+            .unwrap()
+            .clone()
+    }
+
+    // Part { Ratio: float, Count: uint32, Name: String, Items: A[uint32], Child: uint32* }
+    fn context() -> (AdfReflectionContext, AdfType) {
+        let float = built_in(|x| x.type_hash == <f32 as AdfTypeInfo>::HASH);
+        let uint32 = built_in(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH);
+        let string = built_in(|x| x.primitive == AdfPrimitive::String);
+        let items = AdfType::array(&uint32);
+        let child = AdfType::pointer(&uint32);
+        let part = AdfType::structure("Part")
+            .member("Ratio", &float)
+            .member("Count", &uint32)
+            .member("Name", &string)
+            .member("Items", &items)
+            .member("Child", &child)
+            .build();
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: vec![float, uint32, string, items, child, part.clone()],
+            ..Default::default()
+        });
+        (context, part)
+    }
+
+    struct Part<'a> {
+        ratio: f32,
+        count: u32,
+        name: &'a str,
+        items: &'a [u32],
+        child: Option<u32>,
+    }
+
+    impl Default for Part<'_> {
+        fn default() -> Self {
+            Self {
+                ratio: 1.0,
+                count: 1,
+                name: "Part",
+                items: &[1, 2],
+                child: None,
+            }
+        }
+    }
+
+    impl Part<'_> {
+        fn value(&self, part: &AdfType) -> AdfReflectedValue {
+            let uint32 = |value| {
+                AdfReflectedValue(
+                    <u32 as AdfTypeInfo>::HASH,
+                    AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(value)),
+                )
+            };
+            let members = &part.members;
+            AdfReflectedValue(
+                part.type_hash,
+                AdfReflectedPrimitive::Structure(vec![
+                    AdfReflectedValue(
+                        members[0].type_hash,
+                        AdfReflectedPrimitive::Scalar(AdfReflectedScalar::F32(self.ratio)),
+                    ),
+                    uint32(self.count),
+                    AdfReflectedValue(
+                        members[2].type_hash,
+                        AdfReflectedPrimitive::String(Arc::new(self.name.to_owned())),
+                    ),
+                    AdfReflectedValue(
+                        members[3].type_hash,
+                        AdfReflectedPrimitive::Array(Arc::new(
+                            self.items.iter().copied().map(uint32).collect(),
+                        )),
+                    ),
+                    AdfReflectedValue(
+                        members[4].type_hash,
+                        AdfReflectedPrimitive::Pointer(self.child.map(|x| Arc::new(uint32(x)))),
+                    ),
+                ]),
+            )
+        }
+    }
+
+    fn diff(old: Part, new: Part, tolerance: f64) -> Vec<String> {
+        let (context, part) = context();
+        context
+            .diff_values(
+                &old.value(&part),
+                &new.value(&part),
+                &AdfDiffOptions { tolerance },
+            )
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn equal_values_have_no_differences() {
+        assert!(diff(Part::default(), Part::default(), 0.0).is_empty());
+    }
+
+    #[test]
+    fn floats_within_tolerance_are_equal() {
+        let old = || Part::default();
+        let new = || Part {
+            ratio: 1.25,
+            ..Default::default()
+        };
+        assert!(diff(old(), new(), 0.25).is_empty());
+        assert_eq!(diff(old(), new(), 0.1), ["Ratio: 1 -> 1.25"]);
+        assert_eq!(diff(old(), new(), 0.0), ["Ratio: 1 -> 1.25"]);
+
+        // Integers are compared exactly, whatever the tolerance
+        let new = Part {
+            count: 2,
+            ..Default::default()
+        };
+        assert_eq!(diff(old(), new, 10.0), ["Count: 1 -> 2"]);
+    }
+
+    #[test]
+    fn strings_are_compared() {
+        let new = Part {
+            name: "Gear",
+            ..Default::default()
+        };
+        assert_eq!(
+            diff(Part::default(), new, 0.0),
+            [r#"Name: "Part" -> "Gear""#]
+        );
+    }
+
+    #[test]
+    fn array_lengths_are_compared_before_elements() {
+        let new = Part {
+            items: &[1, 3, 4],
+            ..Default::default()
+        };
+        assert_eq!(
+            diff(Part::default(), new, 0.0),
+            ["Items: 2 -> 3 elements", "Items[1]: 2 -> 3"]
+        );
+    }
+
+    #[test]
+    fn pointers_are_compared_by_presence_then_target() {
+        let some = |value| Part {
+            child: Some(value),
+            ..Default::default()
+        };
+        assert_eq!(
+            diff(Part::default(), some(1), 0.0),
+            ["Child: null -> value"]
+        );
+        assert_eq!(
+            diff(some(1), Part::default(), 0.0),
+            ["Child: value -> null"]
+        );
+        assert_eq!(diff(some(1), some(2), 0.0), ["Child: 1 -> 2"]);
+    }
+
+    // Instances are written one at a time, as writing replaces any with the same name
+    fn file(context: &AdfReflectionContext, part: &AdfType, instances: &[(&str, u32)]) -> AdfFile {
+        let mut file = AdfFile::default();
+        for (name, count) in instances {
+            let value = Part {
+                count: *count,
+                ..Default::default()
+            }
+            .value(part);
+            let mut single = AdfFile::default();
+            context.write_instance(name, &value, &mut single).unwrap();
+            file.instances.append(&mut single.instances);
+        }
+        file
+    }
+
+    #[test]
+    fn instances_are_matched_by_name_in_order() {
+        let (context, part) = context();
+        let old = file(&context, &part, &[("A", 1), ("A", 2), ("B", 1)]);
+        let new = file(&context, &part, &[("A", 1), ("C", 1), ("A", 3)]);
+        let differences: Vec<String> = context
+            .diff_files(&old, &new, &AdfDiffOptions::default())
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            differences,
+            ["A.Count: 2 -> 3", "B: removed Part", "C: added Part"]
+        );
+
+        // Without a second `A` to match, the first is compared and the second removed
+        let new = file(&context, &part, &[("A", 2), ("B", 1)]);
+        let differences = context
+            .diff_files(&old, &new, &AdfDiffOptions::default())
+            .unwrap();
+        assert_eq!(
+            differences,
+            [
+                AdfDifference {
+                    path: "A.Count".parse().unwrap(),
+                    change: AdfChange::Scalar {
+                        old: AdfReflectedScalar::U32(1),
+                        new: AdfReflectedScalar::U32(2),
+                    },
+                },
+                AdfDifference {
+                    path: "A".parse().unwrap(),
+                    change: AdfChange::RemovedInstance {
+                        type_name: "Part".into(),
+                    },
+                },
+            ]
+        );
+    }
+
+    // Sets the last element of the named instance, an inline array of floats
+    fn edit(context: &AdfReflectionContext, file: &mut AdfFile, index: usize, ratio: f32) {
+        let instance = Arc::make_mut(&mut file.instances[index]);
+        let mut value = context.read_instance(instance).unwrap();
+        context
+            .set(&mut value, &"[2]".parse().unwrap(), ratio)
+            .unwrap();
+        context.write_instance_value(&value, instance).unwrap();
+    }
+
+    #[test]
+    fn library_copies_are_diffed() {
+        let library = TYPE_LIBRARIES
+            .iter()
+            .find(|lib| lib.extension == "vpgeneralc")
+            .unwrap();
+        let mut old = library.load().unwrap();
+        let mut context = AdfReflectionContext::from_extension(library.extension).unwrap();
+        context.load_types_from_file(&old);
+        let mut new = old.clone();
+
+        edit(&context, &mut old, 0, 1.5);
+        edit(&context, &mut new, 0, 1.25);
+        let removed = new.instances.remove(1);
+
+        let name = old.instances[0].name.as_ref();
+        let differences = context
+            .diff_files(&old, &new, &AdfDiffOptions { tolerance: 0.1 })
+            .unwrap();
+        assert_eq!(
+            differences,
+            [
+                AdfDifference {
+                    path: AdfValuePath(vec![
+                        AdfPathSegment::Member(name.into()),
+                        AdfPathSegment::Element(2),
+                    ]),
+                    change: AdfChange::Scalar {
+                        old: AdfReflectedScalar::F32(1.5),
+                        new: AdfReflectedScalar::F32(1.25),
+                    },
+                },
+                AdfDifference {
+                    path: AdfValuePath(vec![AdfPathSegment::Member(removed.name.to_string())]),
+                    change: AdfChange::RemovedInstance {
+                        type_name: "IA[float]".into(),
+                    },
+                },
+            ]
+        );
+        assert!(context
+            .diff_files(&old, &new, &AdfDiffOptions { tolerance: 0.25 })
+            .unwrap()
+            .iter()
+            .all(|x| matches!(x.change, AdfChange::RemovedInstance { .. })));
+    }
+}
@@ -0,0 +1,313 @@
+use std::{collections::HashSet, sync::Arc};
+
+use thiserror::Error;
+
+use super::{AdfFile, AdfInstance, AdfMemberValue, AdfType};
+
+#[derive(Error, Debug)]
+pub enum AdfMergeError {
+    #[error("type {name} ({type_hash:#010x}) has conflicting definitions")]
+    TypeConflict { name: String, type_hash: u32 },
+    #[error("instance {name} ({type_hash:#010x}) exists in both files")]
+    InstanceConflict { name: String, type_hash: u32 },
+    #[error("instance {0} was not found")]
+    MissingInstance(String),
+}
+
+impl AdfFile {
+    // Types are deduplicated by hash, and must share the same layout to be merged
+    pub fn merge(&mut self, other: &AdfFile) -> Result<(), AdfMergeError> {
+        for type_def in &other.types {
+            if let Some(existing) = self.get_type_by_hash(type_def.type_hash) {
+                if !existing.layout_eq(type_def) {
+                    return Err(AdfMergeError::TypeConflict {
+                        name: type_def.name.to_string(),
+                        type_hash: type_def.type_hash,
+                    });
+                }
+            }
+        }
+        for instance in &other.instances {
+            if self
+                .get_instance_by_hash(instance.name.as_ref(), instance.type_hash)
+                .is_some()
+            {
+                return Err(AdfMergeError::InstanceConflict {
+                    name: instance.name.to_string(),
+                    type_hash: instance.type_hash,
+                });
+            }
+        }
+
+        for type_def in &other.types {
+            if self.get_type_by_hash(type_def.type_hash).is_none() {
+                self.types.push(type_def.clone());
+            }
+        }
+        self.instances.extend(other.instances.iter().cloned());
+        // Without a table of our own, the merged instances are rebuilt from anyway
+        if let Some(hashes) = &mut self.hashes {
+            let other_hashes = other
+                .hashes
+                .clone()
+                .unwrap_or_else(|| other.collect_hashes());
+            for hash in other_hashes {
+                if !hashes.contains(&hash) {
+                    hashes.push(hash);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Only the types referenced by the extracted instances are carried over, and hashes are
+    // rebuilt from the instances when written
+    pub fn extract<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<AdfFile, AdfMergeError> {
+        let mut instances: Vec<Arc<AdfInstance>> = vec![];
+        for name in names {
+            // Names given more than once still only extract their instances once
+            let mut found = false;
+            for instance in &self.instances {
+                if instance.name.as_ref() != name {
+                    continue;
+                }
+                found = true;
+                if !instances.iter().any(|x| Arc::ptr_eq(x, instance)) {
+                    instances.push(instance.clone());
+                }
+            }
+            if !found {
+                return Err(AdfMergeError::MissingInstance(name.to_owned()));
+            }
+        }
+
+        let mut used = HashSet::new();
+        for instance in &instances {
+            self.collect_instance_types(instance, &mut used);
+        }
+
+        Ok(AdfFile {
+            version: self.version,
+            instances,
+            types: self
+                .types
+                .iter()
+                .filter(|type_def| used.contains(&type_def.type_hash))
+                .cloned()
+                .collect(),
+            hashes: None,
+            description: self.description.clone(),
+        })
+    }
+
+    // Deferred values name the type of their target in the buffer, so the instance's values are
+    // walked as well as its type
+    fn collect_instance_types(&self, instance: &AdfInstance, used: &mut HashSet<u32>) {
+        let mut reached = vec![instance.type_hash];
+        self.walk_instance(instance, &mut |type_info, _| {
+            reached.push(type_info.type_hash);
+        });
+        for type_hash in reached {
+            self.collect_used_types(type_hash, used);
+        }
+    }
+
+    fn collect_used_types(&self, type_hash: u32, used: &mut HashSet<u32>) {
+        if !used.insert(type_hash) {
+            return;
+        }
+        let Some(type_def) = self.get_type_by_hash(type_hash) else {
+            return;
+        };
+        if type_def.element_type_hash != 0 {
+            self.collect_used_types(type_def.element_type_hash, used);
+        }
+        for member in type_def.members.iter() {
+            self.collect_used_types(member.type_hash, used);
+            if let AdfMemberValue::InstanceValue(instance) = &member.value {
+                self.collect_instance_types(instance, used);
+            }
+        }
+    }
+}
+
+impl AdfType {
+    // Compares everything that affects how values of this type are laid out, ignoring defaults
+    pub fn layout_eq(&self, other: &AdfType) -> bool {
+        self.primitive == other.primitive
+            && self.size == other.size
+            && self.alignment == other.alignment
+            && self.type_hash == other.type_hash
+            && self.scalar_type == other.scalar_type
+            && self.element_type_hash == other.element_type_hash
+            && self.element_length == other.element_length
+            && self.members.len() == other.members.len()
+            && self.members.iter().zip(other.members.iter()).all(|(a, b)| {
+                a.name == b.name
+                    && a.type_hash == b.type_hash
+                    && a.alignment == b.alignment
+                    && a.offsets == b.offsets
+            })
+            && self.enumerations == other.enumerations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mm_hashing::HashString;
+
+    use super::*;
+    use crate::adf::{built_in_types, AdfPrimitive, AdfTypeInfo};
+
+    fn built_in(predicate: impl Fn(&AdfType) -> bool) -> AdfType {
+        built_in_types()
+            .iter()
+            .find(|x| predicate(x))
+            .unwrap()
+            .clone()
+    }
+
+    fn structure(name: &str, members: &[(&str, &AdfType)]) -> AdfType {
+        members
+            .iter()
+            .fold(AdfType::structure(name), |builder, (name, type_def)| {
+                builder.member(name, type_def)
+            })
+            .build()
+    }
+
+    // Points the deferred value at the start of the instance to a value appended to its buffer
+    fn defer(instance: &mut AdfInstance, target: &AdfType) {
+        let buffer = instance.buffer.to_mut();
+        let offset = buffer.len() as u64;
+        buffer[0..8].copy_from_slice(&offset.to_le_bytes());
+        buffer[8..12].copy_from_slice(&target.type_hash.to_le_bytes());
+        for _ in 0..target.size {
+            buffer.push(0);
+        }
+    }
+
+    // `Holder` reaches `Target` through a deferred value, and `Extra` through a deferred value
+    // in the default of its `Fallback` member
+    fn file() -> AdfFile {
+        let uint32 = built_in(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH);
+        let deferred = built_in(|x| x.primitive == AdfPrimitive::Deferred);
+        let target = structure("Target", &[("Value", &uint32)]);
+        let extra = structure("Extra", &[("Value", &uint32)]);
+        let fallback = structure("Fallback", &[("Data", &deferred)]);
+        let unrelated = structure("Unrelated", &[("Value", &uint32)]);
+
+        let mut default = AdfInstance::from_type("Default", &fallback);
+        defer(&mut default, &extra);
+        let holder = AdfType::structure("Holder")
+            .member("Data", &deferred)
+            .member("Fallback", &fallback)
+            .default_value(AdfMemberValue::InstanceValue(Arc::new(default).into()))
+            .build();
+
+        let mut instance = AdfInstance::from_type("Holder", &holder);
+        defer(&mut instance, &target);
+        AdfFile {
+            instances: vec![
+                Arc::new(instance),
+                Arc::new(AdfInstance::from_type("Unrelated", &unrelated)),
+            ],
+            types: vec![target, extra, fallback, holder, unrelated],
+            ..Default::default()
+        }
+    }
+
+    fn type_names(file: &AdfFile) -> Vec<&str> {
+        file.types.iter().map(|x| x.name.as_ref()).collect()
+    }
+
+    #[test]
+    fn extract_follows_deferred_values_and_defaults() {
+        let extracted = file().extract(["Holder"]).unwrap();
+        assert_eq!(extracted.instances.len(), 1);
+        assert_eq!(
+            type_names(&extracted),
+            vec!["Target", "Extra", "Fallback", "Holder"]
+        );
+        assert_eq!(extracted.hashes, None);
+    }
+
+    #[test]
+    fn extract_missing_instance() {
+        assert!(matches!(
+            file().extract(["Missing"]),
+            Err(AdfMergeError::MissingInstance(name)) if name == "Missing"
+        ));
+    }
+
+    #[test]
+    fn extract_repeated_names() {
+        let extracted = file().extract(["Holder", "Unrelated", "Holder"]).unwrap();
+        let names: Vec<&str> = extracted
+            .instances
+            .iter()
+            .map(|x| x.name.as_ref())
+            .collect();
+        assert_eq!(names, vec!["Holder", "Unrelated"]);
+
+        // Every instance with the name is extracted, once
+        let mut file = file();
+        file.instances.push(file.instances[1].clone());
+        let extracted = file.extract(["Unrelated", "Unrelated"]).unwrap();
+        assert_eq!(extracted.instances.len(), 1);
+        let copy = Arc::new(file.instances[1].as_ref().clone());
+        file.instances.push(copy);
+        let extracted = file.extract(["Unrelated", "Unrelated"]).unwrap();
+        assert_eq!(extracted.instances.len(), 2);
+    }
+
+    #[test]
+    fn merge_extracted_files() {
+        let file = file();
+        let mut merged = file.extract(["Holder"]).unwrap();
+        merged.hashes = Some(vec![HashString::new(1)]);
+        let mut other = file.extract(["Unrelated"]).unwrap();
+        other.hashes = Some(vec![HashString::new(1), HashString::new(2)]);
+        merged.merge(&other).unwrap();
+
+        assert_eq!(merged.instances.len(), 2);
+        assert_eq!(
+            type_names(&merged),
+            vec!["Target", "Extra", "Fallback", "Holder", "Unrelated"]
+        );
+        assert_eq!(
+            merged.hashes,
+            Some(vec![HashString::new(1), HashString::new(2)])
+        );
+    }
+
+    #[test]
+    fn merge_conflicting_instance() {
+        let mut file = file();
+        let other = file.extract(["Unrelated"]).unwrap();
+        assert!(matches!(
+            file.merge(&other),
+            Err(AdfMergeError::InstanceConflict { name, .. }) if name == "Unrelated"
+        ));
+    }
+
+    #[test]
+    fn merge_conflicting_type() {
+        let mut file = file();
+        let mut other = file.extract(["Unrelated"]).unwrap();
+        other.instances.clear();
+        let mut member = other.types[0].members[0].clone();
+        member.offsets.set_byte(4);
+        other.types[0].members = vec![member].into();
+
+        assert!(matches!(
+            file.merge(&other),
+            Err(AdfMergeError::TypeConflict { name, .. }) if name == "Unrelated"
+        ));
+        // Nothing is merged when a conflict is found
+        assert_eq!(file.types.len(), 5);
+    }
+}
@@ -0,0 +1,474 @@
+use std::{collections::HashMap, sync::Arc};
+
+use super::{
+    AdfPathSegment, AdfPrimitive, AdfReflectedPrimitive, AdfReflectedScalar, AdfReflectedValue,
+    AdfReflectionContext, AdfReflectionError, AdfReflectionErrorKind, AdfType, AdfValuePath,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdfMigration {
+    pub value: AdfReflectedValue,
+    // Paths within the old value of members and elements the new type has no room for
+    pub dropped: Vec<AdfValuePath>,
+}
+
+// Values and arrays referenced more than once are migrated once, so they stay shared, and what
+// they drop is reported at the first path they're reached by. They're keyed by their address
+// within the old value, which outlives the migration, and their new type
+#[derive(Default)]
+struct AdfMigrationState {
+    dropped: Vec<AdfValuePath>,
+    values: HashMap<(usize, u32), Arc<AdfReflectedValue>>,
+    arrays: HashMap<(usize, u32), Arc<Vec<AdfReflectedValue>>>,
+}
+
+impl AdfReflectionContext {
+    // Both types must be loaded. Members are matched by name, members the old type lacks take
+    // their defaults, and scalars are converted as long as they fit. Enumerations are matched
+    // by name, keeping the number of values the new type doesn't declare
+    pub fn migrate_value(
+        &self,
+        value: &AdfReflectedValue,
+        type_hash: u32,
+    ) -> Result<AdfMigration, AdfReflectionError> {
+        let mut state = AdfMigrationState::default();
+        let value = self.migrate(value, type_hash, &mut AdfValuePath::default(), &mut state)?;
+        Ok(AdfMigration {
+            value,
+            dropped: state.dropped,
+        })
+    }
+
+    fn migrate(
+        &self,
+        value: &AdfReflectedValue,
+        type_hash: u32,
+        path: &mut AdfValuePath,
+        state: &mut AdfMigrationState,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
+        if value.0 == type_hash {
+            return Ok(value.clone());
+        }
+        let Some(type_info) = self.get_type_by_hash(type_hash) else {
+            return Err(self.migrate_error(
+                type_hash,
+                path,
+                AdfReflectionErrorKind::MissingType(type_hash),
+            ));
+        };
+
+        let primitive = match (&value.1, &type_info.primitive) {
+            (
+                AdfReflectedPrimitive::Scalar(scalar)
+                | AdfReflectedPrimitive::Bitfield(scalar)
+                | AdfReflectedPrimitive::Enumeration(scalar)
+                | AdfReflectedPrimitive::StringHash(scalar),
+                AdfPrimitive::Scalar
+                | AdfPrimitive::Bitfield
+                | AdfPrimitive::Enumeration
+                | AdfPrimitive::StringHash,
+            ) => {
+                let scalar = self
+                    .migrate_scalar(value, scalar, type_info)
+                    .map_err(|kind| self.migrate_error(type_hash, path, kind))?;
+                match type_info.primitive {
+                    AdfPrimitive::Scalar => AdfReflectedPrimitive::Scalar(scalar),
+                    AdfPrimitive::Bitfield => AdfReflectedPrimitive::Bitfield(scalar),
+                    AdfPrimitive::Enumeration => AdfReflectedPrimitive::Enumeration(scalar),
+                    _ => AdfReflectedPrimitive::StringHash(scalar),
+                }
+            }
+            (AdfReflectedPrimitive::String(string), AdfPrimitive::String) => {
+                AdfReflectedPrimitive::String(string.clone())
+            }
+            (AdfReflectedPrimitive::Structure(values), AdfPrimitive::Structure) => {
+                AdfReflectedPrimitive::Structure(
+                    self.migrate_members(value.0, values, type_info, path, state)?,
+                )
+            }
+            (AdfReflectedPrimitive::Array(values), AdfPrimitive::Array) => {
+                let key = (Arc::as_ptr(values) as usize, type_hash);
+                let values = if let Some(values) = state.arrays.get(&key) {
+                    values.clone()
+                } else {
+                    let values = Arc::new(self.migrate_elements(values, type_info, path, state)?);
+                    state.arrays.insert(key, values.clone());
+                    values
+                };
+                AdfReflectedPrimitive::Array(values)
+            }
+            (AdfReflectedPrimitive::Array(values), AdfPrimitive::InlineArray) => {
+                AdfReflectedPrimitive::InlineArray(
+                    self.migrate_elements(values, type_info, path, state)?,
+                )
+            }
+            (
+                AdfReflectedPrimitive::InlineArray(values),
+                AdfPrimitive::Array | AdfPrimitive::InlineArray,
+            ) => {
+                let values = self.migrate_elements(values, type_info, path, state)?;
+                if type_info.primitive == AdfPrimitive::InlineArray {
+                    AdfReflectedPrimitive::InlineArray(values)
+                } else {
+                    AdfReflectedPrimitive::Array(Arc::new(values))
+                }
+            }
+            (AdfReflectedPrimitive::Pointer(value), AdfPrimitive::Pointer) => {
+                AdfReflectedPrimitive::Pointer(
+                    value
+                        .as_ref()
+                        .map(|value| self.migrate_target(value, type_info, path, state))
+                        .transpose()?,
+                )
+            }
+            (AdfReflectedPrimitive::Recursive(value), AdfPrimitive::Recursive) => {
+                AdfReflectedPrimitive::Recursive(
+                    value
+                        .as_ref()
+                        .map(|value| self.migrate_target(value, type_info, path, state))
+                        .transpose()?,
+                )
+            }
+            // Deferred values carry their own type, which isn't part of the layout
+            (AdfReflectedPrimitive::Deferred(value), AdfPrimitive::Deferred) => {
+                AdfReflectedPrimitive::Deferred(value.clone())
+            }
+            (primitive, expected) => {
+                return Err(self.migrate_error(
+                    type_hash,
+                    path,
+                    AdfReflectionErrorKind::PrimitiveMismatch {
+                        expected: expected.clone(),
+                        found: primitive.primitive(),
+                    },
+                ));
+            }
+        };
+        Ok(AdfReflectedValue(type_hash, primitive))
+    }
+
+    fn migrate_scalar(
+        &self,
+        value: &AdfReflectedValue,
+        scalar: &AdfReflectedScalar,
+        type_info: &AdfType,
+    ) -> Result<AdfReflectedScalar, AdfReflectionErrorKind> {
+        if let (AdfReflectedPrimitive::Enumeration(_), AdfPrimitive::Enumeration) =
+            (&value.1, &type_info.primitive)
+        {
+            let name = self
+                .get_type_by_hash(value.0)
+                .and_then(|old_info| old_info.enumeration_name(scalar));
+            if let Some(scalar) = name.and_then(|name| type_info.enumeration_value(name)) {
+                return scalar.coerce(type_info);
+            }
+        }
+        scalar.coerce(type_info)
+    }
+
+    fn migrate_target(
+        &self,
+        value: &Arc<AdfReflectedValue>,
+        type_info: &AdfType,
+        path: &mut AdfValuePath,
+        state: &mut AdfMigrationState,
+    ) -> Result<Arc<AdfReflectedValue>, AdfReflectionError> {
+        let key = (Arc::as_ptr(value) as usize, type_info.element_type_hash);
+        if let Some(value) = state.values.get(&key) {
+            return Ok(value.clone());
+        }
+        let value = Arc::new(self.migrate(value, type_info.element_type_hash, path, state)?);
+        state.values.insert(key, value.clone());
+        Ok(value)
+    }
+
+    fn migrate_members(
+        &self,
+        old_hash: u32,
+        values: &[AdfReflectedValue],
+        type_info: &AdfType,
+        path: &mut AdfValuePath,
+        state: &mut AdfMigrationState,
+    ) -> Result<Vec<AdfReflectedValue>, AdfReflectionError> {
+        let Some(old_info) = self.get_type_by_hash(old_hash) else {
+            return Err(self.migrate_error(
+                old_hash,
+                path,
+                AdfReflectionErrorKind::MissingType(old_hash),
+            ));
+        };
+
+        let mut members = Vec::with_capacity(type_info.members.len());
+        for member in type_info.members.iter() {
+            let name = member.name.as_str();
+            let old = old_info
+                .members
+                .iter()
+                .zip(values)
+                .find(|(x, _)| x.name.as_str() == name);
+            members.push(if let Some((_, old)) = old {
+                path.0.push(AdfPathSegment::Member(name.to_owned()));
+                let value = self.migrate(old, member.type_hash, path, state)?;
+                path.0.pop();
+                value
+            } else {
+                self.member_default_value(member).ok_or_else(|| {
+                    self.migrate_error(
+                        type_info.type_hash,
+                        path,
+                        AdfReflectionErrorKind::MissingMember(name.to_owned()),
+                    )
+                })?
+            });
+        }
+
+        for member in old_info.members.iter().take(values.len()) {
+            if !type_info.members.iter().any(|x| x.name == member.name) {
+                let mut path = path.clone();
+                path.0.push(AdfPathSegment::Member(member.name.to_string()));
+                state.dropped.push(path);
+            }
+        }
+        Ok(members)
+    }
+
+    // Inline arrays are cut or padded with defaults to their new length
+    fn migrate_elements(
+        &self,
+        values: &[AdfReflectedValue],
+        type_info: &AdfType,
+        path: &AdfValuePath,
+        state: &mut AdfMigrationState,
+    ) -> Result<Vec<AdfReflectedValue>, AdfReflectionError> {
+        let element_type_hash = type_info.element_type_hash;
+        let length = if type_info.primitive == AdfPrimitive::InlineArray {
+            type_info.element_length as usize
+        } else {
+            values.len()
+        };
+
+        let mut elements = Vec::with_capacity(length);
+        for (index, value) in values.iter().enumerate() {
+            let mut path = path.clone();
+            path.0.push(AdfPathSegment::Element(index));
+            if index < length {
+                elements.push(self.migrate(value, element_type_hash, &mut path, state)?);
+            } else {
+                state.dropped.push(path);
+            }
+        }
+        while elements.len() < length {
+            let Some(value) = self.default_value(element_type_hash) else {
+                return Err(self.migrate_error(
+                    element_type_hash,
+                    path,
+                    AdfReflectionErrorKind::MissingType(element_type_hash),
+                ));
+            };
+            elements.push(value);
+        }
+
+        Ok(elements)
+    }
+
+    fn migrate_error(
+        &self,
+        type_hash: u32,
+        path: &AdfValuePath,
+        kind: AdfReflectionErrorKind,
+    ) -> AdfReflectionError {
+        let mut error = AdfReflectionError::new(self.type_name(type_hash), None, kind);
+        error.path = path.clone();
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{built_in_types, AdfFile, AdfMemberValue, AdfReflectedScalar, AdfTypeInfo};
+
+    fn built_in<T: AdfTypeInfo>() -> &'static AdfType {
+        built_in_types()
+            .iter()
+            .find(|x| x.type_hash == T::HASH)
+            .unwrap()
+    }
+
+    fn context(types: &[&AdfType]) -> AdfReflectionContext {
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: types.iter().map(|&x| x.clone()).collect(),
+            ..Default::default()
+        });
+        context
+    }
+
+    fn enumeration(type_info: &AdfType, value: i32) -> AdfReflectedValue {
+        AdfReflectedValue(
+            type_info.type_hash,
+            AdfReflectedPrimitive::Enumeration(AdfReflectedScalar::I32(value)),
+        )
+    }
+
+    #[test]
+    fn enumerations_are_migrated_by_name() {
+        let old = AdfType::enumeration("Colour", [("Red", 0), ("Green", 1)]);
+        let new = AdfType::enumeration("Colour", [("Green", 0), ("Red", 1), ("Blue", 2)]);
+        let context = context(&[&old, &new]);
+
+        let migrate = |value| {
+            context
+                .migrate_value(&enumeration(&old, value), new.type_hash)
+                .unwrap()
+                .value
+        };
+        assert_eq!(migrate(0), enumeration(&new, 1));
+        assert_eq!(migrate(1), enumeration(&new, 0));
+        // Values which weren't declared keep their number
+        assert_eq!(migrate(7), enumeration(&new, 7));
+    }
+
+    #[test]
+    fn shared_values_stay_shared() {
+        let structure = |element: &AdfType| {
+            let array = AdfType::array(element);
+            let pointer = AdfType::pointer(element);
+            let type_info = AdfType::structure("Holder")
+                .member("First", &array)
+                .member("Second", &array)
+                .member("Third", &pointer)
+                .member("Fourth", &pointer)
+                .build();
+            (type_info, array, pointer)
+        };
+        let (old, old_array, old_pointer) = structure(built_in::<u32>());
+        let (new, new_array, new_pointer) = structure(built_in::<u16>());
+        let context = context(&[
+            built_in::<u32>(),
+            built_in::<u16>(),
+            &old,
+            &old_array,
+            &old_pointer,
+            &new,
+            &new_array,
+            &new_pointer,
+        ]);
+
+        let scalar = |value| {
+            AdfReflectedValue(
+                <u32 as AdfTypeInfo>::HASH,
+                AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(value)),
+            )
+        };
+        let array = Arc::new(vec![scalar(1), scalar(2)]);
+        let target = Arc::new(scalar(3));
+        let value = AdfReflectedValue(
+            old.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                AdfReflectedValue(
+                    old_array.type_hash,
+                    AdfReflectedPrimitive::Array(array.clone()),
+                ),
+                AdfReflectedValue(old_array.type_hash, AdfReflectedPrimitive::Array(array)),
+                AdfReflectedValue(
+                    old_pointer.type_hash,
+                    AdfReflectedPrimitive::Pointer(Some(target.clone())),
+                ),
+                AdfReflectedValue(
+                    old_pointer.type_hash,
+                    AdfReflectedPrimitive::Pointer(Some(target)),
+                ),
+            ]),
+        );
+
+        let migrated = context.migrate_value(&value, new.type_hash).unwrap().value;
+        let AdfReflectedPrimitive::Structure(members) = &migrated.1 else {
+            panic!("expected a structure, found {:?}", migrated.1);
+        };
+        let shared = |a: &AdfReflectedValue, b: &AdfReflectedValue| match (&a.1, &b.1) {
+            (AdfReflectedPrimitive::Array(a), AdfReflectedPrimitive::Array(b)) => Arc::ptr_eq(a, b),
+            (AdfReflectedPrimitive::Pointer(Some(a)), AdfReflectedPrimitive::Pointer(Some(b))) => {
+                Arc::ptr_eq(a, b)
+            }
+            _ => false,
+        };
+        assert!(shared(&members[0], &members[1]));
+        assert!(shared(&members[2], &members[3]));
+        assert_eq!(
+            members[2].1,
+            AdfReflectedPrimitive::Pointer(Some(Arc::new(AdfReflectedValue(
+                <u16 as AdfTypeInfo>::HASH,
+                AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U16(3)),
+            ))))
+        );
+    }
+
+    fn scalar<T: AdfTypeInfo>(scalar: AdfReflectedScalar) -> AdfReflectedValue {
+        AdfReflectedValue(T::HASH, AdfReflectedPrimitive::Scalar(scalar))
+    }
+
+    #[test]
+    fn members_are_matched_by_name() {
+        let old = AdfType::structure("Item")
+            .member("Count", built_in::<u32>())
+            .member("Legacy", built_in::<u16>())
+            .build();
+        let new = AdfType::structure("Item")
+            .member("Count", built_in::<u16>())
+            .member("Weight", built_in::<u32>())
+            .default_value(AdfMemberValue::InlineValue(7))
+            .build();
+        let context = context(&[built_in::<u32>(), built_in::<u16>(), &old, &new]);
+
+        let value = AdfReflectedValue(
+            old.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                scalar::<u32>(AdfReflectedScalar::U32(3)),
+                scalar::<u16>(AdfReflectedScalar::U16(5)),
+            ]),
+        );
+        let migration = context.migrate_value(&value, new.type_hash).unwrap();
+        assert_eq!(
+            migration.value,
+            AdfReflectedValue(
+                new.type_hash,
+                AdfReflectedPrimitive::Structure(vec![
+                    scalar::<u16>(AdfReflectedScalar::U16(3)),
+                    // Members the old type lacks take their defaults
+                    scalar::<u32>(AdfReflectedScalar::U32(7)),
+                ]),
+            )
+        );
+        assert_eq!(migration.dropped, vec!["Legacy".parse().unwrap()]);
+    }
+
+    #[test]
+    fn inline_arrays_are_cut_or_padded() {
+        let old = AdfType::inline_array(built_in::<u32>(), 3);
+        let shorter = AdfType::inline_array(built_in::<u32>(), 2);
+        let longer = AdfType::inline_array(built_in::<u32>(), 4);
+        let context = context(&[built_in::<u32>(), &old, &shorter, &longer]);
+
+        let elements = |type_info: &AdfType, values: &[u32]| {
+            AdfReflectedValue(
+                type_info.type_hash,
+                AdfReflectedPrimitive::InlineArray(
+                    values
+                        .iter()
+                        .map(|&x| scalar::<u32>(AdfReflectedScalar::U32(x)))
+                        .collect(),
+                ),
+            )
+        };
+        let value = elements(&old, &[1, 2, 3]);
+
+        let migration = context.migrate_value(&value, shorter.type_hash).unwrap();
+        assert_eq!(migration.value, elements(&shorter, &[1, 2]));
+        assert_eq!(migration.dropped, vec!["[2]".parse().unwrap()]);
+
+        let migration = context.migrate_value(&value, longer.type_hash).unwrap();
+        assert_eq!(migration.value, elements(&longer, &[1, 2, 3, 0]));
+        assert!(migration.dropped.is_empty());
+    }
+}
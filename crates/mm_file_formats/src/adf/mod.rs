@@ -1,14 +1,41 @@
 pub mod binary;
 pub use binary::*;
 
+pub mod builder;
+pub use builder::*;
+
 pub mod derive;
 pub use derive::*;
 
+pub mod diff;
+pub use diff::*;
+
+pub mod merge;
+pub use merge::*;
+
+pub mod migrate;
+pub use migrate::*;
+
+pub mod overlay;
+pub use overlay::*;
+
+pub mod path;
+pub use path::*;
+
 pub mod reflection;
 pub use reflection::*;
 
+pub mod serialize;
+pub use serialize::*;
+
 pub mod types;
 pub use types::*;
 
+pub mod validate;
+pub use validate::*;
+
+pub mod visitor;
+pub use visitor::*;
+
 pub mod xml;
 pub use xml::*;
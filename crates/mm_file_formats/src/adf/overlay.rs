@@ -0,0 +1,500 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    type_name, AdfFile, AdfPathSegment, AdfPrimitive, AdfReflectedPrimitive, AdfReflectedValue,
+    AdfReflectionContext, AdfReflectionError, AdfReflectionErrorKind, AdfValuePath, AdfXmlType,
+    AdfXmlValue,
+};
+
+// Partial edits of a base file, applied in order. Paths start with the name of the instance
+// they edit, like `Profiles.AIFleeProfile[1].VulnerableAt`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AdfXmlOverlay {
+    #[serde(rename = "@base")]
+    pub base: String,
+    // Only needed for types which the base file doesn't use
+    #[serde(rename = "type", default)]
+    pub types: Vec<AdfXmlType>,
+    #[serde(rename = "$value", default)]
+    pub edits: Vec<AdfXmlEdit>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdfXmlEdit {
+    // Replaces the value at the path
+    Set(AdfXmlEditValue),
+    // Inserts an element into the array at the path, at the end unless given an index
+    Insert(AdfXmlEditValue),
+    // Removes the array element at the path
+    Remove(AdfXmlEditValue),
+}
+
+// Like `AdfXmlValue`, but the type may be omitted to use that of the value being replaced
+#[derive(Debug, Deserialize, Serialize, Default)]
+pub struct AdfXmlEditValue {
+    #[serde(rename = "@path")]
+    pub path: String,
+    #[serde(rename = "@index", skip_serializing_if = "Option::is_none", default)]
+    pub index: Option<usize>,
+    #[serde(rename = "@type", skip_serializing_if = "Option::is_none", default)]
+    pub type_name: Option<String>,
+    #[serde(rename = "@string", skip_serializing_if = "Option::is_none", default)]
+    pub string: Option<String>,
+    #[serde(rename = "member", default)]
+    pub members: Vec<AdfXmlValue>,
+    #[serde(rename = "value", default)]
+    pub values: Vec<AdfXmlValue>,
+    #[serde(rename = "$text", skip_serializing_if = "String::is_empty", default)]
+    pub value: String,
+}
+
+impl AdfXmlOverlay {
+    // The base is left untouched; edited instances are rewritten in their original position
+    pub fn apply(
+        &self,
+        base: &AdfFile,
+        context: &AdfReflectionContext,
+    ) -> Result<AdfFile, AdfReflectionError> {
+        let mut result = base.clone();
+        let mut values: Vec<(usize, AdfReflectedValue)> = vec![];
+        let mut names = HashMap::new();
+        for instance in &base.instances {
+            collect_type_names(instance.type_hash, context, &mut names, &mut HashSet::new());
+        }
+        let mut types: HashMap<&str, u32> = names
+            .iter()
+            .map(|(&type_hash, name)| (name.as_str(), type_hash))
+            .collect();
+        types.extend(
+            self.types
+                .iter()
+                .map(|x| (x.type_name.as_str(), x.type_hash)),
+        );
+
+        for edit in &self.edits {
+            let edit_value = edit.value();
+            let error = |kind| AdfReflectionError::new("", None, kind);
+            let path: AdfValuePath = edit_value
+                .path
+                .parse()
+                .map_err(|parse_error| error(AdfReflectionErrorKind::InvalidPath(parse_error)))?;
+            let Some(AdfPathSegment::Member(name)) = path.0.first() else {
+                return Err(error(AdfReflectionErrorKind::MissingName));
+            };
+            let Some(index) = base
+                .instances
+                .iter()
+                .position(|x| x.name.as_ref() == name.as_str())
+            else {
+                return Err(error(AdfReflectionErrorKind::MissingInstance(name.clone())));
+            };
+
+            let position = if let Some(position) = values.iter().position(|x| x.0 == index) {
+                position
+            } else {
+                values.push((index, context.read_instance(&base.instances[index])?));
+                values.len() - 1
+            };
+            edit.apply(&mut values[position].1, &path, context, &names, &types)?;
+        }
+
+        for (index, value) in values {
+            context.write_instance_value(&value, Arc::make_mut(&mut result.instances[index]))?;
+        }
+        Ok(result)
+    }
+}
+
+impl AdfXmlEdit {
+    fn value(&self) -> &AdfXmlEditValue {
+        match self {
+            AdfXmlEdit::Set(value) | AdfXmlEdit::Insert(value) | AdfXmlEdit::Remove(value) => value,
+        }
+    }
+
+    // The path starts with the instance's name, which `root` is the value of
+    fn apply(
+        &self,
+        root: &mut AdfReflectedValue,
+        path: &AdfValuePath,
+        context: &AdfReflectionContext,
+        names: &HashMap<u32, String>,
+        types: &HashMap<&str, u32>,
+    ) -> Result<(), AdfReflectionError> {
+        let get_mut = |root, segments: &[AdfPathSegment]| {
+            context
+                .get_mut(root, &AdfValuePath(segments[1..].to_vec()))
+                .map_err(|error| prefix(error, &segments[..1]))
+        };
+        match self {
+            AdfXmlEdit::Set(edit) => {
+                let value = get_mut(root, &path.0)?;
+                *value = edit
+                    .to_value(value.0, context, names, types)
+                    .map_err(|error| prefix(error, &path.0))?;
+            }
+            AdfXmlEdit::Insert(edit) => {
+                let value = get_mut(root, &path.0)?;
+                let type_hash = value.0;
+                let error = |kind| prefix(edit_error(type_hash, names, kind), &path.0);
+                let AdfReflectedPrimitive::Array(elements) = &mut value.1 else {
+                    return Err(error(AdfReflectionErrorKind::PrimitiveMismatch {
+                        expected: AdfPrimitive::Array,
+                        found: value.1.primitive(),
+                    }));
+                };
+                let Some(type_info) = context.get_type_by_hash(type_hash) else {
+                    return Err(error(AdfReflectionErrorKind::MissingType(type_hash)));
+                };
+                let length = elements.len();
+                let index = edit.index.unwrap_or(length);
+                if index > length {
+                    return Err(error(AdfReflectionErrorKind::ElementIndex {
+                        index,
+                        length,
+                    }));
+                }
+                let element = edit
+                    .to_value(type_info.element_type_hash, context, names, types)
+                    .map_err(|error| prefix(error.element(index), &path.0))?;
+                Arc::make_mut(elements).insert(index, element);
+            }
+            AdfXmlEdit::Remove(_) => {
+                let (index, parent) = match path.0.split_last() {
+                    Some((AdfPathSegment::Element(index), parent)) if !parent.is_empty() => {
+                        (index, parent)
+                    }
+                    // Only array elements can be removed, not members or whole instances
+                    _ => {
+                        let segment = path.0.last().cloned().unwrap_or(AdfPathSegment::AnyElement);
+                        let mut error = AdfReflectionError::new(
+                            "",
+                            None,
+                            AdfReflectionErrorKind::InvalidSegment(segment),
+                        );
+                        error.path = path.clone();
+                        return Err(error);
+                    }
+                };
+                let value = get_mut(root, parent)?;
+                let type_hash = value.0;
+                let error = |kind| prefix(edit_error(type_hash, names, kind), parent);
+                let AdfReflectedPrimitive::Array(elements) = &mut value.1 else {
+                    return Err(error(AdfReflectionErrorKind::PrimitiveMismatch {
+                        expected: AdfPrimitive::Array,
+                        found: value.1.primitive(),
+                    }));
+                };
+                let length = elements.len();
+                if *index >= length {
+                    return Err(error(AdfReflectionErrorKind::ElementIndex {
+                        index: *index,
+                        length,
+                    }));
+                }
+                Arc::make_mut(elements).remove(*index);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AdfXmlEditValue {
+    fn to_value(
+        &self,
+        type_hash: u32,
+        context: &AdfReflectionContext,
+        names: &HashMap<u32, String>,
+        types: &HashMap<&str, u32>,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
+        let type_name = match &self.type_name {
+            Some(type_name) => type_name.clone(),
+            None => names.get(&type_hash).cloned().ok_or_else(|| {
+                edit_error(
+                    type_hash,
+                    names,
+                    AdfReflectionErrorKind::MissingType(type_hash),
+                )
+            })?,
+        };
+        AdfXmlValue {
+            name: None,
+            type_name,
+            string: self.string.clone(),
+            members: self.members.clone(),
+            values: self.values.clone(),
+            value: self.value.clone(),
+        }
+        .to_value(types, context)
+    }
+}
+
+fn edit_error(
+    type_hash: u32,
+    names: &HashMap<u32, String>,
+    kind: AdfReflectionErrorKind,
+) -> AdfReflectionError {
+    let type_name = names
+        .get(&type_hash)
+        .cloned()
+        .unwrap_or_else(|| format!("{type_hash:#010x}"));
+    AdfReflectionError::new(type_name, None, kind)
+}
+
+// Names every type reachable from the given one, including those no value currently uses
+fn collect_type_names(
+    type_hash: u32,
+    context: &AdfReflectionContext,
+    names: &mut HashMap<u32, String>,
+    visited: &mut HashSet<u32>,
+) {
+    if !visited.insert(type_hash) {
+        return;
+    }
+    let Some(type_info) = context.get_type_by_hash(type_hash) else {
+        return;
+    };
+    if let Some(name) = type_name(type_hash, context) {
+        names.insert(type_hash, name);
+    }
+    if type_info.element_type_hash != 0 {
+        collect_type_names(type_info.element_type_hash, context, names, visited);
+    }
+    for member in type_info.members.iter() {
+        collect_type_names(member.type_hash, context, names, visited);
+    }
+}
+
+fn prefix(mut error: AdfReflectionError, segments: &[AdfPathSegment]) -> AdfReflectionError {
+    for segment in segments.iter().rev() {
+        error.path.push_front(segment.clone());
+    }
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{
+        built_in_types, AdfInstance, AdfReflectedScalar, AdfType, AdfTypeInfo, AdfXmlEditValue,
+    };
+
+    fn uint32() -> &'static AdfType {
+        built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap()
+    }
+
+    fn context(file: &AdfFile) -> AdfReflectionContext {
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: built_in_types().to_vec(),
+            ..Default::default()
+        });
+        context.load_types_from_file(file);
+        context
+    }
+
+    fn file() -> (AdfFile, AdfReflectionContext) {
+        let settings = AdfType::structure("Settings")
+            .member("Value", uint32())
+            .build();
+        let file = AdfFile {
+            instances: ["First", "Second", "Second", "Third"]
+                .iter()
+                .map(|name| Arc::new(AdfInstance::from_type(name, &settings)))
+                .collect(),
+            types: vec![settings],
+            ..Default::default()
+        };
+        let context = context(&file);
+        (file, context)
+    }
+
+    // A single instance named `List`, holding an array of 1, 2 and 3 in `Values`
+    fn list() -> (AdfFile, AdfReflectionContext) {
+        let array = AdfType::array(uint32());
+        let list = AdfType::structure("List").member("Values", &array).build();
+        let mut file = AdfFile {
+            types: vec![list.clone(), array],
+            ..Default::default()
+        };
+        let context = context(&file);
+
+        let mut instance = AdfInstance::from_type("List", &list);
+        let elements = [1, 2, 3].map(|x| {
+            AdfReflectedValue(
+                uint32().type_hash,
+                AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(x)),
+            )
+        });
+        let value = AdfReflectedValue(
+            list.type_hash,
+            AdfReflectedPrimitive::Structure(vec![AdfReflectedValue(
+                file.types[1].type_hash,
+                AdfReflectedPrimitive::Array(Arc::new(elements.to_vec())),
+            )]),
+        );
+        context.write_instance_value(&value, &mut instance).unwrap();
+        file.instances.push(Arc::new(instance));
+        (file, context)
+    }
+
+    fn list_values(file: &AdfFile, context: &AdfReflectionContext) -> Vec<u32> {
+        let value = context.read_instance(&file.instances[0]).unwrap();
+        let value = context.get(&value, &"Values".parse().unwrap()).unwrap();
+        let AdfReflectedPrimitive::Array(elements) = &value.1 else {
+            panic!("expected an array, found {:?}", value.1);
+        };
+        elements
+            .iter()
+            .map(|x| match x.1 {
+                AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(x)) => x,
+                ref x => panic!("expected a u32, found {x:?}"),
+            })
+            .collect()
+    }
+
+    fn overlay(edits: Vec<AdfXmlEdit>) -> AdfXmlOverlay {
+        AdfXmlOverlay {
+            base: String::new(),
+            types: vec![],
+            edits,
+        }
+    }
+
+    fn insert(path: &str, index: Option<usize>, value: &str) -> AdfXmlEdit {
+        AdfXmlEdit::Insert(AdfXmlEditValue {
+            path: path.into(),
+            index,
+            value: value.into(),
+            ..Default::default()
+        })
+    }
+
+    fn remove(path: &str) -> AdfXmlEdit {
+        AdfXmlEdit::Remove(AdfXmlEditValue {
+            path: path.into(),
+            ..Default::default()
+        })
+    }
+
+    fn set(path: &str, value: &str) -> AdfXmlEdit {
+        AdfXmlEdit::Set(AdfXmlEditValue {
+            path: path.into(),
+            value: value.into(),
+            ..Default::default()
+        })
+    }
+
+    fn value(instance: &AdfInstance) -> u32 {
+        u32::from_le_bytes(instance.buffer[0..4].try_into().unwrap())
+    }
+
+    // Instances sharing a name with a later one are edited in place, leaving the rest alone
+    #[test]
+    fn edited_instances_keep_their_position() {
+        let (base, context) = file();
+        let overlay = overlay(vec![set("Second.Value", "5"), set("Third.Value", "7")]);
+        let result = overlay.apply(&base, &context).unwrap();
+
+        let names: Vec<&str> = result.instances.iter().map(|x| x.name.as_ref()).collect();
+        assert_eq!(names, ["First", "Second", "Second", "Third"]);
+        let values: Vec<u32> = result.instances.iter().map(|x| value(x)).collect();
+        assert_eq!(values, [0, 5, 0, 7]);
+        assert!(Arc::ptr_eq(&result.instances[0], &base.instances[0]));
+        assert!(Arc::ptr_eq(&result.instances[2], &base.instances[2]));
+    }
+
+    #[test]
+    fn elements_are_inserted_and_removed() {
+        let (base, context) = list();
+        let overlay = overlay(vec![
+            // Elements go at the end unless given an index
+            insert("List.Values", None, "4"),
+            insert("List.Values", Some(0), "0"),
+            remove("List.Values[2]"),
+        ]);
+        let result = overlay.apply(&base, &context).unwrap();
+        assert_eq!(list_values(&result, &context), [0, 1, 3, 4]);
+        assert_eq!(list_values(&base, &context), [1, 2, 3]);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_rejected() {
+        let (base, context) = list();
+        let apply = |edit| overlay(vec![edit]).apply(&base, &context).unwrap_err();
+
+        // Inserting at the length appends, but not past it
+        let result = overlay(vec![insert("List.Values", Some(3), "4")])
+            .apply(&base, &context)
+            .unwrap();
+        assert_eq!(list_values(&result, &context), [1, 2, 3, 4]);
+        let error = apply(insert("List.Values", Some(4), "4"));
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::ElementIndex {
+                index: 4,
+                length: 3
+            }
+        ));
+        assert_eq!(error.path, "List.Values".parse().unwrap());
+
+        let error = apply(remove("List.Values[3]"));
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::ElementIndex {
+                index: 3,
+                length: 3
+            }
+        ));
+        assert_eq!(error.path, "List.Values".parse().unwrap());
+
+        // Members aren't elements, so can't be removed
+        let error = apply(remove("List.Values"));
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::InvalidSegment(AdfPathSegment::Member(_))
+        ));
+    }
+
+    // Types come before the edits, which quick-xml collects in document order
+    #[test]
+    fn overlays_are_read_from_xml() {
+        let xml = format!(
+            r#"<overlay base="list.bin">
+                <type name="Count">{}</type>
+                <insert path="List.Values" type="Count">4</insert>
+                <insert path="List.Values" index="0">0</insert>
+                <remove path="List.Values[2]"/>
+                <set path="List.Values[0]">9</set>
+            </overlay>"#,
+            uint32().type_hash
+        );
+        let mut deserializer = quick_xml::de::Deserializer::from_str(&xml);
+        let overlay = AdfXmlOverlay::deserialize(&mut deserializer).unwrap();
+        assert_eq!(overlay.base, "list.bin");
+        assert_eq!(overlay.types.len(), 1);
+        assert_eq!(overlay.types[0].type_name, "Count");
+        assert!(matches!(
+            overlay.edits.as_slice(),
+            [
+                AdfXmlEdit::Insert(AdfXmlEditValue { index: None, .. }),
+                AdfXmlEdit::Insert(AdfXmlEditValue { index: Some(0), .. }),
+                AdfXmlEdit::Remove(_),
+                AdfXmlEdit::Set(_),
+            ]
+        ));
+
+        let (base, context) = list();
+        let result = overlay.apply(&base, &context).unwrap();
+        assert_eq!(list_values(&result, &context), [9, 1, 3, 4]);
+    }
+}
@@ -0,0 +1,681 @@
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use super::{
+    AdfFile, AdfPrimitive, AdfReflectedPrimitive, AdfReflectedScalar, AdfReflectedValue,
+    AdfReflectionContext, AdfReflectionError, AdfReflectionErrorKind,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AdfPathSegment {
+    Member(String),
+    Element(usize),
+    // Wildcards, written as `*` and `[*]`, which only queries accept
+    AnyMember,
+    AnyElement,
+}
+
+impl std::fmt::Display for AdfPathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdfPathSegment::Member(name) => write!(f, "{name}"),
+            AdfPathSegment::Element(element) => write!(f, "[{element}]"),
+            AdfPathSegment::AnyMember => write!(f, "*"),
+            AdfPathSegment::AnyElement => write!(f, "[*]"),
+        }
+    }
+}
+
+// Location of a value within an instance, written as `Sheet[3].CellIndex[17]`; pointers are
+// followed without a segment of their own
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AdfValuePath(pub Vec<AdfPathSegment>);
+
+impl AdfValuePath {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[inline]
+    pub fn push_front(&mut self, segment: AdfPathSegment) {
+        self.0.insert(0, segment);
+    }
+}
+
+impl std::fmt::Display for AdfValuePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, segment) in self.0.iter().enumerate() {
+            match segment {
+                AdfPathSegment::Member(_) | AdfPathSegment::AnyMember if index != 0 => {
+                    write!(f, ".{segment}")?;
+                }
+                _ => write!(f, "{segment}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AdfPathParseError {
+    #[error("expected a member name at {0}")]
+    MissingName(usize),
+    #[error("unclosed element index at {0}")]
+    UnclosedIndex(usize),
+    #[error("invalid element index {0:?}")]
+    InvalidIndex(String),
+    #[error("unexpected {0:?} at {1}")]
+    Unexpected(char, usize),
+}
+
+impl std::str::FromStr for AdfValuePath {
+    type Err = AdfPathParseError;
+
+    // The inverse of `Display`; an empty path refers to the value itself
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut position = 0;
+        while let Some(next) = path[position..].chars().next() {
+            match next {
+                '[' => {
+                    let Some(end) = path[position..].find(']') else {
+                        return Err(AdfPathParseError::UnclosedIndex(position));
+                    };
+                    let index = &path[position + 1..position + end];
+                    segments.push(if index == "*" {
+                        AdfPathSegment::AnyElement
+                    } else {
+                        match index.parse() {
+                            Ok(index) => AdfPathSegment::Element(index),
+                            Err(_) => return Err(AdfPathParseError::InvalidIndex(index.into())),
+                        }
+                    });
+                    position += end + 1;
+                }
+                '.' if !segments.is_empty() => {
+                    position += 1;
+                    let (segment, end) = parse_member(path, position)?;
+                    segments.push(segment);
+                    position = end;
+                }
+                _ if segments.is_empty() => {
+                    let (segment, end) = parse_member(path, position)?;
+                    segments.push(segment);
+                    position = end;
+                }
+                _ => return Err(AdfPathParseError::Unexpected(next, position)),
+            }
+        }
+        Ok(Self(segments))
+    }
+}
+
+fn parse_member(path: &str, start: usize) -> Result<(AdfPathSegment, usize), AdfPathParseError> {
+    let length = path[start..]
+        .find(['.', '[', ']'])
+        .unwrap_or(path.len() - start);
+    let name = &path[start..start + length];
+    let segment = match name {
+        "" => return Err(AdfPathParseError::MissingName(start)),
+        "*" => AdfPathSegment::AnyMember,
+        name => AdfPathSegment::Member(name.into()),
+    };
+    Ok((segment, start + length))
+}
+
+// Paths are relative to the value they're used on, so an instance's name isn't part of them;
+// `get_in_file` and `query_in_file` take paths which start with one
+impl AdfReflectionContext {
+    pub fn get<'a>(
+        &self,
+        value: &'a AdfReflectedValue,
+        path: &AdfValuePath,
+    ) -> Result<&'a AdfReflectedValue, AdfReflectionError> {
+        let mut value = value;
+        for (index, segment) in path.0.iter().enumerate() {
+            value = self
+                .child(value, segment)
+                .map_err(|kind| self.path_error(value.0, path, index, kind))?;
+        }
+        Ok(value)
+    }
+
+    // Shared values are copied before being modified, so other references to them are unaffected
+    pub fn get_mut<'a>(
+        &self,
+        value: &'a mut AdfReflectedValue,
+        path: &AdfValuePath,
+    ) -> Result<&'a mut AdfReflectedValue, AdfReflectionError> {
+        let mut value = value;
+        for (index, segment) in path.0.iter().enumerate() {
+            let type_hash = value.0;
+            value = self
+                .child_mut(value, segment)
+                .map_err(|kind| self.path_error(type_hash, path, index, kind))?;
+        }
+        Ok(value)
+    }
+
+    // The scalar is converted to the type of the value, as long as it fits
+    pub fn set(
+        &self,
+        value: &mut AdfReflectedValue,
+        path: &AdfValuePath,
+        scalar: impl Into<AdfReflectedScalar>,
+    ) -> Result<(), AdfReflectionError> {
+        let value = self.get_mut(value, path)?;
+        let type_hash = value.0;
+        let error = |kind| self.path_error(type_hash, path, path.0.len(), kind);
+
+        let value = target_mut(value).map_err(error)?;
+        let Some(type_info) = self.get_type_by_hash(value.0) else {
+            return Err(error(AdfReflectionErrorKind::MissingType(value.0)));
+        };
+        match &mut value.1 {
+            AdfReflectedPrimitive::Scalar(current)
+            | AdfReflectedPrimitive::Bitfield(current)
+            | AdfReflectedPrimitive::Enumeration(current)
+            | AdfReflectedPrimitive::StringHash(current) => {
+                *current = scalar.into().coerce(type_info).map_err(error)?;
+                Ok(())
+            }
+            _ => Err(error(AdfReflectionErrorKind::PrimitiveMismatch {
+                expected: type_info.primitive.clone(),
+                found: AdfPrimitive::Scalar,
+            })),
+        }
+    }
+
+    // Returns every value matching the path along with its own path, without wildcards. Values
+    // which can't match, like members of null pointers, are skipped rather than failing
+    pub fn query<'a>(
+        &self,
+        value: &'a AdfReflectedValue,
+        path: &AdfValuePath,
+    ) -> impl Iterator<Item = (AdfValuePath, &'a AdfReflectedValue)> {
+        let mut matches = vec![];
+        self.collect_matches(value, &path.0, AdfValuePath::default(), &mut matches);
+        matches.into_iter()
+    }
+
+    // The first segment names the instance, as in overlays and diffs, so `Vehicle.Gears[2]` is
+    // a member of the instance called `Vehicle`. The first instance with the name is used
+    pub fn get_in_file(
+        &self,
+        file: &AdfFile,
+        path: &AdfValuePath,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
+        let error = |kind| AdfReflectionError::new("", None, kind);
+        let Some((AdfPathSegment::Member(name), rest)) = path.0.split_first() else {
+            return Err(error(AdfReflectionErrorKind::MissingName));
+        };
+        let Some(instance) = file.instances.iter().find(|x| x.name.as_ref() == name) else {
+            return Err(error(AdfReflectionErrorKind::MissingInstance(name.clone())));
+        };
+        let value = self.read_instance(instance)?;
+        self.get(&value, &AdfValuePath(rest.to_vec()))
+            .cloned()
+            .map_err(|error| error.member(name.as_str()))
+    }
+
+    // As `query`, with the first segment naming the instance, so `*.Name` matches the member of
+    // every instance. Only the instances whose name matches are read
+    pub fn query_in_file(
+        &self,
+        file: &AdfFile,
+        path: &AdfValuePath,
+    ) -> Result<Vec<(AdfValuePath, AdfReflectedValue)>, AdfReflectionError> {
+        let Some((first, rest)) = path.0.split_first() else {
+            return Ok(vec![]);
+        };
+        let rest = AdfValuePath(rest.to_vec());
+        let mut matches = vec![];
+        for instance in &file.instances {
+            match first {
+                AdfPathSegment::Member(name) if name.as_str() == instance.name.as_ref() => {}
+                AdfPathSegment::AnyMember => {}
+                _ => continue,
+            }
+            let value = self.read_instance(instance)?;
+            for (mut path, value) in self.query(&value, &rest) {
+                path.push_front(AdfPathSegment::Member(instance.name.to_string()));
+                matches.push((path, value.clone()));
+            }
+        }
+        Ok(matches)
+    }
+
+    fn collect_matches<'a>(
+        &self,
+        value: &'a AdfReflectedValue,
+        segments: &[AdfPathSegment],
+        path: AdfValuePath,
+        matches: &mut Vec<(AdfValuePath, &'a AdfReflectedValue)>,
+    ) {
+        let Some((segment, rest)) = segments.split_first() else {
+            matches.push((path, value));
+            return;
+        };
+        let Ok(target) = target(value) else {
+            return;
+        };
+
+        let children: Vec<(AdfPathSegment, &AdfReflectedValue)> = match (segment, &target.1) {
+            (AdfPathSegment::AnyMember, AdfReflectedPrimitive::Structure(values)) => self
+                .get_type_by_hash(target.0)
+                .map(|type_info| {
+                    type_info
+                        .members
+                        .iter()
+                        .zip(values.iter())
+                        .map(|(member, value)| {
+                            (AdfPathSegment::Member(member.name.to_string()), value)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            (AdfPathSegment::AnyElement, AdfReflectedPrimitive::Array(values)) => values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (AdfPathSegment::Element(index), value))
+                .collect(),
+            (AdfPathSegment::AnyElement, AdfReflectedPrimitive::InlineArray(values)) => values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (AdfPathSegment::Element(index), value))
+                .collect(),
+            (segment, _) => self
+                .child(target, segment)
+                .map(|child| (segment.clone(), child))
+                .into_iter()
+                .collect(),
+        };
+
+        for (segment, child) in children {
+            let mut path = path.clone();
+            path.0.push(segment);
+            self.collect_matches(child, rest, path, matches);
+        }
+    }
+
+    fn child<'a>(
+        &self,
+        value: &'a AdfReflectedValue,
+        segment: &AdfPathSegment,
+    ) -> Result<&'a AdfReflectedValue, AdfReflectionErrorKind> {
+        let value = target(value)?;
+        match (segment, &value.1) {
+            (AdfPathSegment::Member(name), AdfReflectedPrimitive::Structure(values)) => {
+                let index = self.member_index(value.0, name)?;
+                values
+                    .get(index)
+                    .ok_or_else(|| AdfReflectionErrorKind::UnknownMember(name.clone()))
+            }
+            (AdfPathSegment::Element(index), AdfReflectedPrimitive::Array(values)) => {
+                element(values, *index)
+            }
+            (AdfPathSegment::Element(index), AdfReflectedPrimitive::InlineArray(values)) => {
+                element(values, *index)
+            }
+            (AdfPathSegment::AnyMember | AdfPathSegment::AnyElement, _) => {
+                Err(AdfReflectionErrorKind::Wildcard)
+            }
+            (segment, _) => Err(AdfReflectionErrorKind::InvalidSegment(segment.clone())),
+        }
+    }
+
+    fn child_mut<'a>(
+        &self,
+        value: &'a mut AdfReflectedValue,
+        segment: &AdfPathSegment,
+    ) -> Result<&'a mut AdfReflectedValue, AdfReflectionErrorKind> {
+        let value = target_mut(value)?;
+        let type_hash = value.0;
+        match (segment, &mut value.1) {
+            (AdfPathSegment::Member(name), AdfReflectedPrimitive::Structure(values)) => {
+                let index = self.member_index(type_hash, name)?;
+                values
+                    .get_mut(index)
+                    .ok_or_else(|| AdfReflectionErrorKind::UnknownMember(name.clone()))
+            }
+            (AdfPathSegment::Element(index), AdfReflectedPrimitive::Array(values)) => {
+                element_mut(Arc::make_mut(values).as_mut_slice(), *index)
+            }
+            (AdfPathSegment::Element(index), AdfReflectedPrimitive::InlineArray(values)) => {
+                element_mut(values, *index)
+            }
+            (AdfPathSegment::AnyMember | AdfPathSegment::AnyElement, _) => {
+                Err(AdfReflectionErrorKind::Wildcard)
+            }
+            (segment, _) => Err(AdfReflectionErrorKind::InvalidSegment(segment.clone())),
+        }
+    }
+
+    fn member_index(&self, type_hash: u32, name: &str) -> Result<usize, AdfReflectionErrorKind> {
+        self.get_type_by_hash(type_hash)
+            .ok_or(AdfReflectionErrorKind::MissingType(type_hash))?
+            .members
+            .iter()
+            .position(|member| member.name.as_str() == name)
+            .ok_or_else(|| AdfReflectionErrorKind::UnknownMember(name.into()))
+    }
+
+    fn path_error(
+        &self,
+        type_hash: u32,
+        path: &AdfValuePath,
+        length: usize,
+        kind: AdfReflectionErrorKind,
+    ) -> AdfReflectionError {
+        let type_name = self
+            .get_type_by_hash(type_hash)
+            .map_or_else(|| format!("{type_hash:#010x}"), |x| x.name.to_string());
+        let mut error = AdfReflectionError::new(type_name, None, kind);
+        error.path = AdfValuePath(path.0[..length].to_vec());
+        error
+    }
+}
+
+// Pointers, recursive and deferred values are followed to the value they refer to
+fn target(value: &AdfReflectedValue) -> Result<&AdfReflectedValue, AdfReflectionErrorKind> {
+    match &value.1 {
+        AdfReflectedPrimitive::Pointer(Some(value))
+        | AdfReflectedPrimitive::Recursive(Some(value))
+        | AdfReflectedPrimitive::Deferred(Some(value)) => target(value),
+        AdfReflectedPrimitive::Pointer(None)
+        | AdfReflectedPrimitive::Recursive(None)
+        | AdfReflectedPrimitive::Deferred(None) => Err(AdfReflectionErrorKind::MissingValue),
+        _ => Ok(value),
+    }
+}
+
+fn target_mut(
+    value: &mut AdfReflectedValue,
+) -> Result<&mut AdfReflectedValue, AdfReflectionErrorKind> {
+    if !matches!(
+        value.1,
+        AdfReflectedPrimitive::Pointer(_)
+            | AdfReflectedPrimitive::Recursive(_)
+            | AdfReflectedPrimitive::Deferred(_)
+    ) {
+        return Ok(value);
+    }
+    match &mut value.1 {
+        AdfReflectedPrimitive::Pointer(Some(value))
+        | AdfReflectedPrimitive::Recursive(Some(value))
+        | AdfReflectedPrimitive::Deferred(Some(value)) => target_mut(Arc::make_mut(value)),
+        _ => Err(AdfReflectionErrorKind::MissingValue),
+    }
+}
+
+fn element(
+    values: &[AdfReflectedValue],
+    index: usize,
+) -> Result<&AdfReflectedValue, AdfReflectionErrorKind> {
+    values
+        .get(index)
+        .ok_or(AdfReflectionErrorKind::ElementIndex {
+            index,
+            length: values.len(),
+        })
+}
+
+fn element_mut(
+    values: &mut [AdfReflectedValue],
+    index: usize,
+) -> Result<&mut AdfReflectedValue, AdfReflectionErrorKind> {
+    let length = values.len();
+    values
+        .get_mut(index)
+        .ok_or(AdfReflectionErrorKind::ElementIndex { index, length })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{built_in_types, AdfType, AdfTypeInfo};
+
+    fn scalar<T: AdfTypeInfo>() -> AdfType {
+        built_in_types()
+            .iter()
+            .find(|x| x.type_hash == T::HASH)
+            .unwrap()
+            .clone()
+    }
+
+    // Vehicle { Top: uint32, Gears: A[Gear { Ratio: float }] }
+    fn context() -> (AdfReflectionContext, AdfType, AdfType) {
+        let float = scalar::<f32>();
+        let uint32 = scalar::<u32>();
+        let gear = AdfType::structure("Gear").member("Ratio", &float).build();
+        let gears = AdfType::array(&gear);
+        let vehicle = AdfType::structure("Vehicle")
+            .member("Top", &uint32)
+            .member("Gears", &gears)
+            .build();
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: vec![float, uint32, gear.clone(), gears, vehicle.clone()],
+            ..Default::default()
+        });
+        (context, vehicle, gear)
+    }
+
+    fn vehicle(vehicle: &AdfType, gear: &AdfType, top: u32, ratios: &[f32]) -> AdfReflectedValue {
+        let scalar =
+            |type_hash, value| AdfReflectedValue(type_hash, AdfReflectedPrimitive::Scalar(value));
+        let gears = ratios
+            .iter()
+            .map(|&ratio| {
+                AdfReflectedValue(
+                    gear.type_hash,
+                    AdfReflectedPrimitive::Structure(vec![scalar(
+                        gear.members[0].type_hash,
+                        AdfReflectedScalar::F32(ratio),
+                    )]),
+                )
+            })
+            .collect();
+        AdfReflectedValue(
+            vehicle.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                scalar(vehicle.members[0].type_hash, AdfReflectedScalar::U32(top)),
+                AdfReflectedValue(
+                    vehicle.members[1].type_hash,
+                    AdfReflectedPrimitive::Array(Arc::new(gears)),
+                ),
+            ]),
+        )
+    }
+
+    fn path(path: &str) -> AdfValuePath {
+        path.parse().unwrap()
+    }
+
+    #[test]
+    fn invalid_paths_are_rejected() {
+        let parse = |path: &str| path.parse::<AdfValuePath>();
+        assert_eq!(parse("Gears[1"), Err(AdfPathParseError::UnclosedIndex(5)));
+        assert_eq!(
+            parse("Gears[x]"),
+            Err(AdfPathParseError::InvalidIndex("x".into()))
+        );
+        assert_eq!(
+            parse("Gears[-1]"),
+            Err(AdfPathParseError::InvalidIndex("-1".into()))
+        );
+        assert_eq!(
+            parse("Gears..Ratio"),
+            Err(AdfPathParseError::MissingName(6))
+        );
+        assert_eq!(parse(".Gears"), Err(AdfPathParseError::MissingName(0)));
+        assert_eq!(parse("Gears."), Err(AdfPathParseError::MissingName(6)));
+        assert_eq!(parse("Gears]"), Err(AdfPathParseError::Unexpected(']', 5)));
+    }
+
+    #[test]
+    fn paths_round_trip_through_text() {
+        for text in [
+            "",
+            "Top",
+            "Gears[2].Ratio",
+            "[0][1].Name",
+            "*.Gears[*].Ratio",
+        ] {
+            assert_eq!(path(text).to_string(), text);
+        }
+        assert_eq!(
+            path("Gears[*].*").0,
+            vec![
+                AdfPathSegment::Member("Gears".into()),
+                AdfPathSegment::AnyElement,
+                AdfPathSegment::AnyMember,
+            ]
+        );
+    }
+
+    #[test]
+    fn set_coerces_to_the_value_type() {
+        let (context, vehicle_type, gear) = context();
+        let mut value = vehicle(&vehicle_type, &gear, 100, &[3.0, 2.0]);
+
+        context.set(&mut value, &path("Top"), 120u8).unwrap();
+        context
+            .set(&mut value, &path("Gears[1].Ratio"), 1i32)
+            .unwrap();
+        assert_eq!(value, vehicle(&vehicle_type, &gear, 120, &[3.0, 1.0]));
+
+        let error = context.set(&mut value, &path("Top"), -1i32).unwrap_err();
+        assert!(matches!(error.kind, AdfReflectionErrorKind::ScalarRange(_)));
+        let error = context.set(&mut value, &path("Gears"), 1u32).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::PrimitiveMismatch { .. }
+        ));
+        let error = context
+            .set(&mut value, &path("Gears[2].Ratio"), 1u32)
+            .unwrap_err();
+        assert_eq!(error.path, path("Gears"));
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::ElementIndex {
+                index: 2,
+                length: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn shared_values_are_copied_when_set() {
+        let (context, vehicle_type, gear) = context();
+        let value = vehicle(&vehicle_type, &gear, 100, &[3.0]);
+        let mut copy = value.clone();
+        context
+            .set(&mut copy, &path("Gears[0].Ratio"), 2.5f32)
+            .unwrap();
+        assert_eq!(value, vehicle(&vehicle_type, &gear, 100, &[3.0]));
+        assert_eq!(copy, vehicle(&vehicle_type, &gear, 100, &[2.5]));
+    }
+
+    #[test]
+    fn queries_expand_wildcards() {
+        let (context, vehicle_type, gear) = context();
+        let value = vehicle(&vehicle_type, &gear, 100, &[3.0, 2.0]);
+
+        let matches: Vec<(String, &AdfReflectedValue)> = context
+            .query(&value, &path("Gears[*].Ratio"))
+            .map(|(path, value)| (path.to_string(), value))
+            .collect();
+        let ratios = value_at(&context, &value, "Gears");
+        assert_eq!(
+            matches,
+            vec![
+                (
+                    "Gears[0].Ratio".to_owned(),
+                    value_at(&context, ratios, "[0].Ratio")
+                ),
+                (
+                    "Gears[1].Ratio".to_owned(),
+                    value_at(&context, ratios, "[1].Ratio")
+                ),
+            ]
+        );
+        assert_eq!(context.query(&value, &path("*")).count(), 2);
+        assert_eq!(context.query(&value, &path("Missing[*]")).count(), 0);
+
+        let error = context.get(&value, &path("Gears[*]")).unwrap_err();
+        assert!(matches!(error.kind, AdfReflectionErrorKind::Wildcard));
+    }
+
+    fn value_at<'a>(
+        context: &AdfReflectionContext,
+        value: &'a AdfReflectedValue,
+        text: &str,
+    ) -> &'a AdfReflectedValue {
+        context.get(value, &path(text)).unwrap()
+    }
+
+    #[test]
+    fn file_paths_start_with_the_instance_name() {
+        let (context, vehicle_type, gear) = context();
+        let mut file = AdfFile::default();
+        let first = vehicle(&vehicle_type, &gear, 100, &[3.0, 2.0]);
+        let second = vehicle(&vehicle_type, &gear, 150, &[2.5]);
+        context.write_instance(&"Car", &first, &mut file).unwrap();
+        context
+            .write_instance(&"Truck", &second, &mut file)
+            .unwrap();
+
+        // Relative to the value, the instance's name is just another member
+        let error = context
+            .get(&first, &path("Car.Gears[1].Ratio"))
+            .unwrap_err();
+        assert!(matches!(error.kind, AdfReflectionErrorKind::UnknownMember(name) if name == "Car"));
+
+        assert_eq!(
+            context
+                .get_in_file(&file, &path("Car.Gears[1].Ratio"))
+                .unwrap(),
+            *value_at(&context, &first, "Gears[1].Ratio")
+        );
+        let error = context
+            .get_in_file(&file, &path("Truck.Gears[1].Ratio"))
+            .unwrap_err();
+        assert_eq!(error.path, path("Truck.Gears"));
+        let error = context.get_in_file(&file, &path("Bus.Top")).unwrap_err();
+        assert!(
+            matches!(error.kind, AdfReflectionErrorKind::MissingInstance(name) if name == "Bus")
+        );
+        let error = context.get_in_file(&file, &path("*.Top")).unwrap_err();
+        assert!(matches!(error.kind, AdfReflectionErrorKind::MissingName));
+
+        let matches: Vec<(String, AdfReflectedValue)> = context
+            .query_in_file(&file, &path("*.Top"))
+            .unwrap()
+            .into_iter()
+            .map(|(path, value)| (path.to_string(), value))
+            .collect();
+        assert_eq!(
+            matches,
+            vec![
+                (
+                    "Car.Top".to_owned(),
+                    value_at(&context, &first, "Top").clone()
+                ),
+                (
+                    "Truck.Top".to_owned(),
+                    value_at(&context, &second, "Top").clone()
+                ),
+            ]
+        );
+        assert_eq!(
+            context
+                .query_in_file(&file, &path("Truck.Gears[*]"))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -1,20 +1,246 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use aligned_vec::{AVec, RuntimeAlign};
+use binrw::Endian;
+use thiserror::Error;
 
-use crate::common::NullString;
+use crate::common::{read_pod, write_pod, NullString};
 
 use super::{
-    AdfFile, AdfInstance, AdfPrimitive, AdfScalarType, AdfType, AdfTypeFlags, AdfTypeInfo,
-    AdfTypeLib, BUILT_IN_TYPE_LIBRARY, TYPE_LIBRARIES,
+    AdfFile, AdfInstance, AdfInstanceReadWriteError, AdfMember, AdfMemberValue, AdfPathParseError,
+    AdfPathSegment, AdfPrimitive, AdfRead, AdfScalarType, AdfType, AdfTypeFlags, AdfTypeInfo,
+    AdfTypeLib, AdfValuePath, AdfWrite, BUILT_IN_TYPE_LIBRARY, TYPE_LIBRARIES,
 };
 
+#[derive(Error, Debug)]
+pub enum AdfReflectionErrorKind {
+    #[error("failed to find type {0:#010x}")]
+    MissingType(u32),
+    #[error("failed to find type {0}")]
+    MissingTypeName(String),
+    #[error("{size} bytes are outside of the buffer")]
+    OutOfBounds { size: usize },
+    #[error("not aligned to {0} bytes")]
+    Misaligned(usize),
+    #[error("expected a {expected:?} value, found {found:?}")]
+    PrimitiveMismatch {
+        expected: AdfPrimitive,
+        found: AdfPrimitive,
+    },
+    #[error("expected a value of type {expected:#010x}, found {found:#010x}")]
+    TypeMismatch { expected: u32, found: u32 },
+    #[error("expected {expected} members, found {found}")]
+    MemberCount { expected: usize, found: usize },
+    #[error("expected {expected} elements, found {found}")]
+    ArrayLength { expected: usize, found: usize },
+    #[error("invalid {scalar_type:?} scalar of {size} bytes")]
+    InvalidScalar {
+        scalar_type: AdfScalarType,
+        size: u32,
+    },
+    #[error("{0:?} doesn't match the scalar type")]
+    ScalarMismatch(AdfReflectedScalar),
+    #[error("failed to parse {0:?}")]
+    InvalidText(String),
+    #[error("expected a value")]
+    MissingValue,
+    #[error("member {0} is missing and has no default")]
+    MissingMember(String),
+    #[error("instance has no name")]
+    MissingName,
+    #[error("instance {0} was not found")]
+    MissingInstance(String),
+    #[error("failed to create instance")]
+    InstanceCreation,
+    #[error("nested deeper than {0} values")]
+    DepthLimit(usize),
+    #[error("followed more than {0} references")]
+    ReferenceLimit(usize),
+    #[error("refers back to a value containing it")]
+    CyclicReference,
+    #[error("has no member {0}")]
+    UnknownMember(String),
+    #[error("element {index} is outside of {length} elements")]
+    ElementIndex { index: usize, length: usize },
+    #[error("can't be indexed by {0}")]
+    InvalidSegment(AdfPathSegment),
+    #[error("wildcards can only be used in queries")]
+    Wildcard,
+    #[error("{0:?} is out of range")]
+    ScalarRange(AdfReflectedScalar),
+    #[error("invalid path: {0}")]
+    InvalidPath(AdfPathParseError),
+    #[error("typed value: {0}")]
+    Typed(AdfInstanceReadWriteError),
+    #[error("typed value of {size} bytes aligned to {alignment} doesn't match the type")]
+    TypedLayout { size: u64, alignment: u64 },
+    #[error("{0} values aren't supported")]
+    UnsupportedValue(&'static str),
+    #[error("{0}")]
+    Custom(String),
+}
+
+// Values read from XML have no offset
+#[derive(Error, Debug)]
+pub struct AdfReflectionError {
+    pub path: AdfValuePath,
+    pub type_name: String,
+    pub offset: Option<usize>,
+    pub kind: AdfReflectionErrorKind,
+}
+
+impl AdfReflectionError {
+    pub fn new(
+        type_name: impl Into<String>,
+        offset: Option<usize>,
+        kind: AdfReflectionErrorKind,
+    ) -> Self {
+        Self {
+            path: AdfValuePath::default(),
+            type_name: type_name.into(),
+            offset,
+            kind,
+        }
+    }
+
+    // Paths are built up as errors are passed back to the parent values
+    #[must_use]
+    pub fn member(mut self, name: impl Into<String>) -> Self {
+        self.path.push_front(AdfPathSegment::Member(name.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn element(mut self, index: usize) -> Self {
+        self.path.push_front(AdfPathSegment::Element(index));
+        self
+    }
+}
+
+impl std::fmt::Display for AdfReflectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{} ", self.path)?;
+        }
+        if !self.type_name.is_empty() {
+            write!(f, "({}", self.type_name)?;
+            if let Some(offset) = self.offset {
+                write!(f, " at {offset:#x}")?;
+            }
+            write!(f, "): ")?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+// Traversals of values fail once they pass these, so corrupt or crafted files can't overflow
+// the stack or run forever
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdfReflectionLimits {
+    // Values nested within each other, including through references
+    pub max_depth: usize,
+    // Pointers, arrays, recursive and deferred values followed while traversing an instance
+    pub max_references: usize,
+}
+
+impl Default for AdfReflectionLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_references: 1 << 24,
+        }
+    }
+}
+
+pub(crate) struct AdfReflectionBudget {
+    limits: AdfReflectionLimits,
+    depth: usize,
+    references: usize,
+}
+
+impl AdfReflectionBudget {
+    pub(crate) fn new(limits: AdfReflectionLimits) -> Self {
+        Self {
+            limits,
+            depth: 0,
+            references: 0,
+        }
+    }
+
+    // Every successful `enter` must be paired with a `leave`
+    pub(crate) fn enter(&mut self) -> Result<(), AdfReflectionErrorKind> {
+        if self.depth >= self.limits.max_depth {
+            return Err(AdfReflectionErrorKind::DepthLimit(self.limits.max_depth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    pub(crate) fn follow(&mut self) -> Result<(), AdfReflectionErrorKind> {
+        if self.references >= self.limits.max_references {
+            return Err(AdfReflectionErrorKind::ReferenceLimit(
+                self.limits.max_references,
+            ));
+        }
+        self.references += 1;
+        Ok(())
+    }
+}
+
+// Values read from the same offset share one `Arc`, like `AdfReaderReferences` for typed reads
+struct AdfReflectedReadState {
+    endian: Endian,
+    budget: AdfReflectionBudget,
+    values: HashMap<(usize, u32), Arc<AdfReflectedValue>>,
+    arrays: HashMap<(usize, u32), Arc<Vec<AdfReflectedValue>>>,
+    strings: HashMap<usize, Arc<String>>,
+    // References still being read, which a cycle would lead back to
+    pending: HashSet<(usize, u32)>,
+}
+
+impl AdfReflectedReadState {
+    fn new(endian: Endian, limits: AdfReflectionLimits) -> Self {
+        Self {
+            endian,
+            budget: AdfReflectionBudget::new(limits),
+            values: HashMap::new(),
+            arrays: HashMap::new(),
+            strings: HashMap::new(),
+            pending: HashSet::new(),
+        }
+    }
+}
+
+// Offsets of the values already written, keyed by the address of their `Arc`
+struct AdfReflectedWriteState {
+    endian: Endian,
+    budget: AdfReflectionBudget,
+    offsets: HashMap<usize, u64>,
+}
+
+impl AdfReflectedWriteState {
+    fn new(endian: Endian, limits: AdfReflectionLimits) -> Self {
+        Self {
+            endian,
+            budget: AdfReflectionBudget::new(limits),
+            offsets: HashMap::new(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct AdfReflectionContext {
     types: HashMap<u32, AdfType>,
+    limits: AdfReflectionLimits,
 }
 
-// TODO: we need to handle endian swapping + possible stack overflow; it's OK for now
 impl AdfReflectionContext {
     pub fn from_extension(extension: impl AsRef<str>) -> binrw::BinResult<AdfReflectionContext> {
         let mut result = Self::default();
@@ -72,6 +298,14 @@ impl AdfReflectionContext {
             .extend(file.types.iter().map(|x| (x.type_hash, x.clone())));
     }
 
+    pub fn limits(&self) -> AdfReflectionLimits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: AdfReflectionLimits) {
+        self.limits = limits;
+    }
+
     pub fn get_type_by_info<T: AdfTypeInfo>(&self) -> Option<&AdfType> {
         self.types.get(&T::HASH)
     }
@@ -88,12 +322,18 @@ impl AdfReflectionContext {
             .find(|x| x.name.as_str() == type_name)
     }
 
-    pub fn read_instance(&self, instance: &AdfInstance) -> Result<AdfReflectedValue, ()> {
-        let Some(buffer) = instance.buffer.try_lock().ok() else {
-            todo!("failed to lock buffer");
-        };
-
-        self.read_value_by_hash(instance.type_hash, buffer.as_slice(), 0, 0)
+    pub fn read_instance(
+        &self,
+        instance: &AdfInstance,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
+        self.read_value_by_hash(
+            instance.type_hash,
+            &instance.buffer,
+            0,
+            0,
+            &mut AdfReflectedReadState::new(instance.endian, self.limits),
+        )
+        .map_err(|error| error.member(instance.name.as_ref()))
     }
 
     pub fn write_instance(
@@ -101,21 +341,195 @@ impl AdfReflectionContext {
         name: &impl AsRef<str>,
         value: &AdfReflectedValue,
         adf: &mut AdfFile,
-    ) {
+    ) -> Result<(), AdfReflectionError> {
+        let name = name.as_ref();
+        let error = |kind| AdfReflectionError::new(format!("{:#010x}", value.0), None, kind);
         let Some(type_info) = self.get_type_by_hash(value.0) else {
-            todo!("failed to get type info: {}", value.0);
+            return Err(error(AdfReflectionErrorKind::MissingType(value.0)).member(name));
         };
 
         let Some(instance) = adf.new_instance_from_type(name, type_info) else {
-            todo!("failed to create instance: {}", name.as_ref());
+            return Err(error(AdfReflectionErrorKind::InstanceCreation).member(name));
         };
+        self.write_instance_value(value, instance)
+    }
 
-        let Ok(mut buffer) = instance.buffer.try_lock() else {
-            todo!("failed to lock buffer");
+    // Replaces the contents of the instance, which keeps its name, position and endianness
+    pub fn write_instance_value(
+        &self,
+        value: &AdfReflectedValue,
+        instance: &mut AdfInstance,
+    ) -> Result<(), AdfReflectionError> {
+        let error = |kind| AdfReflectionError::new(format!("{:#010x}", value.0), None, kind);
+        let Some(type_info) = self.get_type_by_hash(value.0) else {
+            return Err(
+                error(AdfReflectionErrorKind::MissingType(value.0)).member(instance.name.as_ref())
+            );
         };
 
-        self.write_value_by_hash(&value.1, value.0, &mut buffer, 0, 0)
-            .expect("failed to write value");
+        let mut result = AdfInstance::from_type("", type_info);
+        result.name = instance.name.clone();
+        result.endian = instance.endian;
+        self.write_value_by_hash(
+            &value.1,
+            value.0,
+            result.buffer.to_mut(),
+            0,
+            0,
+            &mut AdfReflectedWriteState::new(result.endian, self.limits),
+        )
+        .map_err(|error| error.member(instance.name.as_ref()))?;
+        *instance = result;
+        Ok(())
+    }
+
+    // Typed values go through an instance buffer, so their layout must match the loaded type
+    pub fn from_typed<T: AdfWrite + AdfTypeInfo>(
+        &self,
+        value: &T,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
+        let mut instance = AdfInstance::from_type("", self.typed_type::<T>()?);
+        instance
+            .write(value)
+            .map_err(|error| typed_error::<T>(AdfReflectionErrorKind::Typed(error)))?;
+        self.read_value_by_hash(
+            T::HASH,
+            &instance.buffer,
+            0,
+            0,
+            &mut AdfReflectedReadState::new(instance.endian, self.limits),
+        )
+    }
+
+    pub fn to_typed<T: AdfRead + AdfTypeInfo>(
+        &self,
+        value: &AdfReflectedValue,
+    ) -> Result<T, AdfReflectionError> {
+        if value.0 != T::HASH {
+            return Err(typed_error::<T>(AdfReflectionErrorKind::TypeMismatch {
+                expected: T::HASH,
+                found: value.0,
+            }));
+        }
+        let mut instance = AdfInstance::from_type("", self.typed_type::<T>()?);
+        let endian = instance.endian;
+        self.write_value_by_hash(
+            &value.1,
+            value.0,
+            instance.buffer.to_mut(),
+            0,
+            0,
+            &mut AdfReflectedWriteState::new(endian, self.limits),
+        )?;
+        instance
+            .read()
+            .map_err(|error| typed_error::<T>(AdfReflectionErrorKind::Typed(error)))
+    }
+
+    fn typed_type<T: AdfTypeInfo>(&self) -> Result<&AdfType, AdfReflectionError> {
+        let Some(type_info) = self.get_type_by_info::<T>() else {
+            return Err(typed_error::<T>(AdfReflectionErrorKind::MissingType(
+                T::HASH,
+            )));
+        };
+        if u64::from(type_info.size) != T::SIZE || u64::from(type_info.alignment) != T::ALIGN {
+            return Err(typed_error::<T>(AdfReflectionErrorKind::TypedLayout {
+                size: T::SIZE,
+                alignment: T::ALIGN,
+            }));
+        }
+        Ok(type_info)
+    }
+
+    // Members take their defaults where the type has them, and anything else is zeroed or empty
+    pub fn default_value(&self, type_hash: u32) -> Option<AdfReflectedValue> {
+        self.default_value_in(type_hash, &mut AdfReflectionBudget::new(self.limits))
+    }
+
+    // Inline defaults hold the raw bits of the value, in little endian
+    pub fn member_default_value(&self, member: &AdfMember) -> Option<AdfReflectedValue> {
+        self.member_default_value_in(member, &mut AdfReflectionBudget::new(self.limits))
+    }
+
+    // Pointers and recursive values are null, as types may contain themselves through them
+    fn default_value_in(
+        &self,
+        type_hash: u32,
+        budget: &mut AdfReflectionBudget,
+    ) -> Option<AdfReflectedValue> {
+        let type_info = self.get_type_by_hash(type_hash)?;
+        budget.enter().ok()?;
+
+        let zero = [0u8; 8];
+        let zero = zero.get(..type_info.size as usize).unwrap_or_default();
+        let primitive = match type_info.primitive {
+            AdfPrimitive::Scalar => AdfReflectedPrimitive::Scalar(
+                Self::read_scalar(type_info, zero, Endian::Little).ok()?,
+            ),
+            AdfPrimitive::Structure => AdfReflectedPrimitive::Structure(
+                type_info
+                    .members
+                    .iter()
+                    .map(|member| self.member_default_value_in(member, budget))
+                    .collect::<Option<_>>()?,
+            ),
+            AdfPrimitive::Pointer => AdfReflectedPrimitive::Pointer(None),
+            AdfPrimitive::Array => AdfReflectedPrimitive::Array(Arc::default()),
+            AdfPrimitive::InlineArray => AdfReflectedPrimitive::InlineArray(
+                (0..type_info.element_length)
+                    .map(|_| self.default_value_in(type_info.element_type_hash, budget))
+                    .collect::<Option<_>>()?,
+            ),
+            AdfPrimitive::String => AdfReflectedPrimitive::String(Arc::default()),
+            AdfPrimitive::Bitfield => AdfReflectedPrimitive::Bitfield(
+                Self::read_bitfield(type_info, zero, 0, Endian::Little).ok()?,
+            ),
+            AdfPrimitive::Enumeration => AdfReflectedPrimitive::Enumeration(
+                Self::read_scalar(type_info, zero, Endian::Little).ok()?,
+            ),
+            AdfPrimitive::StringHash => AdfReflectedPrimitive::StringHash(
+                Self::read_scalar(type_info, zero, Endian::Little).ok()?,
+            ),
+            AdfPrimitive::Recursive => AdfReflectedPrimitive::Recursive(None),
+            AdfPrimitive::Deferred => AdfReflectedPrimitive::Deferred(None),
+        };
+        budget.leave();
+        Some(AdfReflectedValue(type_hash, primitive))
+    }
+
+    fn member_default_value_in(
+        &self,
+        member: &AdfMember,
+        budget: &mut AdfReflectionBudget,
+    ) -> Option<AdfReflectedValue> {
+        let type_info = self.get_type_by_hash(member.type_hash)?;
+
+        match &member.value {
+            AdfMemberValue::InlineValue(value) if type_info.size <= 8 => {
+                let bytes = value.to_le_bytes();
+                let bytes = &bytes[..type_info.size as usize];
+                let primitive = match type_info.primitive {
+                    AdfPrimitive::Scalar => AdfReflectedPrimitive::Scalar(
+                        Self::read_scalar(type_info, bytes, Endian::Little).ok()?,
+                    ),
+                    AdfPrimitive::Bitfield => AdfReflectedPrimitive::Bitfield(
+                        Self::read_bitfield(type_info, bytes, 0, Endian::Little).ok()?,
+                    ),
+                    AdfPrimitive::Enumeration => AdfReflectedPrimitive::Enumeration(
+                        Self::read_scalar(type_info, bytes, Endian::Little).ok()?,
+                    ),
+                    AdfPrimitive::StringHash => AdfReflectedPrimitive::StringHash(
+                        Self::read_scalar(type_info, bytes, Endian::Little).ok()?,
+                    ),
+                    _ => return self.default_value_in(member.type_hash, budget),
+                };
+                Some(AdfReflectedValue(member.type_hash, primitive))
+            }
+            AdfMemberValue::InstanceValue(instance) if instance.type_hash == member.type_hash => {
+                self.read_instance(instance).ok()
+            }
+            _ => self.default_value_in(member.type_hash, budget),
+        }
     }
 
     fn read_value_by_hash(
@@ -124,12 +538,17 @@ impl AdfReflectionContext {
         buffer: &[u8],
         offset: usize,
         shift: usize,
-    ) -> Result<AdfReflectedValue, ()> {
+        state: &mut AdfReflectedReadState,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
         let Some(type_info) = self.get_type_by_hash(type_hash) else {
-            todo!("failed to get type: {}", type_hash);
+            return Err(AdfReflectionError::new(
+                format!("{type_hash:#010x}"),
+                Some(offset),
+                AdfReflectionErrorKind::MissingType(type_hash),
+            ));
         };
 
-        self.read_value_by_info(type_info, buffer, offset, shift)
+        self.read_value_by_info(type_info, buffer, offset, shift, state)
     }
 
     fn write_value_by_hash(
@@ -139,12 +558,17 @@ impl AdfReflectionContext {
         buffer: &mut AVec<u8, RuntimeAlign>,
         offset: usize,
         shift: usize,
-    ) -> Result<(), ()> {
+        state: &mut AdfReflectedWriteState,
+    ) -> Result<(), AdfReflectionError> {
         let Some(type_info) = self.get_type_by_hash(type_hash) else {
-            return Err(());
+            return Err(AdfReflectionError::new(
+                format!("{type_hash:#010x}"),
+                Some(offset),
+                AdfReflectionErrorKind::MissingType(type_hash),
+            ));
         };
 
-        self.write_value_by_info(primitive, type_info, buffer, offset, shift)
+        self.write_value_by_info(primitive, type_info, buffer, offset, shift, state)
     }
 
     fn read_value_by_info(
@@ -153,120 +577,201 @@ impl AdfReflectionContext {
         buffer: &[u8],
         offset: usize,
         shift: usize,
-    ) -> Result<AdfReflectedValue, ()> {
+        state: &mut AdfReflectedReadState,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
         let type_size = type_info.size as usize;
         let alignment = type_info.alignment as usize;
-        let type_hash = type_info.type_hash;
+        let error = |kind| AdfReflectionError::new(type_info.name.as_str(), Some(offset), kind);
+
         // Validate the buffer contains the requested slice
         if buffer.len() < type_size || buffer.len() - type_size < offset {
-            todo!("slice outside of buffer");
+            return Err(error(AdfReflectionErrorKind::OutOfBounds {
+                size: type_size,
+            }));
         };
 
-        let slice = &buffer[offset..offset + type_size];
-        let pointer = slice.as_ptr() as usize;
-        // Validate the slice is correctly aligned
-        if (pointer % alignment) != 0 {
-            todo!("alignment incorrect");
+        // Validate the type covers the layout of its primitive, embedded types may be corrupt
+        let fixed_size = Self::fixed_size(&type_info.primitive);
+        if type_size < fixed_size {
+            return Err(error(AdfReflectionErrorKind::OutOfBounds {
+                size: fixed_size,
+            }));
+        }
+
+        // Validate the slice is correctly aligned, shared buffers may start anywhere in memory
+        if (offset % alignment.max(1)) != 0 {
+            return Err(error(AdfReflectionErrorKind::Misaligned(alignment)));
         };
+        let slice = &buffer[offset..offset + type_size];
+        state.budget.enter().map_err(error)?;
 
-        Ok(match type_info.primitive {
-            AdfPrimitive::Scalar => AdfReflectedValue(
-                type_hash,
-                AdfReflectedPrimitive::Scalar(Self::read_scalar(type_info, slice)?),
-            ),
-            AdfPrimitive::Structure => {
-                let mut members = Vec::with_capacity(type_info.members.len());
-                for member in type_info.members.iter() {
-                    let member_offset = member.offsets.byte() as usize;
-                    let member_bit_offset = member.offsets.bit() as usize;
-                    members.push(self.read_value_by_hash(
-                        member.type_hash,
-                        buffer,
-                        offset + member_offset,
-                        member_bit_offset,
-                    )?);
-                }
-                AdfReflectedValue(type_hash, AdfReflectedPrimitive::Structure(members))
-            }
-            AdfPrimitive::Pointer => {
-                let Some(type_info) = self.get_type_by_hash(type_info.element_type_hash) else {
-                    todo!("failed to get type info: {}", type_info.element_type_hash);
-                };
-                // TODO: map of pointers, so we have one Arc<AdfReflectedValue> per read
-                let offset = *bytemuck::from_bytes::<u64>(slice) as usize;
-                AdfReflectedValue(
-                    type_hash,
-                    AdfReflectedPrimitive::Pointer(
-                        self.read_value_by_info(type_info, buffer, offset, 0)?
-                            .into(),
-                    ),
-                )
+        let primitive = match type_info.primitive {
+            AdfPrimitive::Scalar
+            | AdfPrimitive::Bitfield
+            | AdfPrimitive::Enumeration
+            | AdfPrimitive::StringHash => {
+                Self::read_scalar_primitive(type_info, slice, shift, state.endian).map_err(error)?
             }
-            AdfPrimitive::Array => {
-                let Some(type_info) = self.get_type_by_hash(type_info.element_type_hash) else {
-                    todo!("failed to get type info: {}", type_info.element_type_hash);
-                };
-                // TODO: map of pointers, so we have one Arc<Vec<AdfReflectedValue>> per read
-                let offset = *bytemuck::from_bytes::<u64>(&slice[0..8]) as usize;
-                let count = *bytemuck::from_bytes::<u64>(&slice[8..16]) as usize;
-                AdfReflectedValue(
-                    type_hash,
-                    AdfReflectedPrimitive::Array(
-                        self.read_array(type_info, buffer, offset, count)?.into(),
-                    ),
-                )
+            AdfPrimitive::Structure => AdfReflectedPrimitive::Structure(
+                self.read_members(type_info, buffer, offset, state)?,
+            ),
+            AdfPrimitive::Pointer | AdfPrimitive::Recursive | AdfPrimitive::Deferred => {
+                self.read_indirect(type_info, buffer, offset, state)?
             }
+            AdfPrimitive::Array => AdfReflectedPrimitive::Array(
+                self.read_shared_array(type_info, buffer, offset, state)?,
+            ),
             AdfPrimitive::InlineArray => {
                 let count = type_info.element_length as usize;
-                let Some(type_info) = self.get_type_by_hash(type_info.element_type_hash) else {
-                    todo!("failed to get type info: {}", type_info.element_type_hash);
-                };
-                AdfReflectedValue(
-                    type_hash,
-                    AdfReflectedPrimitive::InlineArray(
-                        self.read_array(type_info, buffer, offset, count)?.into(),
-                    ),
-                )
+                let element_type = self.element_type(type_info, offset)?;
+                AdfReflectedPrimitive::InlineArray(self.read_array(
+                    element_type,
+                    buffer,
+                    offset,
+                    count,
+                    state,
+                )?)
             }
             AdfPrimitive::String => {
-                // TODO: map of pointers, so we have one Arc<String> per read
-                let start = *bytemuck::from_bytes::<u64>(slice) as usize;
-                let mut end = start;
-                while end < buffer.len() && buffer[end] != 0 {
-                    end += 1;
-                }
-                let slice = &buffer[start..end];
-                AdfReflectedValue(
-                    type_hash,
-                    AdfReflectedPrimitive::String(
-                        String::from_utf8_lossy(slice).to_string().into(),
-                    ),
-                )
+                AdfReflectedPrimitive::String(Self::read_string(type_info, buffer, offset, state)?)
             }
-            AdfPrimitive::Recursive => {
-                todo!("Recursive is not implemented (no idea how this type works, sorry)")
+        };
+        state.budget.leave();
+        Ok(AdfReflectedValue(type_info.type_hash, primitive))
+    }
+
+    fn read_scalar_primitive(
+        type_info: &AdfType,
+        slice: &[u8],
+        shift: usize,
+        endian: Endian,
+    ) -> Result<AdfReflectedPrimitive, AdfReflectionErrorKind> {
+        Ok(match type_info.primitive {
+            AdfPrimitive::Bitfield => AdfReflectedPrimitive::Bitfield(Self::read_bitfield(
+                type_info, slice, shift, endian,
+            )?),
+            AdfPrimitive::Enumeration => {
+                AdfReflectedPrimitive::Enumeration(Self::read_scalar(type_info, slice, endian)?)
             }
-            AdfPrimitive::Bitfield => AdfReflectedValue(
-                type_hash,
-                AdfReflectedPrimitive::Bitfield(Self::read_bitfield(type_info, slice, shift)?),
-            ),
-            AdfPrimitive::Enumeration => AdfReflectedValue(
-                type_hash,
-                AdfReflectedPrimitive::Enumeration(Self::read_scalar(type_info, slice)?),
-            ),
+            // TODO: why is element_type_hash = 0x48c5294d? Doesn't matter for our use case, but still.
             AdfPrimitive::StringHash => {
-                // TODO: why is element_type_hash = 0x48c5294d? Doesn't matter for our use case, but still.
-                AdfReflectedValue(
-                    type_hash,
-                    AdfReflectedPrimitive::StringHash(Self::read_scalar(type_info, slice)?),
-                )
-            }
-            AdfPrimitive::Deferred => {
-                todo!("Deferred is not implemented (type_hash + offset)")
+                AdfReflectedPrimitive::StringHash(Self::read_scalar(type_info, slice, endian)?)
             }
+            _ => AdfReflectedPrimitive::Scalar(Self::read_scalar(type_info, slice, endian)?),
+        })
+    }
+
+    fn read_members(
+        &self,
+        type_info: &AdfType,
+        buffer: &[u8],
+        offset: usize,
+        state: &mut AdfReflectedReadState,
+    ) -> Result<Vec<AdfReflectedValue>, AdfReflectionError> {
+        let mut members = Vec::with_capacity(type_info.members.len());
+        for member in type_info.members.iter() {
+            let member_offset = member.offsets.byte() as usize;
+            let member_bit_offset = member.offsets.bit() as usize;
+            members.push(
+                self.read_value_by_hash(
+                    member.type_hash,
+                    buffer,
+                    offset + member_offset,
+                    member_bit_offset,
+                    state,
+                )
+                .map_err(|error| error.member(member.name.as_str()))?,
+            );
+        }
+        Ok(members)
+    }
+
+    // Pointers and recursive values are laid out alike, the latter being used by types which
+    // contain themselves. Deferred values store their target's type alongside its offset, and
+    // null values of any of them have no target
+    fn read_indirect(
+        &self,
+        type_info: &AdfType,
+        buffer: &[u8],
+        offset: usize,
+        state: &mut AdfReflectedReadState,
+    ) -> Result<AdfReflectedPrimitive, AdfReflectionError> {
+        let target = read_pod::<u64>(&buffer[offset..offset + 8], state.endian) as usize;
+        let target_hash = if type_info.primitive == AdfPrimitive::Deferred {
+            read_pod::<u32>(&buffer[offset + 8..offset + 12], state.endian)
+        } else {
+            type_info.element_type_hash
+        };
+
+        let value = if target == 0 || target_hash == 0 {
+            None
+        } else {
+            let Some(target_type) = self.get_type_by_hash(target_hash) else {
+                return Err(AdfReflectionError::new(
+                    type_info.name.as_str(),
+                    Some(offset),
+                    AdfReflectionErrorKind::MissingType(target_hash),
+                ));
+            };
+            Some(self.read_reference(target_type, buffer, target, state)?)
+        };
+        Ok(match type_info.primitive {
+            AdfPrimitive::Pointer => AdfReflectedPrimitive::Pointer(value),
+            AdfPrimitive::Recursive => AdfReflectedPrimitive::Recursive(value),
+            _ => AdfReflectedPrimitive::Deferred(value),
         })
     }
 
+    fn read_shared_array(
+        &self,
+        type_info: &AdfType,
+        buffer: &[u8],
+        offset: usize,
+        state: &mut AdfReflectedReadState,
+    ) -> Result<Arc<Vec<AdfReflectedValue>>, AdfReflectionError> {
+        let element_type = self.element_type(type_info, offset)?;
+        let start = read_pod::<u64>(&buffer[offset..offset + 8], state.endian) as usize;
+        let count = read_pod::<u64>(&buffer[offset + 8..offset + 16], state.endian) as usize;
+        state
+            .budget
+            .follow()
+            .map_err(|kind| AdfReflectionError::new(type_info.name.as_str(), Some(offset), kind))?;
+
+        // Arrays may share their start with a longer one, which can't be reused
+        let key = (start, element_type.type_hash);
+        if let Some(values) = state.arrays.get(&key) {
+            if values.len() == count {
+                return Ok(values.clone());
+            }
+        }
+        let values = Arc::new(self.read_array(element_type, buffer, start, count, state)?);
+        state.arrays.insert(key, values.clone());
+        Ok(values)
+    }
+
+    fn read_string(
+        type_info: &AdfType,
+        buffer: &[u8],
+        offset: usize,
+        state: &mut AdfReflectedReadState,
+    ) -> Result<Arc<String>, AdfReflectionError> {
+        let start = read_pod::<u64>(&buffer[offset..offset + 8], state.endian) as usize;
+        if let Some(string) = state.strings.get(&start) {
+            return Ok(string.clone());
+        }
+        let Some(string) = buffer.get(start..) else {
+            return Err(AdfReflectionError::new(
+                type_info.name.as_str(),
+                Some(start),
+                AdfReflectionErrorKind::OutOfBounds { size: 1 },
+            ));
+        };
+        let end = string.iter().position(|&x| x == 0).unwrap_or(string.len());
+        let string = Arc::new(String::from_utf8_lossy(&string[..end]).to_string());
+        state.strings.insert(start, string.clone());
+        Ok(string)
+    }
+
     fn write_value_by_info(
         &self,
         value: &AdfReflectedPrimitive,
@@ -274,157 +779,316 @@ impl AdfReflectionContext {
         buffer: &mut AVec<u8, RuntimeAlign>,
         offset: usize,
         shift: usize,
-    ) -> Result<(), ()> {
+        state: &mut AdfReflectedWriteState,
+    ) -> Result<(), AdfReflectionError> {
         let type_size = type_info.size as usize;
         let alignment = type_info.alignment as usize;
+        let error = |kind| AdfReflectionError::new(type_info.name.as_str(), Some(offset), kind);
+
         // Validate the buffer contains the requested slice
         if buffer.len() < type_size || buffer.len() - type_size < offset {
-            todo!("slice outside of buffer");
+            return Err(error(AdfReflectionErrorKind::OutOfBounds {
+                size: type_size,
+            }));
         };
 
-        let buffer_size = buffer.len();
+        // Validate the type covers the layout of its primitive
+        let fixed_size = Self::fixed_size(&type_info.primitive);
+        if type_size < fixed_size {
+            return Err(error(AdfReflectionErrorKind::OutOfBounds {
+                size: fixed_size,
+            }));
+        }
+
         let slice = &mut buffer[offset..offset + type_size];
         let pointer = slice.as_ptr() as usize;
         // Validate the slice is correctly aligned
-        if (pointer % alignment) != 0 {
-            todo!("incorrect alignment");
+        if (pointer % alignment.max(1)) != 0 {
+            return Err(error(AdfReflectionErrorKind::Misaligned(alignment)));
         };
 
         // Validate primitive type
-        macro_rules! validate_primitive {
-            ($p:expr) => {{
-                if type_info.primitive != $p {
-                    todo!("invalid primitive: {:?}", $p);
-                }
-            }};
-        }
-
-        #[inline(always)]
-        const fn align(value: usize, alignment: usize) -> usize {
-            let align = alignment - 1;
-            (value + align) & !align
+        if type_info.primitive != value.primitive() {
+            return Err(error(AdfReflectionErrorKind::PrimitiveMismatch {
+                expected: type_info.primitive.clone(),
+                found: value.primitive(),
+            }));
         }
+        state.budget.enter().map_err(error)?;
 
         match value {
-            AdfReflectedPrimitive::Scalar(scalar) => {
-                validate_primitive!(AdfPrimitive::Scalar);
-                Self::write_scalar(slice, scalar, type_info)?;
+            AdfReflectedPrimitive::Scalar(scalar)
+            | AdfReflectedPrimitive::Enumeration(scalar)
+            | AdfReflectedPrimitive::StringHash(scalar) => {
+                Self::write_scalar(slice, scalar, type_info, state.endian).map_err(error)?;
+            }
+            AdfReflectedPrimitive::Bitfield(scalar) => {
+                Self::write_bitfield(slice, scalar, type_info, shift, state.endian)
+                    .map_err(error)?;
             }
             AdfReflectedPrimitive::Structure(members) => {
-                validate_primitive!(AdfPrimitive::Structure);
-                if members.len() != type_info.members.len() {
-                    todo!("unexpected member count: {}", members.len());
-                };
-                for (value, member) in members.iter().zip(type_info.members.iter()) {
-                    if value.0 != member.type_hash {
-                        todo!("unexpected member type: {}", value.0);
-                    }
-                    let member_offset = member.offsets.byte() as usize;
-                    let member_bit_offset = member.offsets.bit() as usize;
-                    self.write_value_by_hash(
-                        &value.1,
-                        member.type_hash,
-                        buffer,
-                        offset + member_offset,
-                        member_bit_offset,
-                    )?;
-                }
+                self.write_members(members, type_info, buffer, offset, state)?;
             }
-            AdfReflectedPrimitive::Pointer(value) => {
-                validate_primitive!(AdfPrimitive::Pointer);
-                if type_info.element_type_hash != value.0 {
-                    todo!("unexpected pointer type: {}", value.0);
-                };
-                let Some(type_info) = self.get_type_by_hash(type_info.element_type_hash) else {
-                    todo!("failed to get type info: {}", type_info.element_type_hash);
-                };
-
-                let offset = align(buffer_size, type_info.alignment.max(16) as usize);
-                *bytemuck::from_bytes_mut::<u64>(&mut slice[0..8]) = offset as u64;
-
-                let slack = offset - buffer_size;
-                let size = slack + (type_info.size as usize);
-                buffer.resize(buffer_size + size, 0u8);
-
-                self.write_value_by_info(&value.1, type_info, buffer, offset, 0)?;
+            AdfReflectedPrimitive::Pointer(target)
+            | AdfReflectedPrimitive::Recursive(target)
+            | AdfReflectedPrimitive::Deferred(target) => {
+                self.write_indirect(target.as_ref(), type_info, buffer, offset, state)?;
             }
             AdfReflectedPrimitive::Array(values) => {
-                validate_primitive!(AdfPrimitive::Array);
-                let Some(type_info) = self.get_type_by_hash(type_info.element_type_hash) else {
-                    todo!("failed to get type info: {}", type_info.element_type_hash);
-                };
-
-                let offset = align(buffer_size, type_info.alignment.max(16) as usize);
-                *bytemuck::from_bytes_mut::<u64>(&mut slice[0..8]) = offset as u64;
-
-                let count = values.len();
-                *bytemuck::from_bytes_mut::<u64>(&mut slice[8..16]) = count as u64;
-
-                let slack = offset - buffer_size;
-                let size = slack + (type_info.size as usize) * count;
-                buffer.resize(buffer_size + size, 0u8);
-
-                self.write_array(values, type_info, buffer, offset, count)?;
+                self.write_shared_array(values, type_info, buffer, offset, state)?;
             }
             AdfReflectedPrimitive::InlineArray(values) => {
-                validate_primitive!(AdfPrimitive::InlineArray);
                 let count = type_info.element_length as usize;
                 if values.len() != count {
-                    todo!("unexpected array size: {}", values.len());
-                };
-                let Some(type_info) = self.get_type_by_hash(type_info.element_type_hash) else {
-                    todo!("failed to get type info: {}", type_info.element_type_hash);
+                    return Err(error(AdfReflectionErrorKind::ArrayLength {
+                        expected: count,
+                        found: values.len(),
+                    }));
                 };
-                self.write_array(values, type_info, buffer, offset, count)?;
+                let element_type = self.element_type(type_info, offset)?;
+                self.write_array(values, element_type, buffer, offset, state)?;
             }
             AdfReflectedPrimitive::String(string) => {
-                validate_primitive!(AdfPrimitive::String);
+                Self::write_string(string, buffer, offset, state);
+            }
+        };
 
-                let offset = buffer_size as u64;
-                *bytemuck::from_bytes_mut::<u64>(slice) = offset;
+        state.budget.leave();
+        Ok(())
+    }
 
-                let size = string.as_bytes().len() + 1;
-                buffer.resize(buffer_size + size, 0u8);
-                for (i, &char) in string.as_bytes().iter().enumerate() {
-                    buffer[buffer_size + i] = char;
-                }
-            }
-            AdfReflectedPrimitive::Bitfield(scalar) => {
-                validate_primitive!(AdfPrimitive::Bitfield);
-                Self::write_bitfield(slice, scalar, type_info, shift)?;
-            }
-            AdfReflectedPrimitive::Enumeration(scalar) => {
-                validate_primitive!(AdfPrimitive::Enumeration);
-                Self::write_scalar(slice, scalar, type_info)?;
-            }
-            AdfReflectedPrimitive::StringHash(scalar) => {
-                validate_primitive!(AdfPrimitive::StringHash);
-                Self::write_scalar(slice, scalar, type_info)?;
-            }
-            AdfReflectedPrimitive::Deferred(_) => {
-                validate_primitive!(AdfPrimitive::Deferred);
+    fn write_members(
+        &self,
+        members: &[AdfReflectedValue],
+        type_info: &AdfType,
+        buffer: &mut AVec<u8, RuntimeAlign>,
+        offset: usize,
+        state: &mut AdfReflectedWriteState,
+    ) -> Result<(), AdfReflectionError> {
+        if members.len() != type_info.members.len() {
+            return Err(AdfReflectionError::new(
+                type_info.name.as_str(),
+                Some(offset),
+                AdfReflectionErrorKind::MemberCount {
+                    expected: type_info.members.len(),
+                    found: members.len(),
+                },
+            ));
+        };
+        for (value, member) in members.iter().zip(type_info.members.iter()) {
+            let member_offset = member.offsets.byte() as usize;
+            let member_bit_offset = member.offsets.bit() as usize;
+            if value.0 != member.type_hash {
+                return Err(AdfReflectionError::new(
+                    type_info.name.as_str(),
+                    Some(offset + member_offset),
+                    AdfReflectionErrorKind::TypeMismatch {
+                        expected: member.type_hash,
+                        found: value.0,
+                    },
+                )
+                .member(member.name.as_str()));
             }
+            self.write_value_by_hash(
+                &value.1,
+                member.type_hash,
+                buffer,
+                offset + member_offset,
+                member_bit_offset,
+                state,
+            )
+            .map_err(|error| error.member(member.name.as_str()))?;
+        }
+        Ok(())
+    }
+
+    // Null values are written without a target. Pointers and recursive values must target
+    // their element type, while deferred values store the type of whatever they target
+    fn write_indirect(
+        &self,
+        target: Option<&Arc<AdfReflectedValue>>,
+        type_info: &AdfType,
+        buffer: &mut AVec<u8, RuntimeAlign>,
+        offset: usize,
+        state: &mut AdfReflectedWriteState,
+    ) -> Result<(), AdfReflectionError> {
+        let error = |kind| AdfReflectionError::new(type_info.name.as_str(), Some(offset), kind);
+        buffer[offset..offset + type_info.size as usize].fill(0);
+        let Some(value) = target else {
+            return Ok(());
         };
 
+        let deferred = type_info.primitive == AdfPrimitive::Deferred;
+        if !deferred && type_info.element_type_hash != value.0 {
+            return Err(error(AdfReflectionErrorKind::TypeMismatch {
+                expected: type_info.element_type_hash,
+                found: value.0,
+            }));
+        };
+        let Some(target_type) = self.get_type_by_hash(value.0) else {
+            return Err(error(AdfReflectionErrorKind::MissingType(value.0)));
+        };
+        let target = self.write_reference(value, target_type, buffer, state)?;
+        write_pod(&mut buffer[offset..offset + 8], target, state.endian);
+        if deferred {
+            write_pod(&mut buffer[offset + 8..offset + 12], value.0, state.endian);
+        }
         Ok(())
     }
 
+    fn write_shared_array(
+        &self,
+        values: &Arc<Vec<AdfReflectedValue>>,
+        type_info: &AdfType,
+        buffer: &mut AVec<u8, RuntimeAlign>,
+        offset: usize,
+        state: &mut AdfReflectedWriteState,
+    ) -> Result<(), AdfReflectionError> {
+        let element_type = self.element_type(type_info, offset)?;
+        state
+            .budget
+            .follow()
+            .map_err(|kind| AdfReflectionError::new(type_info.name.as_str(), Some(offset), kind))?;
+
+        let key = Arc::as_ptr(values) as usize;
+        let target = if let Some(&target) = state.offsets.get(&key) {
+            target
+        } else {
+            let target = align(buffer.len(), element_type.alignment.max(16) as usize);
+            buffer.resize(target + (element_type.size as usize) * values.len(), 0u8);
+            state.offsets.insert(key, target as u64);
+            self.write_array(values, element_type, buffer, target, state)?;
+            target as u64
+        };
+        write_pod(&mut buffer[offset..offset + 8], target, state.endian);
+        write_pod(
+            &mut buffer[offset + 8..offset + 16],
+            values.len() as u64,
+            state.endian,
+        );
+        Ok(())
+    }
+
+    fn write_string(
+        string: &Arc<String>,
+        buffer: &mut AVec<u8, RuntimeAlign>,
+        offset: usize,
+        state: &mut AdfReflectedWriteState,
+    ) {
+        let key = Arc::as_ptr(string) as usize;
+        let target = if let Some(&target) = state.offsets.get(&key) {
+            target
+        } else {
+            let target = buffer.len();
+            buffer.resize(target + string.len() + 1, 0u8);
+            buffer[target..target + string.len()].copy_from_slice(string.as_bytes());
+            state.offsets.insert(key, target as u64);
+            target as u64
+        };
+        write_pod(&mut buffer[offset..offset + 8], target, state.endian);
+    }
+
+    // Bytes read and written by primitives with a fixed layout: an offset, followed by the
+    // count of arrays or the target type of deferred values
+    fn fixed_size(primitive: &AdfPrimitive) -> usize {
+        match primitive {
+            AdfPrimitive::Pointer | AdfPrimitive::Recursive | AdfPrimitive::String => 8,
+            AdfPrimitive::Deferred => 12,
+            AdfPrimitive::Array => 16,
+            _ => 0,
+        }
+    }
+
+    fn element_type(
+        &self,
+        type_info: &AdfType,
+        offset: usize,
+    ) -> Result<&AdfType, AdfReflectionError> {
+        self.get_type_by_hash(type_info.element_type_hash)
+            .ok_or_else(|| {
+                AdfReflectionError::new(
+                    type_info.name.as_str(),
+                    Some(offset),
+                    AdfReflectionErrorKind::MissingType(type_info.element_type_hash),
+                )
+            })
+    }
+
+    fn read_reference(
+        &self,
+        type_info: &AdfType,
+        buffer: &[u8],
+        offset: usize,
+        state: &mut AdfReflectedReadState,
+    ) -> Result<Arc<AdfReflectedValue>, AdfReflectionError> {
+        let error = |kind| AdfReflectionError::new(type_info.name.as_str(), Some(offset), kind);
+        state.budget.follow().map_err(error)?;
+
+        let key = (offset, type_info.type_hash);
+        if let Some(value) = state.values.get(&key) {
+            return Ok(value.clone());
+        }
+        if !state.pending.insert(key) {
+            return Err(error(AdfReflectionErrorKind::CyclicReference));
+        }
+        let value = Arc::new(self.read_value_by_info(type_info, buffer, offset, 0, state)?);
+        state.pending.remove(&key);
+        state.values.insert(key, value.clone());
+        Ok(value)
+    }
+
+    // Returns the offset of the value, which is only written the first time it's referenced
+    fn write_reference(
+        &self,
+        value: &Arc<AdfReflectedValue>,
+        type_info: &AdfType,
+        buffer: &mut AVec<u8, RuntimeAlign>,
+        state: &mut AdfReflectedWriteState,
+    ) -> Result<u64, AdfReflectionError> {
+        state
+            .budget
+            .follow()
+            .map_err(|kind| AdfReflectionError::new(type_info.name.as_str(), None, kind))?;
+
+        let key = Arc::as_ptr(value) as usize;
+        if let Some(&offset) = state.offsets.get(&key) {
+            return Ok(offset);
+        }
+        let offset = align(buffer.len(), type_info.alignment.max(16) as usize);
+        buffer.resize(offset + type_info.size as usize, 0u8);
+        state.offsets.insert(key, offset as u64);
+        self.write_value_by_info(&value.1, type_info, buffer, offset, 0, state)?;
+        Ok(offset as u64)
+    }
+
     fn read_array(
         &self,
         type_info: &AdfType,
         buffer: &[u8],
         offset: usize,
         count: usize,
-    ) -> Result<Vec<AdfReflectedValue>, ()> {
+        state: &mut AdfReflectedReadState,
+    ) -> Result<Vec<AdfReflectedValue>, AdfReflectionError> {
         let element_size = type_info.size as usize;
+        // Validate the count before allocating for it, it may be garbage
+        let size = element_size.saturating_mul(count);
+        if !offset
+            .checked_add(size)
+            .is_some_and(|end| end <= buffer.len())
+        {
+            return Err(AdfReflectionError::new(
+                type_info.name.as_str(),
+                Some(offset),
+                AdfReflectionErrorKind::OutOfBounds { size },
+            ));
+        }
+
         let mut values = Vec::with_capacity(count);
         for i in 0..count {
-            values.push(self.read_value_by_info(
-                type_info,
-                buffer,
-                offset + (i * element_size),
-                0,
-            )?);
+            values.push(
+                self.read_value_by_info(type_info, buffer, offset + (i * element_size), 0, state)
+                    .map_err(|error| error.element(i))?,
+            );
         }
         Ok(values)
     }
@@ -435,45 +1099,51 @@ impl AdfReflectionContext {
         type_info: &AdfType,
         buffer: &mut AVec<u8, RuntimeAlign>,
         offset: usize,
-        count: usize,
-    ) -> Result<(), ()> {
-        if values.len() != count {
-            todo!("unexpected array length: {}", values.len());
-        }
-
+        state: &mut AdfReflectedWriteState,
+    ) -> Result<(), AdfReflectionError> {
         let element_size = type_info.size as usize;
         for (i, value) in values.iter().enumerate() {
+            let offset = offset + (i * element_size);
             if value.0 != type_info.type_hash {
-                todo!("unexpected type: {}", value.0);
+                return Err(AdfReflectionError::new(
+                    type_info.name.as_str(),
+                    Some(offset),
+                    AdfReflectionErrorKind::TypeMismatch {
+                        expected: type_info.type_hash,
+                        found: value.0,
+                    },
+                )
+                .element(i));
             }
 
-            self.write_value_by_info(&value.1, type_info, buffer, offset + (i * element_size), 0)?;
+            self.write_value_by_info(&value.1, type_info, buffer, offset, 0, state)
+                .map_err(|error| error.element(i))?;
         }
         Ok(())
     }
 
-    fn read_scalar(type_info: &AdfType, buffer: &[u8]) -> Result<AdfReflectedScalar, ()> {
-        use bytemuck::from_bytes as read;
-        match type_info.scalar_type {
-            AdfScalarType::Signed => match type_info.size {
-                1 => Ok(AdfReflectedScalar::I8(*read(buffer))),
-                2 => Ok(AdfReflectedScalar::I16(*read(buffer))),
-                4 => Ok(AdfReflectedScalar::I32(*read(buffer))),
-                8 => Ok(AdfReflectedScalar::I64(*read(buffer))),
-                size => todo!("invalid scalar size: {}", size),
-            },
-            AdfScalarType::Unsigned => match type_info.size {
-                1 => Ok(AdfReflectedScalar::U8(*read(buffer))),
-                2 => Ok(AdfReflectedScalar::U16(*read(buffer))),
-                4 => Ok(AdfReflectedScalar::U32(*read(buffer))),
-                8 => Ok(AdfReflectedScalar::U64(*read(buffer))),
-                size => todo!("invalid scalar size: {}", size),
-            },
-            AdfScalarType::Float => match type_info.size {
-                4 => Ok(AdfReflectedScalar::F32(*read(buffer))),
-                8 => Ok(AdfReflectedScalar::F64(*read(buffer))),
-                size => todo!("invalid scalar size: {}", size),
-            },
+    pub(crate) fn read_scalar(
+        type_info: &AdfType,
+        buffer: &[u8],
+        endian: Endian,
+    ) -> Result<AdfReflectedScalar, AdfReflectionErrorKind> {
+        macro_rules! read {
+            ($t:tt) => {
+                read_pod::<$t>(buffer, endian)
+            };
+        }
+        match (type_info.scalar_type, type_info.size) {
+            (AdfScalarType::Signed, 1) => Ok(AdfReflectedScalar::I8(read!(i8))),
+            (AdfScalarType::Signed, 2) => Ok(AdfReflectedScalar::I16(read!(i16))),
+            (AdfScalarType::Signed, 4) => Ok(AdfReflectedScalar::I32(read!(i32))),
+            (AdfScalarType::Signed, 8) => Ok(AdfReflectedScalar::I64(read!(i64))),
+            (AdfScalarType::Unsigned, 1) => Ok(AdfReflectedScalar::U8(read!(u8))),
+            (AdfScalarType::Unsigned, 2) => Ok(AdfReflectedScalar::U16(read!(u16))),
+            (AdfScalarType::Unsigned, 4) => Ok(AdfReflectedScalar::U32(read!(u32))),
+            (AdfScalarType::Unsigned, 8) => Ok(AdfReflectedScalar::U64(read!(u64))),
+            (AdfScalarType::Float, 4) => Ok(AdfReflectedScalar::F32(read!(f32))),
+            (AdfScalarType::Float, 8) => Ok(AdfReflectedScalar::F64(read!(f64))),
+            (scalar_type, size) => Err(AdfReflectionErrorKind::InvalidScalar { scalar_type, size }),
         }
     }
 
@@ -481,20 +1151,18 @@ impl AdfReflectionContext {
         buffer: &mut [u8],
         scalar: &AdfReflectedScalar,
         type_info: &AdfType,
-    ) -> Result<(), ()> {
+        endian: Endian,
+    ) -> Result<(), AdfReflectionErrorKind> {
         macro_rules! write {
             ($t:tt, $st:expr, $v:expr) => {{
                 let size = std::mem::size_of::<$t>() as u32;
-                if type_info.size != size {
-                    todo!("invalid scalar size: {}", size);
-                }
-                if type_info.alignment != size {
-                    todo!("invalid scalar alignment: {}", size);
+                if type_info.size != size
+                    || type_info.alignment != size
+                    || type_info.scalar_type != $st
+                {
+                    return Err(AdfReflectionErrorKind::ScalarMismatch(scalar.clone()));
                 }
-                if type_info.scalar_type != $st {
-                    todo!("invalid scalar type: {:?}", $st);
-                }
-                *bytemuck::from_bytes_mut::<$t>(buffer) = *$v;
+                write_pod::<$t>(buffer, *$v, endian);
                 Ok(())
             }};
         }
@@ -513,33 +1181,28 @@ impl AdfReflectionContext {
         }
     }
 
-    fn read_bitfield(
+    pub(crate) fn read_bitfield(
         type_info: &AdfType,
         buffer: &[u8],
         shift: usize,
-    ) -> Result<AdfReflectedScalar, ()> {
+        endian: Endian,
+    ) -> Result<AdfReflectedScalar, AdfReflectionErrorKind> {
         let mask = ((1usize << type_info.element_length as usize) - 1usize) << shift;
         macro_rules! read {
             ($t:tt) => {
-                (*bytemuck::from_bytes::<$t>(buffer) & mask as $t) >> shift
+                (read_pod::<$t>(buffer, endian) & mask as $t) >> shift
             };
         }
-        match type_info.scalar_type {
-            AdfScalarType::Signed => match type_info.size {
-                1 => Ok(AdfReflectedScalar::I8(read!(i8))),
-                2 => Ok(AdfReflectedScalar::I16(read!(i16))),
-                4 => Ok(AdfReflectedScalar::I32(read!(i32))),
-                8 => Ok(AdfReflectedScalar::I64(read!(i64))),
-                size => todo!("invalid scalar size: {}", size),
-            },
-            AdfScalarType::Unsigned => match type_info.size {
-                1 => Ok(AdfReflectedScalar::U8(read!(u8))),
-                2 => Ok(AdfReflectedScalar::U16(read!(u16))),
-                4 => Ok(AdfReflectedScalar::U32(read!(u32))),
-                8 => Ok(AdfReflectedScalar::U64(read!(u64))),
-                size => todo!("invalid scalar size: {}", size),
-            },
-            AdfScalarType::Float => todo!("invalid scalar type: {:?}", type_info.scalar_type),
+        match (type_info.scalar_type, type_info.size) {
+            (AdfScalarType::Signed, 1) => Ok(AdfReflectedScalar::I8(read!(i8))),
+            (AdfScalarType::Signed, 2) => Ok(AdfReflectedScalar::I16(read!(i16))),
+            (AdfScalarType::Signed, 4) => Ok(AdfReflectedScalar::I32(read!(i32))),
+            (AdfScalarType::Signed, 8) => Ok(AdfReflectedScalar::I64(read!(i64))),
+            (AdfScalarType::Unsigned, 1) => Ok(AdfReflectedScalar::U8(read!(u8))),
+            (AdfScalarType::Unsigned, 2) => Ok(AdfReflectedScalar::U16(read!(u16))),
+            (AdfScalarType::Unsigned, 4) => Ok(AdfReflectedScalar::U32(read!(u32))),
+            (AdfScalarType::Unsigned, 8) => Ok(AdfReflectedScalar::U64(read!(u64))),
+            (scalar_type, size) => Err(AdfReflectionErrorKind::InvalidScalar { scalar_type, size }),
         }
     }
 
@@ -548,21 +1211,22 @@ impl AdfReflectionContext {
         scalar: &AdfReflectedScalar,
         type_info: &AdfType,
         shift: usize,
-    ) -> Result<(), ()> {
+        endian: Endian,
+    ) -> Result<(), AdfReflectionErrorKind> {
         macro_rules! write {
             ($t:tt, $st:expr, $v:expr) => {{
                 let mask = (1 << type_info.element_length) - 1;
                 let size = std::mem::size_of::<$t>() as u32;
-                if type_info.size != size {
-                    todo!("invalid scalar size: {}", size);
-                }
-                if type_info.alignment != size {
-                    todo!("invalid scalar alignment: {}", size);
+                if type_info.size != size
+                    || type_info.alignment != size
+                    || type_info.scalar_type != $st
+                {
+                    return Err(AdfReflectionErrorKind::ScalarMismatch(scalar.clone()));
                 }
-                if type_info.scalar_type != $st {
-                    todo!("invalid scalar type: {:?}", $st);
-                }
-                *bytemuck::from_bytes_mut::<$t>(buffer) |= ($v & mask) << shift;
+                // Clear the existing bits first, the buffer may already hold a default
+                let value =
+                    (read_pod::<$t>(buffer, endian) & !(mask << shift)) | (($v & mask) << shift);
+                write_pod::<$t>(buffer, value, endian);
                 Ok(())
             }};
         }
@@ -576,23 +1240,26 @@ impl AdfReflectionContext {
             AdfReflectedScalar::I32(value) => write!(i32, AdfScalarType::Signed, value),
             AdfReflectedScalar::U64(value) => write!(u64, AdfScalarType::Unsigned, value),
             AdfReflectedScalar::I64(value) => write!(i64, AdfScalarType::Signed, value),
-            _ => todo!("invalid scalar value: {:?}", scalar),
+            _ => Err(AdfReflectionErrorKind::ScalarMismatch(scalar.clone())),
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum AdfReflectedPrimitive {
     // Represents a numeric value.
     Scalar(AdfReflectedScalar),
     // Represents a structure comprised of multiple reflected values.
     Structure(Vec<AdfReflectedValue>),
-    // Represents an indirect reflected value of the specified type.
-    Pointer(Arc<AdfReflectedValue>),
+    // Represents an indirect reflected value of the specified type, or nothing when null.
+    Pointer(Option<Arc<AdfReflectedValue>>),
     // Represents an indirect array of reflected values.
     Array(Arc<Vec<AdfReflectedValue>>),
     // Represents an array of reflected values.
     InlineArray(Vec<AdfReflectedValue>),
+    // Represents an indirect reflected value of the element type, or nothing, which allows
+    // types to contain themselves.
+    Recursive(Option<Arc<AdfReflectedValue>>),
     // Represents an indirect string.
     String(Arc<String>),
     // Represents a bitfield derived from a numeric value.
@@ -601,11 +1268,29 @@ pub enum AdfReflectedPrimitive {
     Enumeration(AdfReflectedScalar),
     // Represents an numeric value derived from a string hash.
     StringHash(AdfReflectedScalar),
-    // Represents an indirect reflected value of any type.
-    Deferred(Arc<AdfReflectedValue>),
+    // Represents an indirect reflected value of any type, or nothing.
+    Deferred(Option<Arc<AdfReflectedValue>>),
 }
 
-#[derive(Clone, Debug)]
+impl AdfReflectedPrimitive {
+    pub fn primitive(&self) -> AdfPrimitive {
+        match self {
+            AdfReflectedPrimitive::Scalar(_) => AdfPrimitive::Scalar,
+            AdfReflectedPrimitive::Structure(_) => AdfPrimitive::Structure,
+            AdfReflectedPrimitive::Pointer(_) => AdfPrimitive::Pointer,
+            AdfReflectedPrimitive::Array(_) => AdfPrimitive::Array,
+            AdfReflectedPrimitive::InlineArray(_) => AdfPrimitive::InlineArray,
+            AdfReflectedPrimitive::Recursive(_) => AdfPrimitive::Recursive,
+            AdfReflectedPrimitive::String(_) => AdfPrimitive::String,
+            AdfReflectedPrimitive::Bitfield(_) => AdfPrimitive::Bitfield,
+            AdfReflectedPrimitive::Enumeration(_) => AdfPrimitive::Enumeration,
+            AdfReflectedPrimitive::StringHash(_) => AdfPrimitive::StringHash,
+            AdfReflectedPrimitive::Deferred(_) => AdfPrimitive::Deferred,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum AdfReflectedScalar {
     U8(u8),
     I8(i8),
@@ -619,5 +1304,756 @@ pub enum AdfReflectedScalar {
     F64(f64),
 }
 
-#[derive(Clone, Debug)]
+macro_rules! impl_scalar_from {
+    ($($t:ty => $variant:ident),*) => {
+        $(
+            impl From<$t> for AdfReflectedScalar {
+                fn from(value: $t) -> Self {
+                    Self::$variant(value)
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar_from!(
+    u8 => U8, i8 => I8, u16 => U16, i16 => I16, u32 => U32,
+    i32 => I32, f32 => F32, u64 => U64, i64 => I64, f64 => F64
+);
+
+impl AdfReflectedScalar {
+    // Floats are only integers when they're whole
+    pub fn to_integer(&self) -> Option<i128> {
+        match *self {
+            AdfReflectedScalar::U8(value) => Some(value.into()),
+            AdfReflectedScalar::I8(value) => Some(value.into()),
+            AdfReflectedScalar::U16(value) => Some(value.into()),
+            AdfReflectedScalar::I16(value) => Some(value.into()),
+            AdfReflectedScalar::U32(value) => Some(value.into()),
+            AdfReflectedScalar::I32(value) => Some(value.into()),
+            AdfReflectedScalar::U64(value) => Some(value.into()),
+            AdfReflectedScalar::I64(value) => Some(value.into()),
+            AdfReflectedScalar::F32(value) => whole(value.into()),
+            AdfReflectedScalar::F64(value) => whole(value),
+        }
+    }
+
+    pub fn to_float(&self) -> f64 {
+        match *self {
+            AdfReflectedScalar::U8(value) => value.into(),
+            AdfReflectedScalar::I8(value) => value.into(),
+            AdfReflectedScalar::U16(value) => value.into(),
+            AdfReflectedScalar::I16(value) => value.into(),
+            AdfReflectedScalar::U32(value) => value.into(),
+            AdfReflectedScalar::I32(value) => value.into(),
+            AdfReflectedScalar::U64(value) => value as f64,
+            AdfReflectedScalar::I64(value) => value as f64,
+            AdfReflectedScalar::F32(value) => value.into(),
+            AdfReflectedScalar::F64(value) => value,
+        }
+    }
+
+    // Converts to the scalar type of a scalar, bitfield, enumeration or string hash, as long as
+    // the value fits
+    pub fn coerce(&self, type_info: &AdfType) -> Result<Self, AdfReflectionErrorKind> {
+        let integer = self.to_integer();
+        let float = self.to_float();
+        let out_of_range = || AdfReflectionErrorKind::ScalarRange(self.clone());
+
+        // Bitfields only hold as many bits as they're declared with
+        if type_info.primitive == AdfPrimitive::Bitfield
+            && !integer.is_some_and(|value| (0..1i128 << type_info.element_length).contains(&value))
+        {
+            return Err(out_of_range());
+        }
+
+        macro_rules! integer {
+            ($t:ty, $variant:ident) => {
+                AdfReflectedScalar::$variant(
+                    integer
+                        .and_then(|value| <$t>::try_from(value).ok())
+                        .ok_or_else(out_of_range)?,
+                )
+            };
+        }
+        Ok(match (type_info.scalar_type, type_info.size) {
+            (AdfScalarType::Signed, 1) => integer!(i8, I8),
+            (AdfScalarType::Signed, 2) => integer!(i16, I16),
+            (AdfScalarType::Signed, 4) => integer!(i32, I32),
+            (AdfScalarType::Signed, 8) => integer!(i64, I64),
+            (AdfScalarType::Unsigned, 1) => integer!(u8, U8),
+            (AdfScalarType::Unsigned, 2) => integer!(u16, U16),
+            (AdfScalarType::Unsigned, 4) => integer!(u32, U32),
+            (AdfScalarType::Unsigned, 8) => integer!(u64, U64),
+            (AdfScalarType::Float, 4) => {
+                let value = float as f32;
+                if value.is_infinite() && float.is_finite() {
+                    return Err(out_of_range());
+                }
+                AdfReflectedScalar::F32(value)
+            }
+            (AdfScalarType::Float, 8) => AdfReflectedScalar::F64(float),
+            (scalar_type, size) => {
+                return Err(AdfReflectionErrorKind::InvalidScalar { scalar_type, size });
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for AdfReflectedScalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdfReflectedScalar::U8(value) => write!(f, "{value}"),
+            AdfReflectedScalar::I8(value) => write!(f, "{value}"),
+            AdfReflectedScalar::U16(value) => write!(f, "{value}"),
+            AdfReflectedScalar::I16(value) => write!(f, "{value}"),
+            AdfReflectedScalar::U32(value) => write!(f, "{value}"),
+            AdfReflectedScalar::I32(value) => write!(f, "{value}"),
+            AdfReflectedScalar::F32(value) => write!(f, "{value}"),
+            AdfReflectedScalar::U64(value) => write!(f, "{value}"),
+            AdfReflectedScalar::I64(value) => write!(f, "{value}"),
+            AdfReflectedScalar::F64(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl AdfType {
+    // Enumerations can hold values which weren't declared, and those have no name
+    pub fn enumeration_name(&self, value: &AdfReflectedScalar) -> Option<&str> {
+        let value = value.to_integer()?;
+        self.enumerations
+            .iter()
+            .find(|x| self.declared_value(x.value) == value)
+            .map(|x| x.name.as_str())
+    }
+
+    pub fn enumeration_value(&self, name: &str) -> Option<AdfReflectedScalar> {
+        let value = self.declared_value(
+            self.enumerations
+                .iter()
+                .find(|x| x.name.as_str() == name)?
+                .value,
+        );
+        Some(match self.scalar_type {
+            AdfScalarType::Unsigned => AdfReflectedScalar::U64(value as u64),
+            _ => AdfReflectedScalar::I64(value as i64),
+        })
+    }
+
+    // Values are declared as `i32` whatever the storage, so those of unsigned enumerations are
+    // their bits within it; `-1` is `u32::MAX` for one stored in four bytes
+    fn declared_value(&self, value: i32) -> i128 {
+        let value = i128::from(value);
+        match self.scalar_type {
+            AdfScalarType::Unsigned => value & ((1i128 << (self.size.min(8) * 8)) - 1),
+            _ => value,
+        }
+    }
+}
+
+fn typed_error<T: AdfTypeInfo>(kind: AdfReflectionErrorKind) -> AdfReflectionError {
+    AdfReflectionError::new(T::NAME, None, kind)
+}
+
+fn whole(value: f64) -> Option<i128> {
+    (value.is_finite() && value.trunc() == value).then_some(value as i128)
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct AdfReflectedValue(pub u32, pub AdfReflectedPrimitive);
+
+#[inline(always)]
+const fn align(value: usize, alignment: usize) -> usize {
+    let align = alignment - 1;
+    (value + align) & !align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{built_in_types, AdfXml, AdfXmlValue};
+
+    // `Node` points to another `Node`, so its default can't be built by following the pointer
+    fn context() -> (AdfReflectionContext, AdfType) {
+        let uint32 = built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap();
+        let mut pointer = AdfType::pointer(&AdfType::structure("Node").build());
+        let node = AdfType::structure("Node")
+            .member("Value", uint32)
+            .member("Next", &pointer)
+            .build();
+        pointer.element_type_hash = node.type_hash;
+
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: vec![uint32.clone(), pointer, node.clone()],
+            ..Default::default()
+        });
+        (context, node)
+    }
+
+    fn node(type_info: &AdfType, value: u32, next: Option<AdfReflectedValue>) -> AdfReflectedValue {
+        AdfReflectedValue(
+            type_info.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                AdfReflectedValue(
+                    <u32 as AdfTypeInfo>::HASH,
+                    AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(value)),
+                ),
+                AdfReflectedValue(
+                    type_info.members[1].type_hash,
+                    AdfReflectedPrimitive::Pointer(next.map(Arc::new)),
+                ),
+            ]),
+        )
+    }
+
+    #[test]
+    fn default_pointers_are_null() {
+        let (context, type_info) = context();
+        assert_eq!(
+            context.default_value(type_info.type_hash),
+            Some(node(&type_info, 0, None))
+        );
+    }
+
+    #[test]
+    fn missing_members_take_null_pointers() {
+        let (context, type_info) = context();
+        let xml = AdfXmlValue {
+            type_name: "Node".into(),
+            members: vec![AdfXmlValue {
+                name: Some("Value".into()),
+                type_name: "uint32".into(),
+                value: "1".into(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let types = HashMap::from([
+            ("Node", type_info.type_hash),
+            ("uint32", <u32 as AdfTypeInfo>::HASH),
+        ]);
+        assert_eq!(
+            xml.to_value(&types, &context).unwrap(),
+            node(&type_info, 1, None)
+        );
+    }
+
+    #[test]
+    fn null_pointers_round_trip() {
+        let (context, type_info) = context();
+        let value = node(&type_info, 1, Some(node(&type_info, 2, None)));
+        let mut adf = AdfFile::default();
+        context.write_instance(&"Node", &value, &mut adf).unwrap();
+        assert_eq!(context.read_instance(&adf.instances[0]).unwrap(), value);
+    }
+
+    #[test]
+    fn library_instances_round_trip() {
+        for library in TYPE_LIBRARIES.iter().chain([BUILT_IN_TYPE_LIBRARY]) {
+            let file = library.load().unwrap();
+            let mut context = AdfReflectionContext::from_extension(library.extension).unwrap();
+            context.load_types_from_file(&file);
+            for instance in &file.instances {
+                let value = context.read_instance(instance).unwrap();
+                let mut written = AdfFile::default();
+                context
+                    .write_instance(&instance.name.as_ref(), &value, &mut written)
+                    .unwrap();
+                assert_eq!(context.read_instance(&written.instances[0]).unwrap(), value);
+            }
+        }
+    }
+
+    // Embedded types may claim a size too small for the layout of their primitive
+    #[test]
+    fn undersized_types_are_out_of_bounds() {
+        for (primitive, value, size) in [
+            (
+                AdfPrimitive::Pointer,
+                AdfReflectedPrimitive::Pointer(None),
+                8,
+            ),
+            (
+                AdfPrimitive::Recursive,
+                AdfReflectedPrimitive::Recursive(None),
+                8,
+            ),
+            (
+                AdfPrimitive::Deferred,
+                AdfReflectedPrimitive::Deferred(None),
+                12,
+            ),
+            (
+                AdfPrimitive::Array,
+                AdfReflectedPrimitive::Array(Arc::default()),
+                16,
+            ),
+            (
+                AdfPrimitive::String,
+                AdfReflectedPrimitive::String(Arc::default()),
+                8,
+            ),
+        ] {
+            let type_info = AdfType {
+                primitive: primitive.clone(),
+                size: 4,
+                alignment: 4,
+                type_hash: 1,
+                name: NullString::from("Short").into(),
+                element_type_hash: <u32 as AdfTypeInfo>::HASH,
+                ..Default::default()
+            };
+            let mut context = AdfReflectionContext::from_extension("").unwrap();
+            context.load_types_from_file(&AdfFile {
+                types: vec![type_info.clone()],
+                ..Default::default()
+            });
+
+            let mut instance = AdfInstance::from_type("Short", &type_info);
+            let error = context.read_instance(&instance).unwrap_err();
+            assert!(
+                matches!(error.kind, AdfReflectionErrorKind::OutOfBounds { size: x } if x == size),
+                "{primitive:?} read: {error}"
+            );
+            let error = context
+                .write_instance_value(&AdfReflectedValue(1, value), &mut instance)
+                .unwrap_err();
+            assert!(
+                matches!(error.kind, AdfReflectionErrorKind::OutOfBounds { size: x } if x == size),
+                "{primitive:?} write: {error}"
+            );
+        }
+    }
+
+    // An instance of a structure with the given members, whose buffer is built by hand
+    fn aliased(members: &[(&str, &AdfType)], bytes: &[u8]) -> (AdfReflectionContext, AdfInstance) {
+        let type_info = members
+            .iter()
+            .fold(
+                AdfType::structure("Aliased"),
+                |builder, (name, type_def)| builder.member(name, type_def),
+            )
+            .build();
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&AdfFile {
+            types: members
+                .iter()
+                .map(|(_, type_def)| (*type_def).clone())
+                .chain([type_info.clone()])
+                .collect(),
+            ..Default::default()
+        });
+
+        let mut instance = AdfInstance::from_type("Aliased", &type_info);
+        let buffer = instance.buffer.to_mut();
+        buffer.resize(bytes.len(), 0);
+        buffer.copy_from_slice(bytes);
+        (context, instance)
+    }
+
+    fn uint32() -> AdfType {
+        built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap()
+            .clone()
+    }
+
+    fn le_bytes(values: &[u64]) -> Vec<u8> {
+        values.iter().flat_map(|x| x.to_le_bytes()).collect()
+    }
+
+    fn members(value: &AdfReflectedValue) -> &[AdfReflectedValue] {
+        match &value.1 {
+            AdfReflectedPrimitive::Structure(members) => members,
+            primitive => panic!("{primitive:?} isn't a structure"),
+        }
+    }
+
+    fn shared_array(value: &AdfReflectedValue) -> &Arc<Vec<AdfReflectedValue>> {
+        match &value.1 {
+            AdfReflectedPrimitive::Array(values) => values,
+            primitive => panic!("{primitive:?} isn't an array"),
+        }
+    }
+
+    fn shared_string(value: &AdfReflectedValue) -> &Arc<String> {
+        match &value.1 {
+            AdfReflectedPrimitive::String(string) => string,
+            primitive => panic!("{primitive:?} isn't a string"),
+        }
+    }
+
+    fn shared_target(value: &AdfReflectedValue) -> &Arc<AdfReflectedValue> {
+        match &value.1 {
+            AdfReflectedPrimitive::Pointer(Some(target)) => target,
+            primitive => panic!("{primitive:?} isn't a pointer with a target"),
+        }
+    }
+
+    #[test]
+    fn aliased_offsets_are_read_once_and_written_once() {
+        let uint32 = uint32();
+        let string = built_in_types()
+            .iter()
+            .find(|x| x.primitive == AdfPrimitive::String)
+            .unwrap()
+            .clone();
+        let array = AdfType::array(&uint32);
+        let pointer = AdfType::pointer(&uint32);
+
+        // Laid out as they're written: arrays and pointer targets aligned to 16, strings packed
+        let mut bytes = le_bytes(&[64, 3, 64, 3, 76, 76, 96, 96]);
+        bytes.extend([1u32, 2, 3].iter().flat_map(|x| x.to_le_bytes()));
+        bytes.extend(b"Gear\0");
+        bytes.resize(96, 0);
+        bytes.extend(7u32.to_le_bytes());
+        let (context, instance) = aliased(
+            &[
+                ("First", &array),
+                ("Second", &array),
+                ("Name", &string),
+                ("Alias", &string),
+                ("Target", &pointer),
+                ("Same", &pointer),
+            ],
+            &bytes,
+        );
+
+        let value = context.read_instance(&instance).unwrap();
+        let members = members(&value);
+        let (first, second) = (shared_array(&members[0]), shared_array(&members[1]));
+        let (name, alias) = (shared_string(&members[2]), shared_string(&members[3]));
+        let (target, same) = (shared_target(&members[4]), shared_target(&members[5]));
+        assert!(Arc::ptr_eq(first, second));
+        assert!(Arc::ptr_eq(name, alias));
+        assert!(Arc::ptr_eq(target, same));
+        assert_eq!(name.as_str(), "Gear");
+
+        let mut written = instance.clone();
+        context.write_instance_value(&value, &mut written).unwrap();
+        assert_eq!(written.buffer.len(), instance.buffer.len());
+        assert_eq!(written.buffer.as_ref(), bytes.as_slice());
+    }
+
+    // An array sharing its start with a longer one gets values of its own, which are then
+    // written separately, but arrays of the same length still share theirs
+    #[test]
+    fn aliased_array_prefixes_are_read_separately() {
+        let array = AdfType::array(&uint32());
+        let mut bytes = le_bytes(&[48, 3, 48, 2, 48, 2]);
+        bytes.extend([1u32, 2, 3].iter().flat_map(|x| x.to_le_bytes()));
+        let (context, instance) = aliased(
+            &[("Whole", &array), ("Start", &array), ("Again", &array)],
+            &bytes,
+        );
+
+        let value = context.read_instance(&instance).unwrap();
+        let members = members(&value);
+        let (whole, start, again) = (
+            shared_array(&members[0]),
+            shared_array(&members[1]),
+            shared_array(&members[2]),
+        );
+        assert!(!Arc::ptr_eq(whole, start));
+        assert!(Arc::ptr_eq(start, again));
+        assert_eq!(start[..], whole[..2]);
+
+        // The prefix is copied after the whole array, at the next multiple of 16
+        let mut written = instance.clone();
+        context.write_instance_value(&value, &mut written).unwrap();
+        assert_eq!(written.buffer.len(), 72);
+        let mut rewritten = written.clone();
+        let read = context.read_instance(&written).unwrap();
+        assert_eq!(read, value);
+        context.write_instance_value(&read, &mut rewritten).unwrap();
+        assert_eq!(rewritten.buffer.len(), written.buffer.len());
+    }
+
+    // Written with the default limits, then read back by a context with the given ones
+    fn write_limited(
+        context: &AdfReflectionContext,
+        value: &AdfReflectedValue,
+        limits: AdfReflectionLimits,
+    ) -> (AdfReflectionContext, AdfFile) {
+        let mut adf = AdfFile::default();
+        context.write_instance(&"Limited", value, &mut adf).unwrap();
+        let mut limited = context.clone();
+        limited.set_limits(limits);
+        (limited, adf)
+    }
+
+    // The instance, its XML and the conversion of its XML back to a value all fail the same way
+    fn assert_limited(
+        context: &AdfReflectionContext,
+        value: &AdfReflectedValue,
+        limits: AdfReflectionLimits,
+        expected: impl Fn(&AdfReflectionErrorKind) -> bool,
+    ) {
+        let (limited, adf) = write_limited(context, value, limits);
+        let error = limited.read_instance(&adf.instances[0]).unwrap_err();
+        assert!(expected(&error.kind), "read_instance: {error}");
+        let error = AdfXml::new(&adf, &limited, "adf", false).unwrap_err();
+        assert!(expected(&error.kind), "AdfXml::new: {error}");
+
+        let xml = AdfXml::new(&adf, context, "adf", false).unwrap();
+        let types: HashMap<&str, u32> = xml
+            .types
+            .iter()
+            .map(|x| (x.type_name.as_str(), x.type_hash))
+            .collect();
+        assert_eq!(xml.instances[0].to_value(&types, context).unwrap(), *value);
+        let error = xml.instances[0].to_value(&types, &limited).unwrap_err();
+        assert!(expected(&error.kind), "to_value: {error}");
+    }
+
+    #[test]
+    fn deep_chains_hit_the_depth_limit() {
+        let (context, type_info) = context();
+        let value = (0..8)
+            .rev()
+            .fold(None, |next, value| Some(node(&type_info, value, next)));
+        let limits = AdfReflectionLimits {
+            max_depth: 8,
+            ..Default::default()
+        };
+        assert_limited(&context, &value.unwrap(), limits, |kind| {
+            matches!(kind, AdfReflectionErrorKind::DepthLimit(8))
+        });
+    }
+
+    #[test]
+    fn wide_fan_outs_hit_the_reference_limit() {
+        let uint32 = uint32();
+        let pointer = AdfType::pointer(&uint32);
+        let array = AdfType::array(&pointer);
+        let fan = AdfType::structure("Fan").member("Targets", &array).build();
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&AdfFile {
+            types: vec![pointer.clone(), array.clone(), fan.clone()],
+            ..Default::default()
+        });
+
+        // The array and each of its targets are a reference, all within a depth of four
+        let targets = (0..8)
+            .map(|value| {
+                AdfReflectedValue(
+                    pointer.type_hash,
+                    AdfReflectedPrimitive::Pointer(Some(Arc::new(AdfReflectedValue(
+                        uint32.type_hash,
+                        AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(value)),
+                    )))),
+                )
+            })
+            .collect();
+        let value = AdfReflectedValue(
+            fan.type_hash,
+            AdfReflectedPrimitive::Structure(vec![AdfReflectedValue(
+                array.type_hash,
+                AdfReflectedPrimitive::Array(Arc::new(targets)),
+            )]),
+        );
+        let limits = AdfReflectionLimits {
+            max_references: 4,
+            ..Default::default()
+        };
+        assert_limited(&context, &value, limits, |kind| {
+            matches!(kind, AdfReflectionErrorKind::ReferenceLimit(4))
+        });
+    }
+
+    // Holder { Data: void, Scalar: void, Empty: void }, where `Data` can target `Target`
+    fn deferred() -> (AdfReflectionContext, AdfType, AdfType) {
+        let uint32 = uint32();
+        let deferred = built_in_types()
+            .iter()
+            .find(|x| x.primitive == AdfPrimitive::Deferred)
+            .unwrap();
+        let target = AdfType::structure("Target")
+            .member("Value", &uint32)
+            .build();
+        let holder = AdfType::structure("Holder")
+            .member("Data", deferred)
+            .member("Scalar", deferred)
+            .member("Empty", deferred)
+            .build();
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&AdfFile {
+            types: vec![target.clone(), holder.clone()],
+            ..Default::default()
+        });
+        (context, holder, target)
+    }
+
+    #[test]
+    fn deferred_values_round_trip() {
+        let (context, holder, target) = deferred();
+        let uint32 = |value| {
+            AdfReflectedValue(
+                <u32 as AdfTypeInfo>::HASH,
+                AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(value)),
+            )
+        };
+        let deferred = |value: Option<AdfReflectedValue>| {
+            AdfReflectedValue(
+                holder.members[0].type_hash,
+                AdfReflectedPrimitive::Deferred(value.map(Arc::new)),
+            )
+        };
+        let value = AdfReflectedValue(
+            holder.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                deferred(Some(AdfReflectedValue(
+                    target.type_hash,
+                    AdfReflectedPrimitive::Structure(vec![uint32(7)]),
+                ))),
+                deferred(Some(uint32(9))),
+                deferred(None),
+            ]),
+        );
+
+        let mut adf = AdfFile::default();
+        context.write_instance(&"Holder", &value, &mut adf).unwrap();
+        let buffer = &adf.instances[0].buffer;
+        let field =
+            |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+        let target_hash =
+            |offset: usize| u32::from_le_bytes(buffer[offset + 8..offset + 12].try_into().unwrap());
+
+        // Each deferred value holds the offset of its target, then the target's type
+        let size = holder.members[1].offsets.byte() as usize;
+        assert_eq!(target_hash(0), target.type_hash);
+        assert_eq!(target_hash(size), <u32 as AdfTypeInfo>::HASH);
+        let target_offset = field(0) as usize;
+        let scalar_offset = field(size) as usize;
+        assert_eq!(buffer[target_offset..target_offset + 4], 7u32.to_le_bytes());
+        assert_eq!(buffer[scalar_offset..scalar_offset + 4], 9u32.to_le_bytes());
+        assert!(buffer[size * 2..size * 3].iter().all(|&x| x == 0));
+
+        assert_eq!(context.read_instance(&adf.instances[0]).unwrap(), value);
+    }
+
+    #[test]
+    fn deferred_values_of_unknown_types_are_rejected() {
+        let (context, holder, _) = deferred();
+        let mut instance = AdfInstance::from_type("Holder", &holder);
+        let buffer = instance.buffer.to_mut();
+        let offset = buffer.len();
+        buffer[0..8].copy_from_slice(&(offset as u64).to_le_bytes());
+        buffer[8..12].copy_from_slice(&0xDEADBEEFu32.to_le_bytes());
+        buffer.resize(offset + 4, 0);
+
+        let error = context.read_instance(&instance).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::MissingType(0xDEADBEEF)
+        ));
+        assert_eq!(error.path.to_string(), "Holder.Data");
+    }
+
+    // Tree { Value: uint32, Left: Recursive[Tree], Right: Recursive[Tree] }
+    fn tree() -> (AdfReflectionContext, AdfType) {
+        let mut recursive = AdfType::pointer(&AdfType::structure("Tree").build());
+        recursive.primitive = AdfPrimitive::Recursive;
+        recursive.type_hash = recursive.compute_type_hash();
+        let tree = AdfType::structure("Tree")
+            .member("Value", &uint32())
+            .member("Left", &recursive)
+            .member("Right", &recursive)
+            .build();
+        recursive.element_type_hash = tree.type_hash;
+
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&AdfFile {
+            types: vec![recursive, tree.clone()],
+            ..Default::default()
+        });
+        (context, tree)
+    }
+
+    fn branch(
+        tree: &AdfType,
+        value: u32,
+        left: Option<Arc<AdfReflectedValue>>,
+        right: Option<Arc<AdfReflectedValue>>,
+    ) -> Arc<AdfReflectedValue> {
+        let recursive = |value| {
+            AdfReflectedValue(
+                tree.members[1].type_hash,
+                AdfReflectedPrimitive::Recursive(value),
+            )
+        };
+        Arc::new(AdfReflectedValue(
+            tree.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                AdfReflectedValue(
+                    <u32 as AdfTypeInfo>::HASH,
+                    AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(value)),
+                ),
+                recursive(left),
+                recursive(right),
+            ]),
+        ))
+    }
+
+    #[test]
+    fn recursive_trees_round_trip() {
+        let (context, tree) = tree();
+        let leaf = branch(&tree, 3, None, None);
+        let shared = branch(&tree, 2, Some(leaf.clone()), None);
+        let root = branch(&tree, 1, Some(shared.clone()), Some(shared));
+
+        let mut adf = AdfFile::default();
+        context.write_instance(&"Tree", &root, &mut adf).unwrap();
+        // The root, then the shared branch and its leaf, each written once
+        assert_eq!(adf.instances[0].buffer.len(), 24 + 8 + 24 + 8 + 24);
+
+        let read = context.read_instance(&adf.instances[0]).unwrap();
+        assert_eq!(read, *root);
+        let members = members(&read);
+        assert!(Arc::ptr_eq(
+            recursive_target(&members[1]),
+            recursive_target(&members[2])
+        ));
+    }
+
+    fn recursive_target(value: &AdfReflectedValue) -> &Arc<AdfReflectedValue> {
+        match &value.1 {
+            AdfReflectedPrimitive::Recursive(Some(target)) => target,
+            primitive => panic!("{primitive:?} isn't a recursive value with a target"),
+        }
+    }
+
+    // The right branch of the root's left branch points back to itself, which a value can't hold
+    #[test]
+    fn recursive_cycles_are_rejected() {
+        let (context, tree) = tree();
+        let mut instance = AdfInstance::from_type("Tree", &tree);
+        let buffer = instance.buffer.to_mut();
+        buffer.resize(64, 0);
+        buffer[8..16].copy_from_slice(&32u64.to_le_bytes());
+        buffer[32..36].copy_from_slice(&2u32.to_le_bytes());
+        buffer[48..56].copy_from_slice(&32u64.to_le_bytes());
+
+        let error = context.read_instance(&instance).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::CyclicReference
+        ));
+        assert_eq!(error.path.to_string(), "Tree.Left.Right");
+
+        let adf = AdfFile {
+            instances: vec![Arc::new(instance)],
+            ..Default::default()
+        };
+        let error = AdfXml::new(&adf, &context, "adf", false).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::CyclicReference
+        ));
+    }
+}
@@ -0,0 +1,949 @@
+use std::sync::Arc;
+
+use serde::{
+    de::{
+        self, value::StrDeserializer, DeserializeOwned, DeserializeSeed, IntoDeserializer,
+        MapAccess, SeqAccess, Visitor,
+    },
+    forward_to_deserialize_any,
+    ser::{self, Impossible, Serialize},
+    Deserialize,
+};
+
+use super::{
+    AdfFile, AdfInstance, AdfMember, AdfPrimitive, AdfReflectedPrimitive, AdfReflectedScalar,
+    AdfReflectedValue, AdfReflectionContext, AdfReflectionError, AdfReflectionErrorKind, AdfType,
+};
+
+impl de::Error for AdfReflectionError {
+    fn custom<T: std::fmt::Display>(message: T) -> Self {
+        AdfReflectionError::new(
+            "",
+            None,
+            AdfReflectionErrorKind::Custom(message.to_string()),
+        )
+    }
+}
+
+impl ser::Error for AdfReflectionError {
+    fn custom<T: std::fmt::Display>(message: T) -> Self {
+        AdfReflectionError::new(
+            "",
+            None,
+            AdfReflectionErrorKind::Custom(message.to_string()),
+        )
+    }
+}
+
+// Errors raised by serde don't know which type they came from, so are given the closest one
+fn annotate(mut error: AdfReflectionError, type_name: &str) -> AdfReflectionError {
+    if error.type_name.is_empty() {
+        error.type_name = type_name.to_owned();
+    }
+    error
+}
+
+impl AdfReflectionContext {
+    // Members are matched to fields by name; members without a field are ignored
+    pub fn deserialize_value<'de, T: Deserialize<'de>>(
+        &self,
+        value: &'de AdfReflectedValue,
+    ) -> Result<T, AdfReflectionError> {
+        let deserializer = AdfDeserializer::new(self, value);
+        T::deserialize(deserializer).map_err(|error| annotate(error, &deserializer.type_name()))
+    }
+
+    // The whole instance is read into reflected values first, so this costs as much as
+    // `read_instance`; `visit_instance` walks the buffer in place when that's too much
+    pub fn deserialize_instance<T: DeserializeOwned>(
+        &self,
+        instance: &AdfInstance,
+    ) -> Result<T, AdfReflectionError> {
+        self.deserialize_value(&self.read_instance(instance)?)
+            .map_err(|error| error.member(instance.name.as_ref()))
+    }
+
+    // Members without a field take their default, and fields without a member are an error
+    pub fn serialize_value<T: Serialize + ?Sized>(
+        &self,
+        type_hash: u32,
+        value: &T,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
+        let serializer = AdfSerializer::new(self, type_hash)?;
+        let type_name = serializer.type_info.name.to_string();
+        value
+            .serialize(serializer)
+            .map_err(|error| annotate(error, &type_name))
+    }
+
+    pub fn serialize_instance<T: Serialize + ?Sized>(
+        &self,
+        name: &impl AsRef<str>,
+        type_hash: u32,
+        value: &T,
+        adf: &mut AdfFile,
+    ) -> Result<(), AdfReflectionError> {
+        let value = self
+            .serialize_value(type_hash, value)
+            .map_err(|error| error.member(name.as_ref()))?;
+        self.write_instance(name, &value, adf)
+    }
+}
+
+// Scalars are visited as their own type, so any field which can hold them will accept them.
+// Enumerations are visited by name when deserialized as an enum, and null pointers as `None`
+#[derive(Clone, Copy)]
+pub struct AdfDeserializer<'a, 'de> {
+    context: &'a AdfReflectionContext,
+    value: &'de AdfReflectedValue,
+}
+
+impl<'a, 'de> AdfDeserializer<'a, 'de> {
+    pub fn new(context: &'a AdfReflectionContext, value: &'de AdfReflectedValue) -> Self {
+        Self { context, value }
+    }
+
+    fn type_name(&self) -> String {
+        self.context
+            .get_type_by_hash(self.value.0)
+            .map_or_else(|| format!("{:#010x}", self.value.0), |x| x.name.to_string())
+    }
+
+    fn error(&self, kind: AdfReflectionErrorKind) -> AdfReflectionError {
+        AdfReflectionError::new(self.type_name(), None, kind)
+    }
+
+    fn type_info(&self) -> Result<&'a AdfType, AdfReflectionError> {
+        self.context
+            .get_type_by_hash(self.value.0)
+            .ok_or_else(|| self.error(AdfReflectionErrorKind::MissingType(self.value.0)))
+    }
+
+    fn target(&self) -> Option<Self> {
+        match &self.value.1 {
+            AdfReflectedPrimitive::Pointer(Some(value))
+            | AdfReflectedPrimitive::Recursive(Some(value))
+            | AdfReflectedPrimitive::Deferred(Some(value)) => {
+                Some(Self::new(self.context, value.as_ref()))
+            }
+            _ => None,
+        }
+    }
+
+    fn scalar(&self) -> Option<&'de AdfReflectedScalar> {
+        match &self.value.1 {
+            AdfReflectedPrimitive::Scalar(value)
+            | AdfReflectedPrimitive::Bitfield(value)
+            | AdfReflectedPrimitive::Enumeration(value)
+            | AdfReflectedPrimitive::StringHash(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'de> de::Deserializer<'de> for AdfDeserializer<'a, 'de> {
+    type Error = AdfReflectionError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        if let Some(scalar) = self.scalar() {
+            return match *scalar {
+                AdfReflectedScalar::U8(value) => visitor.visit_u8(value),
+                AdfReflectedScalar::I8(value) => visitor.visit_i8(value),
+                AdfReflectedScalar::U16(value) => visitor.visit_u16(value),
+                AdfReflectedScalar::I16(value) => visitor.visit_i16(value),
+                AdfReflectedScalar::U32(value) => visitor.visit_u32(value),
+                AdfReflectedScalar::I32(value) => visitor.visit_i32(value),
+                AdfReflectedScalar::F32(value) => visitor.visit_f32(value),
+                AdfReflectedScalar::U64(value) => visitor.visit_u64(value),
+                AdfReflectedScalar::I64(value) => visitor.visit_i64(value),
+                AdfReflectedScalar::F64(value) => visitor.visit_f64(value),
+            };
+        }
+        if let Some(target) = self.target() {
+            return target.deserialize_any(visitor);
+        }
+        match &self.value.1 {
+            AdfReflectedPrimitive::Structure(values) => {
+                let members = &self.type_info()?.members;
+                if members.len() != values.len() {
+                    return Err(self.error(AdfReflectionErrorKind::MemberCount {
+                        expected: members.len(),
+                        found: values.len(),
+                    }));
+                }
+                visitor.visit_map(AdfMemberAccess {
+                    context: self.context,
+                    members: members.iter().zip(values.iter()),
+                    value: None,
+                })
+            }
+            AdfReflectedPrimitive::Array(values) => visitor.visit_seq(AdfElementAccess {
+                context: self.context,
+                values: values.iter().enumerate(),
+            }),
+            AdfReflectedPrimitive::InlineArray(values) => visitor.visit_seq(AdfElementAccess {
+                context: self.context,
+                values: values.iter().enumerate(),
+            }),
+            AdfReflectedPrimitive::String(value) => visitor.visit_borrowed_str(value.as_str()),
+            _ => visitor.visit_none(),
+        }
+    }
+
+    // ADF has no booleans of its own, they're stored as integers
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.scalar().and_then(AdfReflectedScalar::to_integer) {
+            Some(value) => visitor.visit_bool(value != 0),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match &self.value.1 {
+            AdfReflectedPrimitive::Pointer(None)
+            | AdfReflectedPrimitive::Recursive(None)
+            | AdfReflectedPrimitive::Deferred(None) => visitor.visit_none(),
+            _ => match self.target() {
+                Some(target) => visitor.visit_some(target),
+                None => visitor.visit_some(self),
+            },
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if let Some(target) = self.target() {
+            return target.deserialize_enum(name, variants, visitor);
+        }
+        let AdfReflectedPrimitive::Enumeration(scalar) = &self.value.1 else {
+            return self.deserialize_any(visitor);
+        };
+        let Some(name) = self.type_info()?.enumeration_name(scalar) else {
+            return Err(self.error(AdfReflectionErrorKind::ScalarMismatch(scalar.clone())));
+        };
+        let name: StrDeserializer<'a, AdfReflectionError> = name.into_deserializer();
+        visitor.visit_enum(name)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct AdfMemberAccess<'a, 'de, I> {
+    context: &'a AdfReflectionContext,
+    members: I,
+    value: Option<(&'a AdfMember, &'de AdfReflectedValue)>,
+}
+
+impl<'a, 'de, I> MapAccess<'de> for AdfMemberAccess<'a, 'de, I>
+where
+    I: Iterator<Item = (&'a AdfMember, &'de AdfReflectedValue)>,
+{
+    type Error = AdfReflectionError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((member, value)) = self.members.next() else {
+            return Ok(None);
+        };
+        self.value = Some((member, value));
+        let name: StrDeserializer<'a, AdfReflectionError> =
+            member.name.as_str().into_deserializer();
+        seed.deserialize(name).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let Some((member, value)) = self.value.take() else {
+            return Err(de::Error::custom("value requested before its member"));
+        };
+        let deserializer = AdfDeserializer::new(self.context, value);
+        seed.deserialize(deserializer).map_err(|error| {
+            annotate(error, &deserializer.type_name()).member(member.name.as_str())
+        })
+    }
+}
+
+struct AdfElementAccess<'a, I> {
+    context: &'a AdfReflectionContext,
+    values: I,
+}
+
+impl<'a, 'de, I> SeqAccess<'de> for AdfElementAccess<'a, I>
+where
+    I: ExactSizeIterator<Item = (usize, &'de AdfReflectedValue)>,
+{
+    type Error = AdfReflectionError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        let Some((index, value)) = self.values.next() else {
+            return Ok(None);
+        };
+        let deserializer = AdfDeserializer::new(self.context, value);
+        seed.deserialize(deserializer)
+            .map(Some)
+            .map_err(|error| annotate(error, &deserializer.type_name()).element(index))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+// Builds a value of the given type. Pointers and recursive values are created around whatever
+// is serialized, unless it's `None`, and enumerations can be serialized by name. Deferred
+// values can only be serialized as `None`, as nothing says which type their target should be
+pub struct AdfSerializer<'a> {
+    context: &'a AdfReflectionContext,
+    type_info: &'a AdfType,
+    // Pointers and recursive values leading to `type_info`, outermost first
+    indirections: Vec<&'a AdfType>,
+}
+
+impl<'a> AdfSerializer<'a> {
+    pub fn new(
+        context: &'a AdfReflectionContext,
+        type_hash: u32,
+    ) -> Result<Self, AdfReflectionError> {
+        let missing = |type_hash| {
+            AdfReflectionError::new(
+                format!("{type_hash:#010x}"),
+                None,
+                AdfReflectionErrorKind::MissingType(type_hash),
+            )
+        };
+        let mut type_info = context
+            .get_type_by_hash(type_hash)
+            .ok_or_else(|| missing(type_hash))?;
+        let mut indirections = vec![];
+        while matches!(
+            type_info.primitive,
+            AdfPrimitive::Pointer | AdfPrimitive::Recursive
+        ) {
+            let max_depth = context.limits().max_depth;
+            if indirections.len() >= max_depth {
+                return Err(AdfReflectionError::new(
+                    type_info.name.as_str(),
+                    None,
+                    AdfReflectionErrorKind::DepthLimit(max_depth),
+                ));
+            }
+            indirections.push(type_info);
+            type_info = context
+                .get_type_by_hash(type_info.element_type_hash)
+                .ok_or_else(|| missing(type_info.element_type_hash))?;
+        }
+        Ok(Self {
+            context,
+            type_info,
+            indirections,
+        })
+    }
+
+    fn error(&self, kind: AdfReflectionErrorKind) -> AdfReflectionError {
+        AdfReflectionError::new(self.type_info.name.as_str(), None, kind)
+    }
+
+    fn mismatch(&self, found: AdfPrimitive) -> AdfReflectionError {
+        self.error(AdfReflectionErrorKind::PrimitiveMismatch {
+            expected: self.type_info.primitive.clone(),
+            found,
+        })
+    }
+
+    fn wrap(&self, primitive: AdfReflectedPrimitive) -> AdfReflectedValue {
+        let value = AdfReflectedValue(self.type_info.type_hash, primitive);
+        self.indirections
+            .iter()
+            .rev()
+            .fold(value, |value, type_info| {
+                let primitive = if type_info.primitive == AdfPrimitive::Recursive {
+                    AdfReflectedPrimitive::Recursive(Some(Arc::new(value)))
+                } else {
+                    AdfReflectedPrimitive::Pointer(Some(Arc::new(value)))
+                };
+                AdfReflectedValue(type_info.type_hash, primitive)
+            })
+    }
+
+    fn scalar(self, value: AdfReflectedScalar) -> Result<AdfReflectedValue, AdfReflectionError> {
+        let wrapper = match self.type_info.primitive {
+            AdfPrimitive::Scalar => AdfReflectedPrimitive::Scalar,
+            AdfPrimitive::Bitfield => AdfReflectedPrimitive::Bitfield,
+            AdfPrimitive::Enumeration => AdfReflectedPrimitive::Enumeration,
+            AdfPrimitive::StringHash => AdfReflectedPrimitive::StringHash,
+            _ => return Err(self.mismatch(AdfPrimitive::Scalar)),
+        };
+        let value = value
+            .coerce(self.type_info)
+            .map_err(|kind| self.error(kind))?;
+        Ok(self.wrap(wrapper(value)))
+    }
+
+    fn elements(
+        self,
+        length: Option<usize>,
+    ) -> Result<AdfElementSerializer<'a>, AdfReflectionError> {
+        if !matches!(
+            self.type_info.primitive,
+            AdfPrimitive::Array | AdfPrimitive::InlineArray
+        ) {
+            return Err(self.mismatch(AdfPrimitive::Array));
+        }
+        let element_hash = self.type_info.element_type_hash;
+        if self.context.get_type_by_hash(element_hash).is_none() {
+            return Err(self.error(AdfReflectionErrorKind::MissingType(element_hash)));
+        }
+        Ok(AdfElementSerializer {
+            values: Vec::with_capacity(length.unwrap_or_default()),
+            serializer: self,
+        })
+    }
+}
+
+fn unsupported(name: &'static str) -> AdfReflectionError {
+    AdfReflectionError::new("", None, AdfReflectionErrorKind::UnsupportedValue(name))
+}
+
+macro_rules! serialize_scalars {
+    ($($method:ident: $t:ty),*) => {
+        $(
+            fn $method(self, value: $t) -> Result<Self::Ok, Self::Error> {
+                self.scalar(value.into())
+            }
+        )*
+    };
+}
+
+impl<'a> ser::Serializer for AdfSerializer<'a> {
+    type Ok = AdfReflectedValue;
+    type Error = AdfReflectionError;
+
+    type SerializeSeq = AdfElementSerializer<'a>;
+    type SerializeTuple = AdfElementSerializer<'a>;
+    type SerializeTupleStruct = AdfElementSerializer<'a>;
+    type SerializeTupleVariant = Impossible<AdfReflectedValue, AdfReflectionError>;
+    type SerializeMap = Impossible<AdfReflectedValue, AdfReflectionError>;
+    type SerializeStruct = AdfMemberSerializer<'a>;
+    type SerializeStructVariant = Impossible<AdfReflectedValue, AdfReflectionError>;
+
+    serialize_scalars!(
+        serialize_i8: i8, serialize_i16: i16, serialize_i32: i32, serialize_i64: i64,
+        serialize_u8: u8, serialize_u16: u16, serialize_u32: u32, serialize_u64: u64,
+        serialize_f32: f32, serialize_f64: f64
+    );
+
+    fn serialize_bool(self, value: bool) -> Result<Self::Ok, Self::Error> {
+        self.scalar(u8::from(value).into())
+    }
+
+    fn serialize_char(self, value: char) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(value.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Self::Ok, Self::Error> {
+        match self.type_info.primitive {
+            AdfPrimitive::String => {
+                Ok(self.wrap(AdfReflectedPrimitive::String(Arc::new(value.to_owned()))))
+            }
+            AdfPrimitive::Enumeration => {
+                let Some(scalar) = self.type_info.enumeration_value(value) else {
+                    return Err(self.error(AdfReflectionErrorKind::InvalidText(value.into())));
+                };
+                self.scalar(scalar)
+            }
+            _ => Err(self.mismatch(AdfPrimitive::String)),
+        }
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok, Self::Error> {
+        let mut elements = self.elements(Some(value.len()))?;
+        for byte in value {
+            ser::SerializeSeq::serialize_element(&mut elements, byte)?;
+        }
+        ser::SerializeSeq::end(elements)
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        match self.indirections.first() {
+            Some(type_info) if type_info.primitive == AdfPrimitive::Recursive => Ok(
+                AdfReflectedValue(type_info.type_hash, AdfReflectedPrimitive::Recursive(None)),
+            ),
+            Some(type_info) => Ok(AdfReflectedValue(
+                type_info.type_hash,
+                AdfReflectedPrimitive::Pointer(None),
+            )),
+            _ if self.type_info.primitive == AdfPrimitive::Deferred => Ok(AdfReflectedValue(
+                self.type_info.type_hash,
+                AdfReflectedPrimitive::Deferred(None),
+            )),
+            _ => Err(self.error(AdfReflectionErrorKind::MissingValue)),
+        }
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        if self.indirections.is_empty() && self.type_info.primitive == AdfPrimitive::Deferred {
+            return Err(self.error(AdfReflectionErrorKind::UnsupportedValue("deferred target")));
+        }
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("unit"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("unit"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(unsupported("enum variant"))
+    }
+
+    fn serialize_seq(self, length: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        self.elements(length)
+    }
+
+    fn serialize_tuple(self, length: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.elements(Some(length))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        length: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.elements(Some(length))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _length: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(unsupported("enum variant"))
+    }
+
+    fn serialize_map(self, _length: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(unsupported("map"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _length: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        if self.type_info.primitive != AdfPrimitive::Structure {
+            return Err(self.mismatch(AdfPrimitive::Structure));
+        }
+        Ok(AdfMemberSerializer {
+            values: vec![None; self.type_info.members.len()],
+            serializer: self,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _length: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(unsupported("enum variant"))
+    }
+}
+
+pub struct AdfElementSerializer<'a> {
+    serializer: AdfSerializer<'a>,
+    values: Vec<AdfReflectedValue>,
+}
+
+impl<'a> ser::SerializeSeq for AdfElementSerializer<'a> {
+    type Ok = AdfReflectedValue;
+    type Error = AdfReflectionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let index = self.values.len();
+        let serializer = AdfSerializer::new(
+            self.serializer.context,
+            self.serializer.type_info.element_type_hash,
+        )?;
+        let type_name = serializer.type_info.name.to_string();
+        let value = value
+            .serialize(serializer)
+            .map_err(|error| annotate(error, &type_name).element(index))?;
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        let serializer = self.serializer;
+        if serializer.type_info.primitive == AdfPrimitive::Array {
+            return Ok(serializer.wrap(AdfReflectedPrimitive::Array(Arc::new(self.values))));
+        }
+        let expected = serializer.type_info.element_length as usize;
+        if self.values.len() != expected {
+            return Err(serializer.error(AdfReflectionErrorKind::ArrayLength {
+                expected,
+                found: self.values.len(),
+            }));
+        }
+        Ok(serializer.wrap(AdfReflectedPrimitive::InlineArray(self.values)))
+    }
+}
+
+impl<'a> ser::SerializeTuple for AdfElementSerializer<'a> {
+    type Ok = AdfReflectedValue;
+    type Error = AdfReflectionError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for AdfElementSerializer<'a> {
+    type Ok = AdfReflectedValue;
+    type Error = AdfReflectionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct AdfMemberSerializer<'a> {
+    serializer: AdfSerializer<'a>,
+    values: Vec<Option<AdfReflectedValue>>,
+}
+
+impl<'a> ser::SerializeStruct for AdfMemberSerializer<'a> {
+    type Ok = AdfReflectedValue;
+    type Error = AdfReflectionError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        let Some(index) = self
+            .serializer
+            .type_info
+            .members
+            .iter()
+            .position(|member| member.name.as_str() == key)
+        else {
+            return Err(self
+                .serializer
+                .error(AdfReflectionErrorKind::UnknownMember(key.into())));
+        };
+        let member = &self.serializer.type_info.members[index];
+        let serializer = AdfSerializer::new(self.serializer.context, member.type_hash)
+            .map_err(|error| error.member(key))?;
+        let type_name = serializer.type_info.name.to_string();
+        self.values[index] = Some(
+            value
+                .serialize(serializer)
+                .map_err(|error| annotate(error, &type_name).member(key))?,
+        );
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        let serializer = self.serializer;
+        let values = serializer
+            .type_info
+            .members
+            .iter()
+            .zip(self.values)
+            .map(|(member, value)| {
+                value
+                    .or_else(|| serializer.context.member_default_value(member))
+                    .ok_or_else(|| {
+                        serializer.error(AdfReflectionErrorKind::MissingMember(
+                            member.name.to_string(),
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(serializer.wrap(AdfReflectedPrimitive::Structure(values)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binrw::{BinWrite, Endian};
+    use serde::Serialize;
+
+    use super::*;
+    use crate::adf::{built_in_types, AdfLayout, AdfMemberValue, AdfTypeInfo, TYPE_LIBRARIES};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Book {
+        sheet: Arc<Vec<Sheet>>,
+        cell: Arc<Vec<Cell>>,
+        string_data: Arc<Vec<String>>,
+        value_data: Arc<Vec<f32>>,
+        bool_data: Arc<Vec<u8>>,
+        date_data: Arc<Vec<String>>,
+        color_data: Arc<Vec<u32>>,
+        attribute: Arc<Vec<Attribute>>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Sheet {
+        cols: u32,
+        rows: u32,
+        cell_index: Arc<Vec<u32>>,
+        name: String,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Cell {
+        #[serde(rename = "Type")]
+        kind: u16,
+        data_index: u32,
+        attribute_index: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Attribute {
+        #[serde(rename = "FGColorIndex")]
+        fg_color_index: u8,
+        #[serde(rename = "BGColorIndex")]
+        bg_color_index: u8,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Format {
+        Xls,
+        Xlsx,
+    }
+
+    // Holds an `XLSBook` along with what it doesn't have itself: pointers, an enumeration and a
+    // member which is never serialized, so is left to its default
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Export {
+        book: Option<Book>,
+        backup: Option<Book>,
+        format: Format,
+        #[serde(skip_serializing)]
+        revision: u32,
+    }
+
+    fn export_types() -> (AdfFile, u32) {
+        let mut library = TYPE_LIBRARIES
+            .iter()
+            .find(|lib| lib.extension == "xlsc")
+            .unwrap()
+            .load()
+            .unwrap();
+        let book = library.get_type_by_hash(192098653).unwrap().clone();
+        let uint32 = built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap()
+            .clone();
+        let pointer = AdfType::pointer(&book);
+        let format = AdfType::enumeration("XLSFormat", [("Xls", 0), ("Xlsx", 1)]);
+        let export = AdfType::structure("XLSExport")
+            .member("Book", &pointer)
+            .member("Backup", &pointer)
+            .member("Format", &format)
+            .member("Revision", &uint32)
+            .default_value(AdfMemberValue::InlineValue(7))
+            .build();
+        let type_hash = export.type_hash;
+        library.types.extend([pointer, format, export]);
+        library.hashes = Some(vec![]);
+        (library, type_hash)
+    }
+
+    fn context(adf: &AdfFile) -> AdfReflectionContext {
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: built_in_types().to_vec(),
+            ..Default::default()
+        });
+        context.load_types_from_file(adf);
+        context
+    }
+
+    fn book() -> Book {
+        Book {
+            sheet: vec![Sheet {
+                cols: 2,
+                rows: 1,
+                cell_index: vec![0, 1].into(),
+                name: "Sheet".to_owned(),
+            }]
+            .into(),
+            cell: vec![
+                Cell {
+                    kind: 1,
+                    data_index: 0,
+                    attribute_index: 0,
+                },
+                Cell {
+                    kind: 2,
+                    data_index: 0,
+                    attribute_index: 1,
+                },
+            ]
+            .into(),
+            string_data: vec!["Sheet".to_owned()].into(),
+            value_data: vec![1.5].into(),
+            bool_data: vec![1].into(),
+            date_data: vec![].into(),
+            color_data: vec![0xFF0000FF, 0xFFFFFFFF].into(),
+            attribute: vec![
+                Attribute {
+                    fg_color_index: 0,
+                    bg_color_index: 1,
+                },
+                Attribute {
+                    fg_color_index: 1,
+                    bg_color_index: 0,
+                },
+            ]
+            .into(),
+        }
+    }
+
+    fn deferred() -> (AdfReflectionContext, u32) {
+        let deferred = built_in_types()
+            .iter()
+            .find(|x| x.primitive == AdfPrimitive::Deferred)
+            .unwrap();
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: built_in_types().to_vec(),
+            ..Default::default()
+        });
+        (context, deferred.type_hash)
+    }
+
+    #[test]
+    fn deferred_values_serialize_as_none() {
+        let (context, type_hash) = deferred();
+        assert_eq!(
+            context.serialize_value(type_hash, &None::<u32>).unwrap(),
+            AdfReflectedValue(type_hash, AdfReflectedPrimitive::Deferred(None))
+        );
+    }
+
+    #[test]
+    fn deferred_targets_are_unsupported() {
+        let (context, type_hash) = deferred();
+        assert!(matches!(
+            context.serialize_value(type_hash, &Some(1u32)),
+            Err(error) if matches!(error.kind, AdfReflectionErrorKind::UnsupportedValue(_))
+        ));
+    }
+
+    #[test]
+    fn structures_round_trip_through_instances() {
+        let (mut adf, type_hash) = export_types();
+        let context = context(&adf);
+
+        let export = Export {
+            book: Some(book()),
+            backup: None,
+            format: Format::Xlsx,
+            revision: 0,
+        };
+        context
+            .serialize_instance(&"Export", type_hash, &export, &mut adf)
+            .unwrap();
+
+        let mut writer = std::io::Cursor::new(vec![]);
+        adf.write_options(&mut writer, Endian::Little, (AdfLayout::Aligned,))
+            .unwrap();
+        let adf = AdfFile::from_bytes(writer.into_inner()).unwrap();
+
+        let read: Export = context.deserialize_instance(&adf.instances[0]).unwrap();
+        assert_eq!(
+            read,
+            Export {
+                revision: 7,
+                ..export
+            }
+        );
+    }
+
+    #[test]
+    fn enumerations_are_deserialized_by_name() {
+        let (adf, type_hash) = export_types();
+        let context = context(&adf);
+
+        let mut value = context.default_value(type_hash).unwrap();
+        let AdfReflectedPrimitive::Structure(values) = &mut value.1 else {
+            panic!("export isn't a structure");
+        };
+        values[2].1 = AdfReflectedPrimitive::Enumeration(AdfReflectedScalar::U32(2));
+        assert!(matches!(
+            context.deserialize_value::<Export>(&value),
+            Err(error) if matches!(error.kind, AdfReflectionErrorKind::ScalarMismatch(_))
+        ));
+    }
+}
@@ -1,8 +1,6 @@
-use std::io::{BufReader, Cursor};
+use std::sync::OnceLock;
 
-use binrw::BinRead;
-
-use super::AdfFile;
+use super::{AdfFile, AdfType};
 
 macro_rules! adf_type_lib {
     ($extension:expr, $path:expr) => {
@@ -99,6 +97,16 @@ pub static TYPE_LIBRARIES: &'static [AdfTypeLib] = &adf_type_libs!(
     ("xvmc", "xvm_adf.adf"),
 );
 
+pub fn built_in_types() -> &'static [AdfType] {
+    static TYPES: OnceLock<Vec<AdfType>> = OnceLock::new();
+    TYPES.get_or_init(|| {
+        BUILT_IN_TYPE_LIBRARY
+            .load()
+            .map(|library| library.types)
+            .unwrap_or_default()
+    })
+}
+
 pub struct AdfTypeLib {
     pub extension: &'static str,
     pub library: &'static [u8],
@@ -106,7 +114,6 @@ pub struct AdfTypeLib {
 
 impl AdfTypeLib {
     pub fn load(&self) -> binrw::BinResult<AdfFile> {
-        let mut reader = BufReader::new(Cursor::new(self.library));
-        Ok(AdfFile::read_le(&mut reader)?)
+        AdfFile::from_bytes(self.library)
     }
 }
@@ -0,0 +1,688 @@
+use std::{collections::HashSet, ops::Range};
+
+use binrw::Endian;
+use thiserror::Error;
+
+use mm_hashing::hash_little32;
+
+use crate::common::read_pod;
+
+use super::{built_in_types, AdfFile, AdfPrimitive, AdfReflectionContext};
+
+const HEADER_SIZE: usize = 64;
+const TYPE_SIZE: usize = 40;
+const MEMBER_SIZE: usize = 32;
+const ENUM_SIZE: usize = 12;
+const INSTANCE_SIZE: usize = 24;
+const HASH_SIZE: usize = 4;
+
+#[derive(Clone, Debug, Default)]
+pub struct AdfValidationReport {
+    pub endian: Option<Endian>,
+    pub issues: Vec<AdfValidationIssue>,
+}
+
+impl AdfValidationReport {
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdfSection {
+    Header,
+    Types,
+    Instances,
+    InstanceBuffer(String),
+    Hashes,
+    Strings,
+}
+
+impl std::fmt::Display for AdfSection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Header => write!(f, "header"),
+            Self::Types => write!(f, "types"),
+            Self::Instances => write!(f, "instances"),
+            Self::InstanceBuffer(name) => write!(f, "buffer of instance {name}"),
+            Self::Hashes => write!(f, "hashes"),
+            Self::Strings => write!(f, "strings"),
+        }
+    }
+}
+
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum AdfValidationIssue {
+    #[error("invalid magic {0:02x?}")]
+    InvalidMagic([u8; 4]),
+    #[error("unsupported version {0}")]
+    UnsupportedVersion(u32),
+    #[error("header file size {expected} does not match actual size {found}")]
+    FileSize { expected: u32, found: usize },
+    #[error("{section} ({start:#x}..{end:#x}) is outside of the file")]
+    SectionOutOfBounds {
+        section: AdfSection,
+        start: usize,
+        end: usize,
+    },
+    #[error("{first} overlaps {second}")]
+    SectionOverlap {
+        first: AdfSection,
+        second: AdfSection,
+    },
+    #[error("string {index} was expected to be {expected} bytes in length, found {found}")]
+    StringLength {
+        index: usize,
+        expected: u8,
+        found: usize,
+    },
+    #[error("{owner} references invalid string {index}")]
+    InvalidString { owner: String, index: u64 },
+    #[error("instance {instance} has name hash {found:#010x}, expected {expected:#010x}")]
+    InstanceNameHash {
+        instance: String,
+        expected: u32,
+        found: u32,
+    },
+    #[error("instance {instance} has unknown type {type_hash:#010x}")]
+    InstanceType { instance: String, type_hash: u32 },
+    #[error("type {type_name} has invalid primitive {primitive}")]
+    TypePrimitive { type_name: String, primitive: u32 },
+    #[error("type {type_name} has unknown element type {type_hash:#010x}")]
+    ElementType { type_name: String, type_hash: u32 },
+    #[error("member {type_name}.{member} has unknown type {type_hash:#010x}")]
+    MemberType {
+        type_name: String,
+        member: String,
+        type_hash: u32,
+    },
+    #[error(
+        "member {type_name}.{member} at offset {offset} with size {size} does not fit in {type_size} bytes"
+    )]
+    MemberOffset {
+        type_name: String,
+        member: String,
+        offset: u32,
+        size: u32,
+        type_size: u32,
+    },
+    #[error("member {type_name}.{member} has invalid default kind {kind}")]
+    MemberValue {
+        type_name: String,
+        member: String,
+        kind: u32,
+    },
+    #[error("member {type_name}.{member} references missing instance {name_hash:#010x}")]
+    DanglingReference {
+        type_name: String,
+        member: String,
+        name_hash: u32,
+    },
+}
+
+impl AdfFile {
+    // Checks raw ADF bytes without stopping at the first problem; types that aren't embedded
+    // are resolved through the context
+    pub fn validate(bytes: &[u8], context: &AdfReflectionContext) -> AdfValidationReport {
+        let mut validator = AdfValidator {
+            bytes,
+            endian: Endian::Little,
+            context,
+            strings: Vec::new(),
+            strings_missing: false,
+            types: Vec::new(),
+            issues: Vec::new(),
+        };
+        let endian = validator.validate();
+        AdfValidationReport {
+            endian,
+            issues: validator.issues,
+        }
+    }
+}
+
+struct AdfValidatorMember {
+    name: u64,
+    type_hash: u32,
+    offset: u32,
+    kind: u32,
+    value: u64,
+}
+
+struct AdfValidatorType {
+    primitive: u32,
+    size: u32,
+    type_hash: u32,
+    name: u64,
+    element_type_hash: u32,
+    members: Vec<AdfValidatorMember>,
+    enumerations: Vec<u64>,
+}
+
+struct AdfValidator<'a> {
+    bytes: &'a [u8],
+    endian: Endian,
+    context: &'a AdfReflectionContext,
+    strings: Vec<String>,
+    strings_missing: bool,
+    types: Vec<AdfValidatorType>,
+    issues: Vec<AdfValidationIssue>,
+}
+
+impl AdfValidator<'_> {
+    fn validate(&mut self) -> Option<Endian> {
+        let Some(magic) = self.bytes.get(0..4) else {
+            self.out_of_bounds(AdfSection::Header, 0..HEADER_SIZE);
+            return None;
+        };
+        self.endian = match magic {
+            b" FDA" => Endian::Little,
+            b"ADF " => Endian::Big,
+            _ => {
+                let mut found = [0u8; 4];
+                found.copy_from_slice(magic);
+                self.issues.push(AdfValidationIssue::InvalidMagic(found));
+                return None;
+            }
+        };
+
+        // Header fields, followed by a null terminated description
+        let Some(description) = self.bytes.get(HEADER_SIZE..) else {
+            self.out_of_bounds(AdfSection::Header, 0..HEADER_SIZE);
+            return Some(self.endian);
+        };
+        let Some(description_length) = description.iter().position(|&x| x == 0) else {
+            self.out_of_bounds(AdfSection::Header, 0..self.bytes.len() + 1);
+            return Some(self.endian);
+        };
+        let header = 0..HEADER_SIZE + description_length + 1;
+        let field = |index: usize| self.u32(4 + index * 4).unwrap_or_default() as usize;
+        let version = field(0) as u32;
+        let (instance_count, instance_offset) = (field(1), field(2));
+        let (type_count, type_offset) = (field(3), field(4));
+        let (hash_count, hash_offset) = (field(5), field(6));
+        let (string_count, string_offset) = (field(7), field(8));
+        let file_size = field(9) as u32;
+
+        if version != 4 {
+            self.issues
+                .push(AdfValidationIssue::UnsupportedVersion(version));
+        }
+        if file_size as usize != self.bytes.len() {
+            self.issues.push(AdfValidationIssue::FileSize {
+                expected: file_size,
+                found: self.bytes.len(),
+            });
+        }
+
+        let mut sections = vec![(AdfSection::Header, header)];
+        if string_count > 0 {
+            sections.extend(
+                self.read_strings(string_offset, string_count)
+                    .map(|range| (AdfSection::Strings, range)),
+            );
+        }
+        if type_count > 0 {
+            sections.extend(
+                self.read_types(type_offset, type_count)
+                    .map(|range| (AdfSection::Types, range)),
+            );
+        }
+        let mut instance_hashes = HashSet::new();
+        if instance_count > 0 {
+            let range = instance_offset..instance_offset + instance_count * INSTANCE_SIZE;
+            if range.end <= self.bytes.len() {
+                for index in 0..instance_count {
+                    let offset = instance_offset + index * INSTANCE_SIZE;
+                    let (name_hash, buffer) = self.validate_instance(index, offset);
+                    instance_hashes.insert(name_hash);
+                    sections.push(buffer);
+                }
+            }
+            sections.push((AdfSection::Instances, range));
+        }
+        if hash_count > 0 {
+            let range = hash_offset..hash_offset + hash_count * HASH_SIZE;
+            sections.push((AdfSection::Hashes, range));
+        }
+
+        self.validate_sections(sections);
+        self.validate_types(&instance_hashes);
+        Some(self.endian)
+    }
+
+    fn read_strings(&mut self, offset: usize, count: usize) -> Option<Range<usize>> {
+        // Strings that can't be read are reported once, rather than by everything naming them
+        self.strings_missing = true;
+        let Some(lengths) = self.bytes.get(offset..offset + count) else {
+            self.out_of_bounds(AdfSection::Strings, offset..offset + count);
+            return None;
+        };
+
+        let mut position = offset + count;
+        for (index, &expected) in lengths.iter().enumerate() {
+            let string = &self.bytes[position.min(self.bytes.len())..];
+            let Some(length) = string.iter().position(|&x| x == 0) else {
+                self.out_of_bounds(AdfSection::Strings, offset..self.bytes.len() + 1);
+                return None;
+            };
+            if length != expected as usize {
+                self.issues.push(AdfValidationIssue::StringLength {
+                    index,
+                    expected,
+                    found: length,
+                });
+            }
+            self.strings
+                .push(String::from_utf8_lossy(&string[..length]).into_owned());
+            position += length + 1;
+        }
+        self.strings_missing = false;
+        Some(offset..position)
+    }
+
+    fn read_types(&mut self, offset: usize, count: usize) -> Option<Range<usize>> {
+        let mut position = offset;
+        for _ in 0..count {
+            let (Some(head), Some(length)) = (
+                self.bytes.get(position..position + TYPE_SIZE),
+                self.u32(position + 36),
+            ) else {
+                self.out_of_bounds(AdfSection::Types, offset..position + TYPE_SIZE);
+                return None;
+            };
+            let read_u32 = |offset: usize| read_pod::<u32>(&head[offset..offset + 4], self.endian);
+            let mut type_def = AdfValidatorType {
+                primitive: read_u32(0),
+                size: read_u32(4),
+                type_hash: read_u32(12),
+                name: read_pod(&head[16..24], self.endian),
+                element_type_hash: read_u32(28),
+                members: Vec::new(),
+                enumerations: Vec::new(),
+            };
+            position += TYPE_SIZE;
+
+            // Only structures and enumerations have a length, it's padding otherwise
+            let entry_size = match primitive(type_def.primitive) {
+                Some(AdfPrimitive::Structure) => MEMBER_SIZE,
+                Some(AdfPrimitive::Enumeration) => ENUM_SIZE,
+                _ => 0,
+            };
+            let end = position + length as usize * entry_size;
+            if end > self.bytes.len() {
+                self.out_of_bounds(AdfSection::Types, offset..end);
+                return None;
+            }
+            for _ in 0..(end - position) / entry_size.max(1) {
+                let entry = &self.bytes[position..position + entry_size];
+                if entry_size == MEMBER_SIZE {
+                    type_def.members.push(AdfValidatorMember {
+                        name: read_pod(&entry[0..8], self.endian),
+                        type_hash: read_pod(&entry[8..12], self.endian),
+                        offset: read_pod::<u32>(&entry[16..20], self.endian) & 0xFFFFFF,
+                        kind: read_pod(&entry[20..24], self.endian),
+                        value: read_pod(&entry[24..32], self.endian),
+                    });
+                } else {
+                    type_def
+                        .enumerations
+                        .push(read_pod(&entry[0..8], self.endian));
+                }
+                position += entry_size;
+            }
+            self.types.push(type_def);
+        }
+        Some(offset..position)
+    }
+
+    fn validate_instance(
+        &mut self,
+        index: usize,
+        offset: usize,
+    ) -> (u32, (AdfSection, Range<usize>)) {
+        let field = |index: usize| self.u32(offset + index * 4).unwrap_or_default();
+        let (name_hash, type_hash) = (field(0), field(1));
+        let (buffer_offset, buffer_size) = (field(2) as usize, field(3) as usize);
+        let name_index = self.u64(offset + 16).unwrap_or_default();
+
+        let name = if let Some(name) = self.strings.get(name_index as usize) {
+            let expected = hash_little32(name.as_bytes());
+            if expected != name_hash {
+                self.issues.push(AdfValidationIssue::InstanceNameHash {
+                    instance: name.clone(),
+                    expected,
+                    found: name_hash,
+                });
+            }
+            name.clone()
+        } else {
+            if !self.strings_missing {
+                self.issues.push(AdfValidationIssue::InvalidString {
+                    owner: format!("instance #{index}"),
+                    index: name_index,
+                });
+            }
+            format!("#{index}")
+        };
+
+        if self.type_size(type_hash).is_none() {
+            self.issues.push(AdfValidationIssue::InstanceType {
+                instance: name.clone(),
+                type_hash,
+            });
+        }
+
+        (
+            name_hash,
+            (
+                AdfSection::InstanceBuffer(name),
+                buffer_offset..buffer_offset + buffer_size,
+            ),
+        )
+    }
+
+    fn validate_sections(&mut self, mut sections: Vec<(AdfSection, Range<usize>)>) {
+        sections.retain(|(_, range)| !range.is_empty());
+        for (section, range) in &sections {
+            if range.end > self.bytes.len() {
+                self.out_of_bounds(section.clone(), range.clone());
+            }
+        }
+
+        // Sorting by start means each section only needs checking against the furthest reaching one
+        sections.sort_by_key(|(_, range)| range.start);
+        let mut furthest: Option<&(AdfSection, Range<usize>)> = None;
+        for current in &sections {
+            if let Some(previous) = furthest {
+                if current.1.start < previous.1.end {
+                    self.issues.push(AdfValidationIssue::SectionOverlap {
+                        first: previous.0.clone(),
+                        second: current.0.clone(),
+                    });
+                }
+            }
+            if furthest.map_or(true, |previous| current.1.end > previous.1.end) {
+                furthest = Some(current);
+            }
+        }
+    }
+
+    fn validate_types(&mut self, instance_hashes: &HashSet<u32>) {
+        let mut issues = Vec::new();
+        for (index, type_def) in self.types.iter().enumerate() {
+            let type_name = self.string(&mut issues, type_def.name, || format!("type #{index}"));
+
+            let Some(primitive) = primitive(type_def.primitive) else {
+                issues.push(AdfValidationIssue::TypePrimitive {
+                    type_name,
+                    primitive: type_def.primitive,
+                });
+                continue;
+            };
+            if matches!(
+                primitive,
+                AdfPrimitive::Pointer
+                    | AdfPrimitive::Array
+                    | AdfPrimitive::InlineArray
+                    | AdfPrimitive::Recursive
+            ) && self.type_size(type_def.element_type_hash).is_none()
+            {
+                issues.push(AdfValidationIssue::ElementType {
+                    type_name: type_name.clone(),
+                    type_hash: type_def.element_type_hash,
+                });
+            }
+
+            for member in &type_def.members {
+                let member_name = self.string(&mut issues, member.name, || {
+                    format!("member of type {type_name}")
+                });
+                match self.type_size(member.type_hash) {
+                    // Sizes come from the file, so are widened to stay clear of overflow
+                    Some(size)
+                        if u64::from(member.offset) + u64::from(size)
+                            > u64::from(type_def.size) =>
+                    {
+                        issues.push(AdfValidationIssue::MemberOffset {
+                            type_name: type_name.clone(),
+                            member: member_name.clone(),
+                            offset: member.offset,
+                            size,
+                            type_size: type_def.size,
+                        });
+                    }
+                    Some(_) => {}
+                    None => issues.push(AdfValidationIssue::MemberType {
+                        type_name: type_name.clone(),
+                        member: member_name.clone(),
+                        type_hash: member.type_hash,
+                    }),
+                }
+                match member.kind {
+                    0 | 1 => {}
+                    2 if instance_hashes.contains(&(member.value as u32)) => {}
+                    2 => issues.push(AdfValidationIssue::DanglingReference {
+                        type_name: type_name.clone(),
+                        member: member_name,
+                        name_hash: member.value as u32,
+                    }),
+                    kind => issues.push(AdfValidationIssue::MemberValue {
+                        type_name: type_name.clone(),
+                        member: member_name,
+                        kind,
+                    }),
+                }
+            }
+
+            for &name in &type_def.enumerations {
+                self.string(&mut issues, name, || {
+                    format!("enumeration of type {type_name}")
+                });
+            }
+        }
+        self.issues.extend(issues);
+    }
+
+    fn string(
+        &self,
+        issues: &mut Vec<AdfValidationIssue>,
+        index: u64,
+        owner: impl FnOnce() -> String,
+    ) -> String {
+        if let Some(string) = self.strings.get(index as usize) {
+            string.clone()
+        } else {
+            let owner = owner();
+            if !self.strings_missing {
+                issues.push(AdfValidationIssue::InvalidString {
+                    owner: owner.clone(),
+                    index,
+                });
+            }
+            owner
+        }
+    }
+
+    fn type_size(&self, type_hash: u32) -> Option<u32> {
+        if let Some(type_def) = self.types.iter().find(|x| x.type_hash == type_hash) {
+            return Some(type_def.size);
+        }
+        self.context
+            .get_type_by_hash(type_hash)
+            .or_else(|| built_in_types().iter().find(|x| x.type_hash == type_hash))
+            .map(|type_def| type_def.size)
+    }
+
+    fn out_of_bounds(&mut self, section: AdfSection, range: Range<usize>) {
+        self.issues.push(AdfValidationIssue::SectionOutOfBounds {
+            section,
+            start: range.start,
+            end: range.end,
+        });
+    }
+
+    #[inline]
+    fn u32(&self, offset: usize) -> Option<u32> {
+        self.bytes
+            .get(offset..offset + 4)
+            .map(|bytes| read_pod(bytes, self.endian))
+    }
+
+    #[inline]
+    fn u64(&self, offset: usize) -> Option<u64> {
+        self.bytes
+            .get(offset..offset + 8)
+            .map(|bytes| read_pod(bytes, self.endian))
+    }
+}
+
+fn primitive(value: u32) -> Option<AdfPrimitive> {
+    use AdfPrimitive::*;
+    [
+        Scalar,
+        Structure,
+        Pointer,
+        Array,
+        InlineArray,
+        String,
+        Recursive,
+        Bitfield,
+        Enumeration,
+        StringHash,
+        Deferred,
+    ]
+    .into_iter()
+    .nth(value as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::TYPE_LIBRARIES;
+
+    fn library(name: &str) -> Vec<u8> {
+        TYPE_LIBRARIES
+            .iter()
+            .find(|x| x.extension == name)
+            .unwrap()
+            .library
+            .to_vec()
+    }
+
+    fn validate(bytes: &[u8]) -> AdfValidationReport {
+        AdfFile::validate(bytes, &AdfReflectionContext::default())
+    }
+
+    fn set_u32(bytes: &mut [u8], offset: usize, value: u32) {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn get_u32(bytes: &[u8], offset: usize) -> usize {
+        read_pod::<u32>(&bytes[offset..offset + 4], Endian::Little) as usize
+    }
+
+    // Offsets of every type definition in a little endian file
+    fn type_offsets(bytes: &[u8]) -> Vec<usize> {
+        let mut position = get_u32(bytes, 20);
+        let mut offsets = vec![];
+        for _ in 0..get_u32(bytes, 16) {
+            offsets.push(position);
+            let entry_size = match get_u32(bytes, position) {
+                1 => MEMBER_SIZE,
+                8 => ENUM_SIZE,
+                _ => 0,
+            };
+            position += TYPE_SIZE + get_u32(bytes, position + 36) * entry_size;
+        }
+        offsets
+    }
+
+    #[test]
+    fn valid_library() {
+        let report = validate(&library("createdxlsfiles"));
+        assert_eq!(report.endian, Some(Endian::Little));
+        assert_eq!(report.issues, vec![]);
+    }
+
+    #[test]
+    fn truncated_file() {
+        let bytes = library("createdxlsfiles");
+        for length in [0, 3, 40, HEADER_SIZE + 1, bytes.len() / 2, bytes.len() - 1] {
+            let report = validate(&bytes[..length]);
+            assert!(!report.is_valid(), "{length} bytes are valid");
+        }
+
+        let report = validate(&bytes[..bytes.len() - 1]);
+        assert!(report.issues.contains(&AdfValidationIssue::FileSize {
+            expected: bytes.len() as u32,
+            found: bytes.len() - 1,
+        }));
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            AdfValidationIssue::SectionOutOfBounds {
+                section: AdfSection::Strings,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn corrupt_header() {
+        let mut bytes = library("createdxlsfiles");
+        bytes[0] = b'X';
+        assert_eq!(
+            validate(&bytes).issues,
+            vec![AdfValidationIssue::InvalidMagic(*b"XFDA")]
+        );
+
+        let mut bytes = library("createdxlsfiles");
+        set_u32(&mut bytes, 4, 5);
+        set_u32(&mut bytes, 36, u32::MAX);
+        let report = validate(&bytes);
+        assert!(report
+            .issues
+            .contains(&AdfValidationIssue::UnsupportedVersion(5)));
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            AdfValidationIssue::SectionOutOfBounds {
+                section: AdfSection::Strings,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn corrupt_type_sizes() {
+        // Sizes this large overflow the end of any member placed after the start of its type
+        let mut bytes = library("createdxlsfiles");
+        for offset in type_offsets(&bytes) {
+            set_u32(&mut bytes, offset + 4, u32::MAX);
+        }
+        let report = validate(&bytes);
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| matches!(issue, AdfValidationIssue::MemberOffset { .. })));
+    }
+
+    #[test]
+    fn corrupt_type_table() {
+        // A structure claiming more members than the file holds
+        let mut bytes = library("createdxlsfiles");
+        let offset = type_offsets(&bytes)
+            .into_iter()
+            .find(|&offset| get_u32(&bytes, offset) == 1)
+            .unwrap();
+        set_u32(&mut bytes, offset + 36, u32::MAX);
+        let report = validate(&bytes);
+        assert!(report.issues.iter().any(|issue| matches!(
+            issue,
+            AdfValidationIssue::SectionOutOfBounds {
+                section: AdfSection::Types,
+                ..
+            }
+        )));
+    }
+}
@@ -0,0 +1,563 @@
+use binrw::Endian;
+use thiserror::Error;
+
+use crate::common::read_pod;
+
+use super::{
+    AdfInstance, AdfMember, AdfPrimitive, AdfReflectedScalar, AdfReflectionBudget,
+    AdfReflectionContext, AdfReflectionErrorKind, AdfType,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdfVisitControl {
+    Continue,
+    // Don't descend into the value just entered
+    Skip,
+    // Values already entered are still left
+    Stop,
+}
+
+// Where a value sits within its parent
+#[derive(Clone, Copy, Debug)]
+pub enum AdfVisitKey<'a> {
+    Root,
+    Member(&'a AdfMember),
+    Element(usize),
+    Target,
+}
+
+// Every value is reported with its key and type; containers are entered and left, while
+// scalars and strings are reported once. Bitfields, enumerations and string hashes are
+// reported as scalars, the type tells them apart
+pub trait AdfVisitor {
+    fn enter_structure(&mut self, _key: AdfVisitKey<'_>, _type_info: &AdfType) -> AdfVisitControl {
+        AdfVisitControl::Continue
+    }
+
+    fn leave_structure(&mut self, _key: AdfVisitKey<'_>, _type_info: &AdfType) {}
+
+    // Used for both arrays and inline arrays
+    fn enter_array(
+        &mut self,
+        _key: AdfVisitKey<'_>,
+        _type_info: &AdfType,
+        _count: usize,
+    ) -> AdfVisitControl {
+        AdfVisitControl::Continue
+    }
+
+    fn leave_array(&mut self, _key: AdfVisitKey<'_>, _type_info: &AdfType) {}
+
+    // Used for pointers, recursive and deferred values, which are entered and left without a
+    // target when null
+    fn enter_pointer(&mut self, _key: AdfVisitKey<'_>, _type_info: &AdfType) -> AdfVisitControl {
+        AdfVisitControl::Continue
+    }
+
+    fn leave_pointer(&mut self, _key: AdfVisitKey<'_>, _type_info: &AdfType) {}
+
+    fn scalar(
+        &mut self,
+        _key: AdfVisitKey<'_>,
+        _type_info: &AdfType,
+        _value: AdfReflectedScalar,
+    ) -> AdfVisitControl {
+        AdfVisitControl::Continue
+    }
+
+    fn string(
+        &mut self,
+        _key: AdfVisitKey<'_>,
+        _type_info: &AdfType,
+        _value: &str,
+    ) -> AdfVisitControl {
+        AdfVisitControl::Continue
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AdfVisitError {
+    #[error("failed to find type {0:#010x}")]
+    MissingType(u32),
+    #[error("{size} bytes at {offset:#x} are outside of the buffer")]
+    OutOfBounds { offset: usize, size: usize },
+    #[error("invalid {type_name}: {kind}")]
+    InvalidValue {
+        type_name: String,
+        kind: AdfReflectionErrorKind,
+    },
+    #[error("{type_name} at {offset:#x}: {kind}")]
+    LimitExceeded {
+        type_name: String,
+        offset: usize,
+        kind: AdfReflectionErrorKind,
+    },
+}
+
+impl AdfReflectionContext {
+    // Walks the instance buffer in place, without building reflected values
+    pub fn visit_instance(
+        &self,
+        instance: &AdfInstance,
+        visitor: &mut impl AdfVisitor,
+    ) -> Result<(), AdfVisitError> {
+        let mut walker = AdfVisitWalker {
+            context: self,
+            buffer: &instance.buffer,
+            endian: instance.endian,
+            budget: AdfReflectionBudget::new(self.limits()),
+        };
+        walker.visit(AdfVisitKey::Root, instance.type_hash, 0, 0, visitor)?;
+        Ok(())
+    }
+}
+
+struct AdfVisitWalker<'a> {
+    context: &'a AdfReflectionContext,
+    buffer: &'a [u8],
+    endian: Endian,
+    budget: AdfReflectionBudget,
+}
+
+impl<'a> AdfVisitWalker<'a> {
+    fn visit(
+        &mut self,
+        key: AdfVisitKey<'_>,
+        type_hash: u32,
+        offset: usize,
+        shift: usize,
+        visitor: &mut impl AdfVisitor,
+    ) -> Result<AdfVisitControl, AdfVisitError> {
+        let type_info = self.type_info(type_hash)?;
+        let slice = self.slice(offset, type_info.size as usize)?;
+
+        let invalid = |kind| AdfVisitError::InvalidValue {
+            type_name: type_info.name.to_string(),
+            kind,
+        };
+        let limit = |kind| AdfVisitError::LimitExceeded {
+            type_name: type_info.name.to_string(),
+            offset,
+            kind,
+        };
+        self.budget.enter().map_err(limit)?;
+
+        let control = match type_info.primitive {
+            AdfPrimitive::Scalar | AdfPrimitive::Enumeration | AdfPrimitive::StringHash => {
+                let value = AdfReflectionContext::read_scalar(type_info, slice, self.endian)
+                    .map_err(invalid)?;
+                visitor.scalar(key, type_info, value)
+            }
+            AdfPrimitive::Bitfield => {
+                let value =
+                    AdfReflectionContext::read_bitfield(type_info, slice, shift, self.endian)
+                        .map_err(invalid)?;
+                visitor.scalar(key, type_info, value)
+            }
+            AdfPrimitive::Structure => {
+                let control = match visitor.enter_structure(key, type_info) {
+                    AdfVisitControl::Continue => self.visit_members(type_info, offset, visitor)?,
+                    control => control,
+                };
+                visitor.leave_structure(key, type_info);
+                control
+            }
+            AdfPrimitive::Pointer | AdfPrimitive::Recursive | AdfPrimitive::Deferred => {
+                let control = match visitor.enter_pointer(key, type_info) {
+                    AdfVisitControl::Continue => {
+                        self.visit_target(type_info, offset, slice, visitor)?
+                    }
+                    control => control,
+                };
+                visitor.leave_pointer(key, type_info);
+                control
+            }
+            AdfPrimitive::Array | AdfPrimitive::InlineArray => {
+                let (start, count) = if type_info.primitive == AdfPrimitive::Array {
+                    (
+                        self.read::<u64>(slice, offset, 0)? as usize,
+                        self.read::<u64>(slice, offset, 8)? as usize,
+                    )
+                } else {
+                    (offset, type_info.element_length as usize)
+                };
+                let control = match visitor.enter_array(key, type_info, count) {
+                    AdfVisitControl::Continue => {
+                        if type_info.primitive == AdfPrimitive::Array {
+                            self.budget.follow().map_err(limit)?;
+                        }
+                        self.visit_elements(type_info, start, count, visitor)?
+                    }
+                    control => control,
+                };
+                visitor.leave_array(key, type_info);
+                control
+            }
+            AdfPrimitive::String => {
+                let start = self.read::<u64>(slice, offset, 0)? as usize;
+                let string = self.buffer.get(start..).ok_or(AdfVisitError::OutOfBounds {
+                    offset: start,
+                    size: 1,
+                })?;
+                let end = string.iter().position(|&x| x == 0).unwrap_or(string.len());
+                visitor.string(key, type_info, &String::from_utf8_lossy(&string[..end]))
+            }
+        };
+        self.budget.leave();
+        Ok(stopped(control))
+    }
+
+    fn visit_members(
+        &mut self,
+        type_info: &AdfType,
+        offset: usize,
+        visitor: &mut impl AdfVisitor,
+    ) -> Result<AdfVisitControl, AdfVisitError> {
+        for member in type_info.members.iter() {
+            let control = self.visit(
+                AdfVisitKey::Member(member),
+                member.type_hash,
+                offset + member.offsets.byte() as usize,
+                member.offsets.bit() as usize,
+                visitor,
+            )?;
+            if control == AdfVisitControl::Stop {
+                return Ok(control);
+            }
+        }
+        Ok(AdfVisitControl::Continue)
+    }
+
+    fn visit_target(
+        &mut self,
+        type_info: &AdfType,
+        offset: usize,
+        slice: &[u8],
+        visitor: &mut impl AdfVisitor,
+    ) -> Result<AdfVisitControl, AdfVisitError> {
+        let target = self.read::<u64>(slice, offset, 0)? as usize;
+        let target_hash = if type_info.primitive == AdfPrimitive::Deferred {
+            self.read::<u32>(slice, offset, 8)?
+        } else {
+            type_info.element_type_hash
+        };
+        if target == 0 || target_hash == 0 {
+            return Ok(AdfVisitControl::Continue);
+        }
+
+        self.budget
+            .follow()
+            .map_err(|kind| AdfVisitError::LimitExceeded {
+                type_name: type_info.name.to_string(),
+                offset,
+                kind,
+            })?;
+        self.visit(AdfVisitKey::Target, target_hash, target, 0, visitor)
+    }
+
+    fn visit_elements(
+        &mut self,
+        type_info: &AdfType,
+        start: usize,
+        count: usize,
+        visitor: &mut impl AdfVisitor,
+    ) -> Result<AdfVisitControl, AdfVisitError> {
+        let element_size = self.type_info(type_info.element_type_hash)?.size as usize;
+        // Validate the count before walking it, it may be garbage
+        let size = element_size.saturating_mul(count);
+        self.slice(start, size)?;
+
+        for index in 0..count {
+            let control = self.visit(
+                AdfVisitKey::Element(index),
+                type_info.element_type_hash,
+                start + index * element_size,
+                0,
+                visitor,
+            )?;
+            if control == AdfVisitControl::Stop {
+                return Ok(control);
+            }
+        }
+        Ok(AdfVisitControl::Continue)
+    }
+
+    fn type_info(&self, type_hash: u32) -> Result<&'a AdfType, AdfVisitError> {
+        self.context
+            .get_type_by_hash(type_hash)
+            .ok_or(AdfVisitError::MissingType(type_hash))
+    }
+
+    fn slice(&self, offset: usize, size: usize) -> Result<&'a [u8], AdfVisitError> {
+        offset
+            .checked_add(size)
+            .and_then(|end| self.buffer.get(offset..end))
+            .ok_or(AdfVisitError::OutOfBounds { offset, size })
+    }
+
+    // Reads a field of the value at `offset`, which may be smaller than the type claims
+    fn read<T: bytemuck::Pod>(
+        &self,
+        slice: &[u8],
+        offset: usize,
+        at: usize,
+    ) -> Result<T, AdfVisitError> {
+        let size = std::mem::size_of::<T>();
+        slice
+            .get(at..at + size)
+            .map(|bytes| read_pod(bytes, self.endian))
+            .ok_or(AdfVisitError::OutOfBounds {
+                offset: offset + at,
+                size,
+            })
+    }
+}
+
+// Skipped values are still left, as are stopped ones while unwinding, so enter and leave
+// always pair up
+fn stopped(control: AdfVisitControl) -> AdfVisitControl {
+    if control == AdfVisitControl::Stop {
+        control
+    } else {
+        AdfVisitControl::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adf::{built_in_types, AdfFile, AdfTypeInfo};
+
+    // Reports values by name, skipping or stopping at the one named
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+        skip: Option<&'static str>,
+        stop: Option<&'static str>,
+    }
+
+    impl Recorder {
+        fn push(&mut self, event: String, name: &str) -> AdfVisitControl {
+            self.events.push(event);
+            if self.skip == Some(name) {
+                AdfVisitControl::Skip
+            } else if self.stop == Some(name) {
+                AdfVisitControl::Stop
+            } else {
+                AdfVisitControl::Continue
+            }
+        }
+    }
+
+    fn name(key: AdfVisitKey<'_>) -> String {
+        match key {
+            AdfVisitKey::Root => "root".into(),
+            AdfVisitKey::Member(member) => member.name.to_string(),
+            AdfVisitKey::Element(index) => index.to_string(),
+            AdfVisitKey::Target => "target".into(),
+        }
+    }
+
+    impl AdfVisitor for Recorder {
+        fn enter_structure(&mut self, key: AdfVisitKey<'_>, _: &AdfType) -> AdfVisitControl {
+            let name = name(key);
+            self.push(format!("enter {name}"), &name)
+        }
+
+        fn leave_structure(&mut self, key: AdfVisitKey<'_>, _: &AdfType) {
+            self.events.push(format!("leave {}", name(key)));
+        }
+
+        fn enter_array(&mut self, key: AdfVisitKey<'_>, _: &AdfType, _: usize) -> AdfVisitControl {
+            let name = name(key);
+            self.push(format!("enter {name}"), &name)
+        }
+
+        fn leave_array(&mut self, key: AdfVisitKey<'_>, _: &AdfType) {
+            self.events.push(format!("leave {}", name(key)));
+        }
+
+        fn scalar(
+            &mut self,
+            key: AdfVisitKey<'_>,
+            _: &AdfType,
+            _: AdfReflectedScalar,
+        ) -> AdfVisitControl {
+            let name = name(key);
+            self.push(name.clone(), &name)
+        }
+    }
+
+    // `Values` holds two elements, stored after the rest of the instance
+    fn fixture(array: impl FnOnce(&AdfType) -> AdfType) -> (AdfReflectionContext, AdfInstance) {
+        let uint32 = built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap();
+        let array = array(&AdfType::array(uint32));
+        let inner = AdfType::structure("Inner").member("Value", uint32).build();
+        let outer = AdfType::structure("Outer")
+            .member("Values", &array)
+            .member("Inner", &inner)
+            .member("Last", uint32)
+            .build();
+
+        let mut instance = AdfInstance::from_type("Outer", &outer);
+        let buffer = instance.buffer.to_mut();
+        let start = buffer.len() as u64;
+        buffer.extend_from_slice(&[0; 8]);
+        if array.size >= 16 {
+            set_array(&mut instance, start, 2);
+        }
+
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(&AdfFile {
+            types: vec![uint32.clone(), array, inner, outer],
+            ..Default::default()
+        });
+        (context, instance)
+    }
+
+    fn set_array(instance: &mut AdfInstance, offset: u64, count: u64) {
+        let buffer = instance.buffer.to_mut();
+        buffer[0..8].copy_from_slice(&offset.to_le_bytes());
+        buffer[8..16].copy_from_slice(&count.to_le_bytes());
+    }
+
+    fn visit(recorder: &mut Recorder) -> Result<(), AdfVisitError> {
+        let (context, instance) = fixture(Clone::clone);
+        context.visit_instance(&instance, recorder)
+    }
+
+    #[test]
+    fn values_are_visited_in_order() {
+        let mut recorder = Recorder::default();
+        visit(&mut recorder).unwrap();
+        assert_eq!(
+            recorder.events,
+            [
+                "enter root",
+                "enter Values",
+                "0",
+                "1",
+                "leave Values",
+                "enter Inner",
+                "Value",
+                "leave Inner",
+                "Last",
+                "leave root"
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_values_are_left() {
+        let mut recorder = Recorder {
+            skip: Some("Inner"),
+            ..Default::default()
+        };
+        visit(&mut recorder).unwrap();
+        assert!(!recorder.events.iter().any(|x| x == "Value"));
+        assert_eq!(
+            recorder.events[5..],
+            ["enter Inner", "leave Inner", "Last", "leave root"]
+        );
+    }
+
+    #[test]
+    fn stopped_values_are_left() {
+        let mut recorder = Recorder {
+            stop: Some("0"),
+            ..Default::default()
+        };
+        visit(&mut recorder).unwrap();
+        assert_eq!(
+            recorder.events,
+            [
+                "enter root",
+                "enter Values",
+                "0",
+                "leave Values",
+                "leave root"
+            ]
+        );
+
+        let mut recorder = Recorder {
+            stop: Some("Inner"),
+            ..Default::default()
+        };
+        visit(&mut recorder).unwrap();
+        assert_eq!(
+            recorder.events[5..],
+            ["enter Inner", "leave Inner", "leave root"]
+        );
+    }
+
+    #[test]
+    fn corrupt_array_offset() {
+        let (context, mut instance) = fixture(Clone::clone);
+        set_array(&mut instance, u64::MAX - 4, 1);
+        assert!(matches!(
+            context.visit_instance(&instance, &mut Recorder::default()),
+            Err(AdfVisitError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn corrupt_array_count() {
+        let (context, mut instance) = fixture(Clone::clone);
+        set_array(&mut instance, 24, u64::MAX);
+        assert!(matches!(
+            context.visit_instance(&instance, &mut Recorder::default()),
+            Err(AdfVisitError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn undersized_array_type() {
+        let (context, instance) = fixture(|array| AdfType {
+            size: 8,
+            type_hash: array.type_hash.wrapping_add(1),
+            ..array.clone()
+        });
+        assert!(matches!(
+            context.visit_instance(&instance, &mut Recorder::default()),
+            Err(AdfVisitError::OutOfBounds { offset: 8, size: 8 })
+        ));
+    }
+
+    #[test]
+    fn deferred_values_are_followed() {
+        let deferred = built_in_types()
+            .iter()
+            .find(|x| x.primitive == AdfPrimitive::Deferred)
+            .unwrap();
+        let (mut context, target) = fixture(Clone::clone);
+        let holder = AdfType::structure("Holder")
+            .member("Data", deferred)
+            .build();
+        context.load_types_from_file(&AdfFile {
+            types: vec![deferred.clone(), holder.clone()],
+            ..Default::default()
+        });
+
+        let mut instance = AdfInstance::from_type("Holder", &holder);
+        let buffer = instance.buffer.to_mut();
+        let start = buffer.len().next_multiple_of(8);
+        buffer.resize(start, 0);
+        buffer[0..8].copy_from_slice(&(start as u64).to_le_bytes());
+        buffer[8..12].copy_from_slice(&target.type_hash.to_le_bytes());
+        // The target's array points past the holder, so is moved along with it
+        let mut target_buffer = target.buffer.to_vec();
+        target_buffer[0..8].copy_from_slice(&(start as u64 + 24).to_le_bytes());
+        buffer.extend_from_slice(&target_buffer);
+
+        let mut recorder = Recorder::default();
+        context.visit_instance(&instance, &mut recorder).unwrap();
+        assert_eq!(
+            recorder.events[0..3],
+            ["enter root", "enter target", "enter Values"]
+        );
+        assert_eq!(recorder.events.last().unwrap(), "leave root");
+    }
+}
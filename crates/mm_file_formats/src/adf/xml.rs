@@ -1,10 +1,13 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use mm_hashing::{hash_little32, HashList, HashString};
 
 use super::{AdfFile, AdfMemberValue, AdfPrimitive, AdfScalarType, AdfType};
 use serde::{Deserialize, Serialize};
 
 use super::reflection::{
-    AdfReflectedPrimitive, AdfReflectedScalar, AdfReflectedValue, AdfReflectionContext,
+    AdfReflectedPrimitive, AdfReflectedScalar, AdfReflectedValue, AdfReflectionBudget,
+    AdfReflectionContext, AdfReflectionError, AdfReflectionErrorKind, AdfReflectionLimits,
 };
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -21,31 +24,72 @@ pub struct AdfXml {
     pub types: Vec<AdfXmlType>,
     #[serde(rename = "instance", default)]
     pub instances: Vec<AdfXmlValue>,
+    // String hashes which weren't in the hash list, for adding to it; ignored when converting
+    #[serde(rename = "unresolved", skip_serializing_if = "Vec::is_empty", default)]
+    pub unresolved: Vec<String>,
 }
 
 impl AdfXml {
-    pub fn new(adf: &AdfFile, context: &AdfReflectionContext, extension: &str) -> Self {
+    // Members equal to their default can be omitted, as they are filled back in when converting
+    pub fn new(
+        adf: &AdfFile,
+        context: &AdfReflectionContext,
+        extension: &str,
+        omit_defaults: bool,
+    ) -> Result<Self, AdfReflectionError> {
+        Self::new_in(adf, context, extension, omit_defaults, None)
+    }
+
+    // String hashes found in the hash list are written as their string
+    pub fn with_hashes(
+        adf: &AdfFile,
+        context: &AdfReflectionContext,
+        extension: &str,
+        omit_defaults: bool,
+        hashes: &HashList,
+    ) -> Result<Self, AdfReflectionError> {
+        Self::new_in(adf, context, extension, omit_defaults, Some(hashes))
+    }
+
+    fn new_in(
+        adf: &AdfFile,
+        context: &AdfReflectionContext,
+        extension: &str,
+        omit_defaults: bool,
+        hashes: Option<&HashList>,
+    ) -> Result<Self, AdfReflectionError> {
         // Reflect instances
         let instances: Vec<(&str, AdfReflectedValue)> = adf
             .instances
             .iter()
-            .filter_map(|instance| {
-                // TODO: Throw an error when `reflect_instance` uses results
+            .map(|instance| {
                 context
-                    .read_instance(&instance)
-                    .ok()
+                    .read_instance(instance)
                     .map(|x| (instance.name.as_ref(), x))
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         // Collect used types and build a list of unique names
-        let types = collect_types(instances.iter().map(|instance| &instance.1));
+        let types = collect_types(
+            instances.iter().map(|instance| &instance.1),
+            context.limits(),
+        )?;
         let names: HashMap<u32, String> = types
             .iter()
             .filter_map(|&type_hash| type_name(type_hash, &context).map(|name| (type_hash, name)))
             .collect();
 
-        Self {
+        let mut export = AdfXmlExport::new(context, &names, omit_defaults, hashes);
+        let instances = instances
+            .iter()
+            .map(|instance| {
+                let mut value = AdfXmlValue::from_value_in(&instance.1, context, &mut export)?;
+                value.name = Some(instance.0.to_owned());
+                Ok(value)
+            })
+            .collect::<Result<Vec<AdfXmlValue>, _>>()?;
+
+        Ok(Self {
             extension: extension.to_string(),
             embedded_types: !adf.types.is_empty(),
             types: {
@@ -59,23 +103,12 @@ impl AdfXml {
                 types.sort_by(|a, b| a.type_name.cmp(&b.type_name));
                 types
             },
-            instances: {
-                instances
-                    .iter()
-                    .map(|instance| {
-                        AdfXmlValue::from_value_named(
-                            &instance.1,
-                            instance.0.to_string(),
-                            context,
-                            &names,
-                        )
-                    })
-                    .collect::<Vec<AdfXmlValue>>()
-            },
-        }
+            instances,
+            unresolved: export.unresolved.into_iter().map(hash_string).collect(),
+        })
     }
 
-    pub fn convert(&self, context: &AdfReflectionContext) -> AdfFile {
+    pub fn convert(&self, context: &AdfReflectionContext) -> Result<AdfFile, AdfReflectionError> {
         let mut result = AdfFile::default();
 
         // Build type look up
@@ -90,26 +123,34 @@ impl AdfXml {
             result.types = self
                 .types
                 .iter()
-                .filter_map(|type_info| {
-                    let Some(type_info) = context.get_type_by_hash(type_info.type_hash) else {
-                        todo!("failed to find type: {}", type_info.type_name);
+                .map(|xml_type| {
+                    let Some(type_info) = context.get_type_by_hash(xml_type.type_hash) else {
+                        return Err(AdfReflectionError::new(
+                            xml_type.type_name.as_str(),
+                            None,
+                            AdfReflectionErrorKind::MissingType(xml_type.type_hash),
+                        ));
                     };
 
-                    (!matches!(
+                    Ok((!matches!(
                         type_info.primitive,
                         // We can skip types that only exist in builtin_types.adf
                         AdfPrimitive::Scalar | AdfPrimitive::String | AdfPrimitive::Deferred
                     ))
                     .then(|| {
                         let mut type_info = type_info.clone();
-                        // We don't need default values when writing
+                        // Default instances aren't written unless they're in the XML, so only
+                        // keep their names
                         for member in type_info.members.iter_mut() {
-                            member.value = AdfMemberValue::UninitializedValue(());
+                            if let Some(hash) = member.value.instance_hash() {
+                                member.value = AdfMemberValue::UnresolvedInstanceValue(hash);
+                            }
                         }
                         type_info
-                    })
+                    }))
                 })
-                .collect();
+                .filter_map(Result::transpose)
+                .collect::<Result<_, _>>()?;
         }
 
         // Reconstruct reflected instances
@@ -117,60 +158,69 @@ impl AdfXml {
             .instances
             .iter()
             .map(|instance| {
-                (
-                    instance
-                        .name
-                        .as_ref()
-                        .expect("instance must have name")
-                        .as_ref(),
-                    instance.to_value(&types, context),
-                )
+                let Some(name) = instance.name.as_deref() else {
+                    return Err(instance.error(AdfReflectionErrorKind::MissingName));
+                };
+                instance
+                    .to_value(&types, context)
+                    .map(|value| (name, value))
+                    .map_err(|error| error.member(name))
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         // Create final instance buffers
         for instance in instances {
-            context.write_instance(&instance.0, &instance.1, &mut result);
+            context.write_instance(&instance.0, &instance.1, &mut result)?;
         }
 
-        result
+        Ok(result)
     }
 }
 
-fn collect_types<'a>(values: impl Iterator<Item = &'a AdfReflectedValue>) -> HashSet<u32> {
-    let mut default = HashSet::<u32>::default();
-    fold_values(&mut default, values);
-    default
-}
-
-fn fold_values<'a>(
-    types: &mut HashSet<u32>,
+fn collect_types<'a>(
     values: impl Iterator<Item = &'a AdfReflectedValue>,
-) -> &mut HashSet<u32> {
-    values.fold(types, |types, value| insert_value(types, value))
+    limits: AdfReflectionLimits,
+) -> Result<HashSet<u32>, AdfReflectionError> {
+    let mut types = HashSet::<u32>::default();
+    for value in values {
+        insert_value(&mut types, value, &mut AdfReflectionBudget::new(limits))?;
+    }
+    Ok(types)
 }
 
-fn insert_value<'a>(
-    types: &'a mut HashSet<u32>,
+fn insert_value(
+    types: &mut HashSet<u32>,
     value: &AdfReflectedValue,
-) -> &'a mut HashSet<u32> {
-    let types = match &value.1 {
-        AdfReflectedPrimitive::Structure(values) => fold_values(types, values.iter()),
-        AdfReflectedPrimitive::Pointer(value) => insert_value(types, &value),
-        AdfReflectedPrimitive::Array(values) => fold_values(types, values.iter()),
-        AdfReflectedPrimitive::InlineArray(values) => fold_values(types, values.iter()),
-        AdfReflectedPrimitive::Deferred(value) => insert_value(types, &value),
-        _ => types,
-    };
-    insert(types, value.0)
-}
-
-fn insert(types: &mut HashSet<u32>, type_hash: u32) -> &mut HashSet<u32> {
-    types.insert(type_hash);
-    types
+    budget: &mut AdfReflectionBudget,
+) -> Result<(), AdfReflectionError> {
+    let error = |kind| AdfReflectionError::new(format!("{:#010x}", value.0), None, kind);
+    budget.enter().map_err(error)?;
+    match &value.1 {
+        AdfReflectedPrimitive::Structure(values) | AdfReflectedPrimitive::InlineArray(values) => {
+            for value in values {
+                insert_value(types, value, budget)?;
+            }
+        }
+        AdfReflectedPrimitive::Array(values) => {
+            budget.follow().map_err(error)?;
+            for value in values.iter() {
+                insert_value(types, value, budget)?;
+            }
+        }
+        AdfReflectedPrimitive::Pointer(Some(value))
+        | AdfReflectedPrimitive::Recursive(Some(value))
+        | AdfReflectedPrimitive::Deferred(Some(value)) => {
+            budget.follow().map_err(error)?;
+            insert_value(types, value, budget)?;
+        }
+        _ => {}
+    }
+    budget.leave();
+    types.insert(value.0);
+    Ok(())
 }
 
-fn type_name(type_hash: u32, context: &AdfReflectionContext) -> Option<String> {
+pub(crate) fn type_name(type_hash: u32, context: &AdfReflectionContext) -> Option<String> {
     context
         .get_type_by_hash(type_hash)
         .and_then(|type_info| match type_info.primitive {
@@ -228,12 +278,15 @@ pub struct AdfXmlType {
     pub type_hash: u32,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct AdfXmlValue {
     #[serde(rename = "@name", skip_serializing_if = "Option::is_none", default)]
     pub name: Option<String>,
     #[serde(rename = "@type")]
     pub type_name: String,
+    // The string a string hash was computed from, used instead of the value
+    #[serde(rename = "@string", skip_serializing_if = "Option::is_none", default)]
+    pub string: Option<String>,
     #[serde(rename = "member", default)]
     pub members: Vec<AdfXmlValue>,
     #[serde(rename = "value", default)]
@@ -242,76 +295,170 @@ pub struct AdfXmlValue {
     pub value: String,
 }
 
+// State shared by every value of an export
+struct AdfXmlExport<'a> {
+    names: &'a HashMap<u32, String>,
+    omit_defaults: bool,
+    // Defaults of members, by the hash of their structure and their index
+    defaults: HashMap<(u32, usize), Option<AdfReflectedValue>>,
+    hashes: Option<&'a HashList>,
+    unresolved: BTreeSet<u64>,
+    budget: AdfReflectionBudget,
+}
+
+impl<'a> AdfXmlExport<'a> {
+    fn new(
+        context: &AdfReflectionContext,
+        names: &'a HashMap<u32, String>,
+        omit_defaults: bool,
+        hashes: Option<&'a HashList>,
+    ) -> Self {
+        Self {
+            names,
+            omit_defaults,
+            defaults: HashMap::new(),
+            hashes,
+            unresolved: BTreeSet::new(),
+            budget: AdfReflectionBudget::new(context.limits()),
+        }
+    }
+
+    fn is_default(
+        &mut self,
+        context: &AdfReflectionContext,
+        type_info: &AdfType,
+        index: usize,
+        value: &AdfReflectedValue,
+    ) -> bool {
+        self.defaults
+            .entry((type_info.type_hash, index))
+            .or_insert_with(|| context.member_default_value(&type_info.members[index]))
+            .as_ref()
+            .is_some_and(|default| default == value)
+    }
+}
+
 impl AdfXmlValue {
     pub fn from_value(
         value: &AdfReflectedValue,
         context: &AdfReflectionContext,
         names: &HashMap<u32, String>,
-    ) -> Self {
-        // TODO: Throw an error instead of unwrapping
-        let type_info = context.get_type_by_hash(value.0).unwrap();
-        let type_name = names.get(&value.0).unwrap().clone();
+        omit_defaults: bool,
+    ) -> Result<Self, AdfReflectionError> {
+        let mut export = AdfXmlExport::new(context, names, omit_defaults, None);
+        Self::from_value_in(value, context, &mut export)
+    }
+
+    fn from_value_in(
+        value: &AdfReflectedValue,
+        context: &AdfReflectionContext,
+        export: &mut AdfXmlExport<'_>,
+    ) -> Result<Self, AdfReflectionError> {
+        let (Some(type_info), Some(type_name)) = (
+            context.get_type_by_hash(value.0),
+            export.names.get(&value.0),
+        ) else {
+            return Err(AdfReflectionError::new(
+                format!("{:#010x}", value.0),
+                None,
+                AdfReflectionErrorKind::MissingType(value.0),
+            ));
+        };
+
+        let error = |kind| AdfReflectionError::new(type_name.as_str(), None, kind);
+        export.budget.enter().map_err(error)?;
 
         let mut result = Self {
-            type_name,
+            type_name: type_name.clone(),
             ..Default::default()
         };
 
         match &value.1 {
             AdfReflectedPrimitive::Scalar(scalar) => {
-                result.value = scalar_string(scalar);
+                result.value = scalar.to_string();
             }
             AdfReflectedPrimitive::Structure(values) => {
                 result.members.reserve(values.len());
-                for (name, value) in type_info
-                    .members
-                    .iter()
-                    .map(|x| x.name.as_ref())
-                    .zip(values.iter())
+                for (index, (member, value)) in
+                    type_info.members.iter().zip(values.iter()).enumerate()
                 {
-                    result.members.push(Self::from_value_named(
-                        value,
-                        name.to_string(),
-                        context,
-                        names,
-                    ));
+                    if export.omit_defaults && export.is_default(context, type_info, index, value) {
+                        continue;
+                    }
+                    let mut value = Self::from_value_in(value, context, export)
+                        .map_err(|error| error.member(member.name.as_str()))?;
+                    value.name = Some(member.name.to_string());
+                    result.members.push(value);
                 }
             }
-            AdfReflectedPrimitive::Pointer(value) => {
-                result.values.push(Self::from_value(value, context, names));
-            }
             AdfReflectedPrimitive::Array(values) => {
-                result.values.reserve(values.len());
-                for value in values.iter() {
-                    result.values.push(Self::from_value(value, context, names));
-                }
+                export.budget.follow().map_err(error)?;
+                result.values = Self::from_elements(values, context, export)?;
             }
             AdfReflectedPrimitive::InlineArray(values) => {
-                result.values.reserve(values.len());
-                for value in values.iter() {
-                    result.values.push(Self::from_value(value, context, names));
-                }
+                result.values = Self::from_elements(values, context, export)?;
             }
             AdfReflectedPrimitive::String(string) => {
                 result.value = string.to_string();
             }
             AdfReflectedPrimitive::Bitfield(scalar) => {
-                result.value = scalar_string(scalar);
+                result.value = scalar.to_string();
             }
             AdfReflectedPrimitive::Enumeration(scalar) => {
-                // TODO: Write enum value name / bitmask
-                result.value = scalar_string(scalar);
+                // Values which weren't declared are written as numbers
+                result.value = type_info
+                    .enumeration_name(scalar)
+                    .map_or_else(|| scalar.to_string(), str::to_owned);
             }
             AdfReflectedPrimitive::StringHash(scalar) => {
-                // TODO: Write string instead of scalar if we can
-                result.value = scalar_string(scalar);
+                // With a hash list, hashes are written as their string, or in hex when it
+                // doesn't have them; without one they're written as numbers
+                if let Some(hashes) = export.hashes {
+                    let hash = scalar
+                        .to_integer()
+                        .and_then(|x| u64::try_from(x).ok())
+                        .unwrap_or_default();
+                    let string = u32::try_from(hash)
+                        .ok()
+                        .and_then(|hash| hashes.find_string(HashString::new(hash)));
+                    if let Some(string) = string {
+                        result.string = Some(string.clone());
+                    } else {
+                        result.value = hash_string(hash);
+                        export.unresolved.insert(hash);
+                    }
+                } else {
+                    result.value = scalar.to_string();
+                }
             }
-            AdfReflectedPrimitive::Deferred(value) => {
-                result.values.push(Self::from_value(value, context, names));
+            AdfReflectedPrimitive::Pointer(value)
+            | AdfReflectedPrimitive::Recursive(value)
+            | AdfReflectedPrimitive::Deferred(value) => {
+                if let Some(value) = value {
+                    export.budget.follow().map_err(error)?;
+                    result
+                        .values
+                        .push(Self::from_value_in(value, context, export)?);
+                }
             }
         };
 
-        result
+        export.budget.leave();
+        Ok(result)
+    }
+
+    fn from_elements(
+        values: &[AdfReflectedValue],
+        context: &AdfReflectionContext,
+        export: &mut AdfXmlExport<'_>,
+    ) -> Result<Vec<Self>, AdfReflectionError> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                Self::from_value_in(value, context, export).map_err(|error| error.element(index))
+            })
+            .collect()
     }
 
     pub fn from_value_named(
@@ -319,120 +466,489 @@ impl AdfXmlValue {
         name: String,
         context: &AdfReflectionContext,
         names: &HashMap<u32, String>,
-    ) -> Self {
-        let mut result = Self::from_value(value, context, names);
+        omit_defaults: bool,
+    ) -> Result<Self, AdfReflectionError> {
+        let mut result = Self::from_value(value, context, names, omit_defaults)?;
         result.name = Some(name);
-        result
+        Ok(result)
     }
 
     pub fn to_value(
         &self,
         types: &HashMap<&str, u32>,
         context: &AdfReflectionContext,
-    ) -> AdfReflectedValue {
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
+        self.to_value_in(
+            types,
+            context,
+            &mut AdfReflectionBudget::new(context.limits()),
+        )
+    }
+
+    fn to_value_in(
+        &self,
+        types: &HashMap<&str, u32>,
+        context: &AdfReflectionContext,
+        budget: &mut AdfReflectionBudget,
+    ) -> Result<AdfReflectedValue, AdfReflectionError> {
         let Some(type_info) = types
             .get(self.type_name.as_str())
             .and_then(|&type_hash| context.get_type_by_hash(type_hash))
         else {
-            todo!("failed to find type: {}", self.type_name);
+            return Err(self.error(AdfReflectionErrorKind::MissingTypeName(
+                self.type_name.clone(),
+            )));
+        };
+
+        budget.enter().map_err(|kind| self.error(kind))?;
+
+        let scalar = || scalar_value(&self.value, type_info).map_err(|kind| self.error(kind));
+        let elements = |budget: &mut AdfReflectionBudget| {
+            self.values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    value
+                        .to_value_in(types, context, budget)
+                        .map_err(|error| error.element(index))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let target = |budget: &mut AdfReflectionBudget| {
+            self.values
+                .first()
+                .map(|value| {
+                    budget.follow().map_err(|kind| self.error(kind))?;
+                    value.to_value_in(types, context, budget).map(Into::into)
+                })
+                .transpose()
         };
 
         let primitive = match type_info.primitive {
-            AdfPrimitive::Scalar => {
-                AdfReflectedPrimitive::Scalar(scalar_value(&self.value, type_info))
-            }
+            AdfPrimitive::Scalar => AdfReflectedPrimitive::Scalar(scalar()?),
+            // Members missing from the XML take their default
             AdfPrimitive::Structure => AdfReflectedPrimitive::Structure(
-                self.members
-                    .iter()
-                    .map(|member| member.to_value(types, context))
-                    .collect(),
-            ),
-            AdfPrimitive::Pointer => AdfReflectedPrimitive::Pointer(
-                self.values
-                    .get(0)
-                    .expect("pointer must have only one value")
-                    .to_value(types, context)
-                    .into(),
-            ),
-            AdfPrimitive::Array => AdfReflectedPrimitive::Array(
-                self.values
-                    .iter()
-                    .map(|value| value.to_value(types, context))
-                    .collect::<Vec<_>>()
-                    .into(),
-            ),
-            AdfPrimitive::InlineArray => AdfReflectedPrimitive::InlineArray(
-                self.values
+                type_info
+                    .members
                     .iter()
-                    .map(|value| value.to_value(types, context))
-                    .collect(),
+                    .map(|member| {
+                        match self
+                            .members
+                            .iter()
+                            .find(|value| value.name.as_deref() == Some(member.name.as_str()))
+                        {
+                            Some(value) => value
+                                .to_value_in(types, context, budget)
+                                .map_err(|error| error.member(member.name.as_str())),
+                            None => context.member_default_value(member).ok_or_else(|| {
+                                self.error(AdfReflectionErrorKind::MissingMember(
+                                    member.name.to_string(),
+                                ))
+                            }),
+                        }
+                    })
+                    .collect::<Result<_, _>>()?,
             ),
-            AdfPrimitive::String => AdfReflectedPrimitive::String(self.value.clone().into()),
-            AdfPrimitive::Recursive => todo!("recursive is not yet supported!"),
-            AdfPrimitive::Bitfield => {
-                AdfReflectedPrimitive::Bitfield(scalar_value(&self.value, type_info))
+            // Null pointers, recursive and deferred values have no value
+            AdfPrimitive::Pointer => AdfReflectedPrimitive::Pointer(target(budget)?),
+            AdfPrimitive::Array => {
+                budget.follow().map_err(|kind| self.error(kind))?;
+                AdfReflectedPrimitive::Array(elements(budget)?.into())
             }
+            AdfPrimitive::InlineArray => AdfReflectedPrimitive::InlineArray(elements(budget)?),
+            AdfPrimitive::String => AdfReflectedPrimitive::String(self.value.clone().into()),
+            AdfPrimitive::Recursive => AdfReflectedPrimitive::Recursive(target(budget)?),
+            AdfPrimitive::Bitfield => AdfReflectedPrimitive::Bitfield(scalar()?),
+            // Either the name or the number of a value is accepted
             AdfPrimitive::Enumeration => {
-                AdfReflectedPrimitive::Enumeration(scalar_value(&self.value, type_info))
+                AdfReflectedPrimitive::Enumeration(match type_info.enumeration_value(&self.value) {
+                    Some(value) => value.coerce(type_info).map_err(|kind| self.error(kind))?,
+                    None => scalar()?,
+                })
             }
-            AdfPrimitive::StringHash => {
-                AdfReflectedPrimitive::StringHash(scalar_value(&self.value, type_info))
+            AdfPrimitive::StringHash => AdfReflectedPrimitive::StringHash(self.hash(type_info)?),
+            AdfPrimitive::Deferred => AdfReflectedPrimitive::Deferred(target(budget)?),
+        };
+        budget.leave();
+        Ok(AdfReflectedValue(type_info.type_hash, primitive))
+    }
+
+    // Strings are hashed, and values may be given in hex
+    fn hash(&self, type_info: &AdfType) -> Result<AdfReflectedScalar, AdfReflectionError> {
+        let hash = match (&self.string, self.value.strip_prefix("0x")) {
+            (Some(string), _) => AdfReflectedScalar::U32(hash_little32(string.as_bytes())),
+            (None, Some(hex)) => u64::from_str_radix(hex, 16)
+                .ok()
+                .map(AdfReflectedScalar::U64)
+                .ok_or_else(|| {
+                    self.error(AdfReflectionErrorKind::InvalidText(self.value.clone()))
+                })?,
+            (None, None) => {
+                return scalar_value(&self.value, type_info).map_err(|kind| self.error(kind))
             }
-            AdfPrimitive::Deferred => AdfReflectedPrimitive::Deferred(
-                self.values
-                    .get(0)
-                    .expect("deferred must have only one value")
-                    .to_value(types, context)
-                    .into(),
-            ),
         };
-        AdfReflectedValue(type_info.type_hash, primitive)
+        hash.coerce(type_info).map_err(|kind| self.error(kind))
     }
-}
 
-fn scalar_string(scalar: &AdfReflectedScalar) -> String {
-    match scalar {
-        AdfReflectedScalar::U8(value) => format!("{value}"),
-        AdfReflectedScalar::I8(value) => format!("{value}"),
-        AdfReflectedScalar::U16(value) => format!("{value}"),
-        AdfReflectedScalar::I16(value) => format!("{value}"),
-        AdfReflectedScalar::U32(value) => format!("{value}"),
-        AdfReflectedScalar::I32(value) => format!("{value}"),
-        AdfReflectedScalar::F32(value) => format!("{value}"),
-        AdfReflectedScalar::U64(value) => format!("{value}"),
-        AdfReflectedScalar::I64(value) => format!("{value}"),
-        AdfReflectedScalar::F64(value) => format!("{value}"),
+    fn error(&self, kind: AdfReflectionErrorKind) -> AdfReflectionError {
+        AdfReflectionError::new(self.type_name.as_str(), None, kind)
     }
 }
 
-fn scalar_value(scalar: &str, type_info: &AdfType) -> AdfReflectedScalar {
+fn hash_string(hash: u64) -> String {
+    format!("{hash:#010x}")
+}
+
+fn scalar_value(
+    scalar: &str,
+    type_info: &AdfType,
+) -> Result<AdfReflectedScalar, AdfReflectionErrorKind> {
     macro_rules! parse {
         ($t:tt) => {
-            match scalar.parse::<$t>() {
-                Ok(value) => value,
-                _ => todo!("failed to parse {}: {}", stringify!($t), scalar),
-            }
+            scalar
+                .parse::<$t>()
+                .map_err(|_| AdfReflectionErrorKind::InvalidText(scalar.to_owned()))?
         };
     }
-    match type_info.scalar_type {
-        AdfScalarType::Signed => match type_info.size {
-            1 => AdfReflectedScalar::I8(parse!(i8)),
-            2 => AdfReflectedScalar::I16(parse!(i16)),
-            4 => AdfReflectedScalar::I32(parse!(i32)),
-            8 => AdfReflectedScalar::I64(parse!(i64)),
-            size => todo!("unexpected scalar size: {}", size),
-        },
-        AdfScalarType::Unsigned => match type_info.size {
-            1 => AdfReflectedScalar::U8(parse!(u8)),
-            2 => AdfReflectedScalar::U16(parse!(u16)),
-            4 => AdfReflectedScalar::U32(parse!(u32)),
-            8 => AdfReflectedScalar::U64(parse!(u64)),
-            size => todo!("unexpected scalar size: {}", size),
-        },
-        AdfScalarType::Float => match type_info.size {
-            4 => AdfReflectedScalar::F32(parse!(f32)),
-            8 => AdfReflectedScalar::F64(parse!(f64)),
-            size => todo!("unexpected scalar size: {}", size),
-        },
+    Ok(match (type_info.scalar_type, type_info.size) {
+        (AdfScalarType::Signed, 1) => AdfReflectedScalar::I8(parse!(i8)),
+        (AdfScalarType::Signed, 2) => AdfReflectedScalar::I16(parse!(i16)),
+        (AdfScalarType::Signed, 4) => AdfReflectedScalar::I32(parse!(i32)),
+        (AdfScalarType::Signed, 8) => AdfReflectedScalar::I64(parse!(i64)),
+        (AdfScalarType::Unsigned, 1) => AdfReflectedScalar::U8(parse!(u8)),
+        (AdfScalarType::Unsigned, 2) => AdfReflectedScalar::U16(parse!(u16)),
+        (AdfScalarType::Unsigned, 4) => AdfReflectedScalar::U32(parse!(u32)),
+        (AdfScalarType::Unsigned, 8) => AdfReflectedScalar::U64(parse!(u64)),
+        (AdfScalarType::Float, 4) => AdfReflectedScalar::F32(parse!(f32)),
+        (AdfScalarType::Float, 8) => AdfReflectedScalar::F64(parse!(f64)),
+        (scalar_type, size) => {
+            return Err(AdfReflectionErrorKind::InvalidScalar { scalar_type, size });
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        adf::{built_in_types, AdfTypeInfo},
+        common::NullString,
+    };
+
+    // `Known` is in the hash list used by the tests, `Unknown` isn't
+    fn file() -> AdfFile {
+        let string_hash = AdfType {
+            primitive: AdfPrimitive::StringHash,
+            size: 4,
+            alignment: 4,
+            type_hash: HashString::HASH,
+            name: NullString::from(HashString::NAME).into(),
+            scalar_type: AdfScalarType::Unsigned,
+            ..Default::default()
+        };
+        let type_def = AdfType::structure("Test")
+            .member("Known", &string_hash)
+            .member("Unknown", &string_hash)
+            .build();
+        let mut file = AdfFile {
+            types: vec![string_hash, type_def.clone()],
+            ..Default::default()
+        };
+        let buffer = file
+            .new_instance_from_type("Test", &type_def)
+            .unwrap()
+            .buffer
+            .to_mut();
+        buffer[0..4].copy_from_slice(&hash_little32(b"Known").to_le_bytes());
+        buffer[4..8].copy_from_slice(&hash_little32(b"Unknown").to_le_bytes());
+        file
+    }
+
+    fn export(file: &AdfFile, hashes: Option<&HashList>) -> AdfXml {
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(file);
+        match hashes {
+            Some(hashes) => AdfXml::with_hashes(file, &context, "adf", false, hashes),
+            None => AdfXml::new(file, &context, "adf", false),
+        }
+        .unwrap()
+    }
+
+    fn round_trip(file: &AdfFile, xml: &AdfXml) {
+        let mut context = AdfReflectionContext::default();
+        context.load_types_from_file(file);
+        let converted = xml.convert(&context).unwrap();
+        assert_eq!(
+            converted.instances[0].buffer[..8],
+            file.instances[0].buffer[..8]
+        );
+    }
+
+    #[test]
+    fn string_hashes_are_numbers_without_hash_list() {
+        let file = file();
+        let xml = export(&file, None);
+        let members = &xml.instances[0].members;
+        assert_eq!(members[0].value, hash_little32(b"Known").to_string());
+        assert_eq!(members[0].string, None);
+        assert_eq!(members[1].value, hash_little32(b"Unknown").to_string());
+        assert!(xml.unresolved.is_empty());
+        round_trip(&file, &xml);
+    }
+
+    #[test]
+    fn string_hashes_are_resolved_with_hash_list() {
+        let file = file();
+        let mut hashes = HashList::new();
+        hashes.insert_string("Known");
+        let xml = export(&file, Some(&hashes));
+        let members = &xml.instances[0].members;
+        let unknown = format!("{:#010x}", hash_little32(b"Unknown"));
+        assert_eq!(members[0].string.as_deref(), Some("Known"));
+        assert_eq!(members[1].value, unknown);
+        assert_eq!(xml.unresolved, [unknown]);
+        round_trip(&file, &xml);
+    }
+
+    // Written and parsed as adf_converter does
+    fn text_round_trip(xml: &AdfXml) -> AdfXml {
+        let mut buffer = String::new();
+        let mut serializer =
+            quick_xml::se::Serializer::with_root(&mut buffer, Some("adf")).unwrap();
+        serializer.indent('\t', 1);
+        serializer.expand_empty_elements(true);
+        xml.serialize(serializer).unwrap();
+        let mut deserializer = quick_xml::de::Deserializer::from_str(&buffer);
+        AdfXml::deserialize(&mut deserializer).unwrap()
+    }
+
+    // Exports the value as the only instance of a file, then converts it back through text
+    fn xml_round_trip(context: &AdfReflectionContext, value: &AdfReflectedValue) -> AdfXml {
+        let mut file = AdfFile::default();
+        context.write_instance(&"Value", value, &mut file).unwrap();
+        let xml = text_round_trip(&AdfXml::new(&file, context, "adf", false).unwrap());
+        let converted = xml.convert(context).unwrap();
+        assert_eq!(
+            context.read_instance(&converted.instances[0]).unwrap(),
+            *value
+        );
+        xml
+    }
+
+    fn uint32(value: u32) -> AdfReflectedValue {
+        AdfReflectedValue(
+            <u32 as AdfTypeInfo>::HASH,
+            AdfReflectedPrimitive::Scalar(AdfReflectedScalar::U32(value)),
+        )
+    }
+
+    // Holder { Data: void, Scalar: void, Empty: void }, whose values target `Target` and u32
+    #[test]
+    fn deferred_values_are_written_with_their_type() {
+        let uint32_type = built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap();
+        let deferred = built_in_types()
+            .iter()
+            .find(|x| x.primitive == AdfPrimitive::Deferred)
+            .unwrap();
+        let target = AdfType::structure("Target")
+            .member("Value", uint32_type)
+            .build();
+        let holder = AdfType::structure("Holder")
+            .member("Data", deferred)
+            .member("Scalar", deferred)
+            .member("Empty", deferred)
+            .build();
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&AdfFile {
+            types: vec![target.clone(), holder.clone()],
+            ..Default::default()
+        });
+
+        let deferred = |value: Option<AdfReflectedValue>| {
+            AdfReflectedValue(
+                deferred.type_hash,
+                AdfReflectedPrimitive::Deferred(value.map(Arc::new)),
+            )
+        };
+        let value = AdfReflectedValue(
+            holder.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                deferred(Some(AdfReflectedValue(
+                    target.type_hash,
+                    AdfReflectedPrimitive::Structure(vec![uint32(7)]),
+                ))),
+                deferred(Some(uint32(9))),
+                deferred(None),
+            ]),
+        );
+        let xml = xml_round_trip(&context, &value);
+
+        let members = &xml.instances[0].members;
+        assert_eq!(members[0].type_name, "Any");
+        assert_eq!(members[0].values[0].type_name, "Target");
+        assert_eq!(members[0].values[0].members[0].value, "7");
+        assert_eq!(members[1].values[0].type_name, "u32");
+        assert_eq!(members[1].values[0].value, "9");
+        assert!(members[2].values.is_empty());
+    }
+
+    // Tree { Value: uint32, Left: Recursive[Tree], Right: Recursive[Tree] }, with both branches
+    // of the root sharing one subtree
+    #[test]
+    fn recursive_trees_are_nested_values() {
+        let uint32_type = built_in_types()
+            .iter()
+            .find(|x| x.type_hash == <u32 as AdfTypeInfo>::HASH)
+            .unwrap();
+        let mut recursive = AdfType::pointer(&AdfType::structure("Tree").build());
+        recursive.primitive = AdfPrimitive::Recursive;
+        recursive.type_hash = recursive.compute_type_hash();
+        let tree = AdfType::structure("Tree")
+            .member("Value", uint32_type)
+            .member("Left", &recursive)
+            .member("Right", &recursive)
+            .build();
+        recursive.element_type_hash = tree.type_hash;
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&AdfFile {
+            types: vec![recursive.clone(), tree.clone()],
+            ..Default::default()
+        });
+
+        let branch = |value, left: Option<AdfReflectedValue>, right: Option<AdfReflectedValue>| {
+            let recursive = |value: Option<AdfReflectedValue>| {
+                AdfReflectedValue(
+                    recursive.type_hash,
+                    AdfReflectedPrimitive::Recursive(value.map(Arc::new)),
+                )
+            };
+            AdfReflectedValue(
+                tree.type_hash,
+                AdfReflectedPrimitive::Structure(vec![
+                    uint32(value),
+                    recursive(left),
+                    recursive(right),
+                ]),
+            )
+        };
+        let shared = branch(2, Some(branch(3, None, None)), None);
+        let value = branch(1, Some(shared.clone()), Some(shared));
+        let xml = xml_round_trip(&context, &value);
+
+        assert!(xml
+            .types
+            .iter()
+            .any(|x| x.type_name == "Recursive[Tree]" && x.type_hash == recursive.type_hash));
+        let members = &xml.instances[0].members;
+        assert_eq!(members[1].type_name, "Recursive[Tree]");
+        let left = &members[1].values[0];
+        assert_eq!(left.type_name, "Tree");
+        assert_eq!(left.members[0].value, "2");
+        assert_eq!(left.members[1].values[0].members[0].value, "3");
+        assert!(left.members[2].values.is_empty());
+        assert_eq!(members[2].values[0].members[0].value, "2");
+    }
+
+    // Setting { Gear: Gear, Spare: Gear, Mask: Mask }, where `Mask` is unsigned and declares -1
+    fn enumerations() -> (AdfReflectionContext, AdfType) {
+        let gear = AdfType::enumeration("Gear", [("Low", 0), ("High", 1)]);
+        let mut mask = AdfType::enumeration("Mask", [("None", 0), ("All", -1)]);
+        mask.scalar_type = AdfScalarType::Unsigned;
+        let setting = AdfType::structure("Setting")
+            .member("Gear", &gear)
+            .member("Spare", &gear)
+            .member("Mask", &mask)
+            .build();
+        let mut context = AdfReflectionContext::from_extension("").unwrap();
+        context.load_types_from_file(&AdfFile {
+            types: vec![gear, mask, setting.clone()],
+            ..Default::default()
+        });
+        (context, setting)
+    }
+
+    fn setting(setting: &AdfType, gear: i32, spare: i32, mask: u32) -> AdfReflectedValue {
+        let enumeration = |index: usize, scalar| {
+            AdfReflectedValue(
+                setting.members[index].type_hash,
+                AdfReflectedPrimitive::Enumeration(scalar),
+            )
+        };
+        AdfReflectedValue(
+            setting.type_hash,
+            AdfReflectedPrimitive::Structure(vec![
+                enumeration(0, AdfReflectedScalar::I32(gear)),
+                enumeration(1, AdfReflectedScalar::I32(spare)),
+                enumeration(2, AdfReflectedScalar::U32(mask)),
+            ]),
+        )
+    }
+
+    #[test]
+    fn enumerations_are_written_by_name() {
+        let (context, type_info) = enumerations();
+        let xml = xml_round_trip(&context, &setting(&type_info, 1, 0, u32::MAX));
+        let values: Vec<&str> = xml.instances[0]
+            .members
+            .iter()
+            .map(|x| x.value.as_str())
+            .collect();
+        assert_eq!(values, ["High", "Low", "All"]);
+    }
+
+    #[test]
+    fn undeclared_enumerations_are_written_as_numbers() {
+        let (context, type_info) = enumerations();
+        let xml = xml_round_trip(&context, &setting(&type_info, 5, -2, 7));
+        let values: Vec<&str> = xml.instances[0]
+            .members
+            .iter()
+            .map(|x| x.value.as_str())
+            .collect();
+        assert_eq!(values, ["5", "-2", "7"]);
+    }
+
+    #[test]
+    fn enumerations_are_read_by_name_or_number() {
+        let (context, type_info) = enumerations();
+        let mut file = AdfFile::default();
+        let value = setting(&type_info, 0, 0, 0);
+        context.write_instance(&"Value", &value, &mut file).unwrap();
+        let mut xml = AdfXml::new(&file, &context, "adf", false).unwrap();
+
+        let types: HashMap<&str, u32> = xml
+            .types
+            .iter()
+            .map(|x| (x.type_name.as_str(), x.type_hash))
+            .collect();
+        let mut read = |gear: &str, spare: &str, mask: &str| {
+            let mut instance = xml.instances[0].clone();
+            for (member, text) in instance.members.iter_mut().zip([gear, spare, mask]) {
+                member.value = text.into();
+            }
+            instance.to_value(&types, &context)
+        };
+        assert_eq!(
+            read("High", "1", "All").unwrap(),
+            setting(&type_info, 1, 1, u32::MAX)
+        );
+        assert_eq!(
+            read("3", "Low", "4294967295").unwrap(),
+            setting(&type_info, 3, 0, u32::MAX)
+        );
+        let error = read("Neutral", "Low", "None").unwrap_err();
+        assert_eq!(error.path.to_string(), "Gear");
+        // Unsigned values are only named by their declaration, not the number it was declared as
+        let error = read("Low", "Low", "-1").unwrap_err();
+        assert!(matches!(error.kind, AdfReflectionErrorKind::InvalidText(text) if text == "-1"));
+
+        xml.instances[0].members[2].value = "All".into();
+        let converted = xml.convert(&context).unwrap();
+        assert_eq!(converted.instances[0].buffer[8..12], [0xFF; 4]);
     }
 }
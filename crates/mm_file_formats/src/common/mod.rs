@@ -4,8 +4,34 @@ pub use length::*;
 pub mod null_string;
 pub use null_string::*;
 
+#[inline]
+pub fn read_pod<T: bytemuck::Pod>(bytes: &[u8], endian: binrw::Endian) -> T {
+    let mut value: T = bytemuck::pod_read_unaligned(bytes);
+    if endian != binrw::Endian::NATIVE {
+        bytemuck::bytes_of_mut(&mut value).reverse();
+    }
+    value
+}
+
+#[inline]
+pub fn write_pod<T: bytemuck::Pod>(bytes: &mut [u8], value: T, endian: binrw::Endian) {
+    bytes.copy_from_slice(bytemuck::bytes_of(&value));
+    if endian != binrw::Endian::NATIVE {
+        bytes.reverse();
+    }
+}
+
+#[inline]
+pub const fn endian_from_big(big_endian: bool) -> binrw::Endian {
+    if big_endian {
+        binrw::Endian::Big
+    } else {
+        binrw::Endian::Little
+    }
+}
+
 #[inline(always)]
-const fn align(value: u64, alignment: u64) -> u64 {
+pub(crate) const fn align(value: u64, alignment: u64) -> u64 {
     let align = alignment - 1;
     (value + align) & !align
 }
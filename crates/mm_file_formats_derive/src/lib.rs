@@ -0,0 +1,322 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, Ident, LitInt, LitStr,
+    PathArguments, Type,
+};
+
+// Members are laid out in field order, the same as `AdfStructureBuilder` does, so the size,
+// alignment and hash all follow from the field types. `#[adf(name = "...")]` sets the type's name
+// when it differs from the structure's, and `#[adf(hash = ...)]` checks the hash at compile time.
+// `#[adf(type = "...")]` on a field sets the member's type when it isn't the field's, like a
+// pointer to a fixed number of elements being read as `Option<Arc<[u32; 29]>>`
+#[proc_macro_derive(AdfTypeInfo, attributes(adf))]
+pub fn derive_type_info(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    AdfStructure::parse(&input)
+        .map_or_else(Error::into_compile_error, |structure| structure.type_info())
+        .into()
+}
+
+#[proc_macro_derive(AdfRead, attributes(adf))]
+pub fn derive_read(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    AdfStructure::parse(&input)
+        .map_or_else(Error::into_compile_error, |structure| structure.read())
+        .into()
+}
+
+#[proc_macro_derive(AdfWrite, attributes(adf))]
+pub fn derive_write(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    AdfStructure::parse(&input)
+        .map_or_else(Error::into_compile_error, |structure| structure.write())
+        .into()
+}
+
+struct AdfStructure<'a> {
+    ident: &'a Ident,
+    name: String,
+    hash: Option<LitInt>,
+    fields: Vec<(&'a Ident, Type)>,
+}
+
+impl<'a> AdfStructure<'a> {
+    fn parse(input: &'a DeriveInput) -> syn::Result<Self> {
+        // Hashes are built from constants, which can't depend on generic parameters
+        if !input.generics.params.is_empty() {
+            return Err(Error::new_spanned(
+                &input.generics,
+                "generic structures aren't supported",
+            ));
+        }
+        let Data::Struct(data) = &input.data else {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "only structures are supported",
+            ));
+        };
+        let fields = match &data.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .filter_map(|field| field.ident.as_ref().map(|ident| (ident, field)))
+                .map(|(ident, field)| {
+                    let mut ty = field.ty.clone();
+                    for attr in field.attrs.iter().filter(|x| x.path().is_ident("adf")) {
+                        attr.parse_nested_meta(|meta| {
+                            if meta.path.is_ident("type") {
+                                ty = meta.value()?.parse::<LitStr>()?.parse()?;
+                                Ok(())
+                            } else {
+                                Err(meta.error("expected `type`"))
+                            }
+                        })?;
+                    }
+                    Ok((ident, ty))
+                })
+                .collect::<syn::Result<_>>()?,
+            Fields::Unit => vec![],
+            Fields::Unnamed(fields) => {
+                return Err(Error::new_spanned(
+                    fields,
+                    "only structures with named fields are supported",
+                ));
+            }
+        };
+
+        let mut name = input.ident.to_string();
+        let mut hash = None;
+        for attr in input.attrs.iter().filter(|x| x.path().is_ident("adf")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("hash") {
+                    hash = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `name` or `hash`"))
+                }
+            })?;
+        }
+
+        Ok(Self {
+            ident: &input.ident,
+            name,
+            hash,
+            fields,
+        })
+    }
+
+    fn type_info(&self) -> TokenStream2 {
+        let Self {
+            ident, name, hash, ..
+        } = self;
+        let members: Vec<_> = self.fields.iter().map(|(_, ty)| member_type(ty)).collect();
+        let hashes = members.iter().map(|x| &x.hash);
+        let indices = 0..members.len();
+        let layout = {
+            let sizes = members.iter().map(|x| &x.size);
+            let alignments = members.iter().map(|x| &x.alignment);
+            quote!(&[#((#sizes, #alignments)),*])
+        };
+        let check = hash.as_ref().map(|hash| {
+            let message = format!("hash of {name} doesn't match its fields");
+            quote! {
+                const _: () = assert!(
+                    <#ident as ::mm_file_formats::adf::AdfTypeInfo>::HASH == #hash,
+                    #message
+                );
+            }
+        });
+
+        // CommonHash, followed by the hash and offset of each member
+        quote! {
+            impl ::mm_file_formats::adf::AdfTypeInfo for #ident {
+                const NAME: &str = #name;
+                const HASH: u32 = ::mm_file_formats::adf::__private::hash_little32(
+                    ::mm_file_formats::adf::__private::concatcp!(
+                        ::mm_file_formats::adf::__private::hash_little32(
+                            ::mm_file_formats::adf::__private::concatcp!(
+                                #name,
+                                ::mm_file_formats::adf::AdfPrimitive::Structure as u32,
+                                ::mm_file_formats::adf::structure_size(#layout),
+                                ::mm_file_formats::adf::structure_alignment(#layout),
+                            )
+                            .as_bytes()
+                        ),
+                        #(#hashes, ::mm_file_formats::adf::structure_offset(#layout, #indices),)*
+                    )
+                    .as_bytes(),
+                );
+                const SIZE: u64 = ::mm_file_formats::adf::structure_size(#layout);
+                const ALIGN: u64 = ::mm_file_formats::adf::structure_alignment(#layout);
+            }
+
+            #check
+        }
+    }
+
+    // Structures are aligned before their first member and padded after their last, so they
+    // take up `SIZE` bytes regardless of how their members are written
+    fn read(&self) -> TokenStream2 {
+        let ident = self.ident;
+        let names = self.fields.iter().map(|(name, _)| name);
+        quote! {
+            impl ::mm_file_formats::adf::AdfRead for #ident {
+                #[inline]
+                fn read<R: ::std::io::Read + ::std::io::Seek>(
+                    reader: &mut R,
+                    endian: ::mm_file_formats::adf::__private::Endian,
+                    references: &mut ::mm_file_formats::adf::AdfReaderReferences,
+                ) -> ::std::result::Result<Self, ::mm_file_formats::adf::AdfReadWriteError> {
+                    use ::mm_file_formats::common::ReaderExt;
+                    let start = reader.align(<Self as ::mm_file_formats::adf::AdfTypeInfo>::ALIGN)?;
+                    let result = Self {
+                        #(#names: ::mm_file_formats::adf::AdfRead::read(reader, endian, references)?,)*
+                    };
+                    reader.seek_absolute(start + <Self as ::mm_file_formats::adf::AdfTypeInfo>::SIZE)?;
+                    Ok(result)
+                }
+            }
+        }
+    }
+
+    fn write(&self) -> TokenStream2 {
+        let ident = self.ident;
+        let names = self.fields.iter().map(|(name, _)| name);
+        quote! {
+            impl ::mm_file_formats::adf::AdfWrite for #ident {
+                #[inline]
+                fn write<W: ::std::io::Write + ::std::io::Seek>(
+                    &self,
+                    writer: &mut W,
+                    endian: ::mm_file_formats::adf::__private::Endian,
+                    references: &mut ::mm_file_formats::adf::AdfWriterReferences,
+                ) -> ::std::result::Result<(), ::mm_file_formats::adf::AdfReadWriteError> {
+                    use ::mm_file_formats::common::WriterExt;
+                    let start = writer.align(<Self as ::mm_file_formats::adf::AdfTypeInfo>::ALIGN)?;
+                    #(::mm_file_formats::adf::AdfWrite::write(&self.#names, writer, endian, references)?;)*
+                    let end = writer.stream_position()?;
+                    writer.pad(
+                        (start + <Self as ::mm_file_formats::adf::AdfTypeInfo>::SIZE).saturating_sub(end),
+                    )?;
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+// Constant expressions for the `AdfTypeInfo` of a member's type
+struct AdfMemberType {
+    name: TokenStream2,
+    hash: TokenStream2,
+    size: TokenStream2,
+    alignment: TokenStream2,
+}
+
+// Pointers, arrays and inline arrays of derived structures can't implement `AdfTypeInfo` outside
+// of this crate, so they're built up from their element the same way `AdfType::pointer` and
+// friends do. Their names don't include the layout, unlike `type_name!`, since they can be nested
+fn member_type(ty: &Type) -> AdfMemberType {
+    let element = container(ty, "Arc", "Vec")
+        .map(|element| (element, "A[", "]", "3168", quote!(16), quote!(8)))
+        .or_else(|| {
+            container(ty, "Option", "Arc")
+                .map(|element| (element, "", "*", "288", quote!(8), quote!(8)))
+        });
+    if let Some((element, prefix, suffix, layout, size, alignment)) = element {
+        let AdfMemberType {
+            name: element_name,
+            hash: element_hash,
+            ..
+        } = member_type(element);
+        let name =
+            quote!(::mm_file_formats::adf::__private::concatcp!(#prefix, #element_name, #suffix));
+        return AdfMemberType {
+            // CommonHash ^ ElementHash
+            hash: quote! {
+                ::mm_file_formats::adf::__private::hash_little32(
+                    ::mm_file_formats::adf::__private::concatcp!(
+                        ::mm_file_formats::adf::__private::hash_little32(
+                            ::mm_file_formats::adf::__private::concatcp!(#name, #layout).as_bytes()
+                        ),
+                        #element_hash,
+                    )
+                    .as_bytes(),
+                )
+            },
+            name,
+            size,
+            alignment,
+        };
+    }
+
+    if let Type::Array(array) = ty {
+        let AdfMemberType {
+            name: element_name,
+            hash: element_hash,
+            size: element_size,
+            alignment: element_alignment,
+        } = member_type(&array.elem);
+        let length = &array.len;
+        let name = quote!(::mm_file_formats::adf::__private::concatcp!("IA[", #element_name, "]"));
+        return AdfMemberType {
+            // CommonHash ^ ElementHash ^ Length
+            hash: quote! {
+                ::mm_file_formats::adf::__private::hash_little32(
+                    ::mm_file_formats::adf::__private::concatcp!(
+                        ::mm_file_formats::adf::__private::hash_little32(
+                            ::mm_file_formats::adf::__private::concatcp!(
+                                #name,
+                                ::mm_file_formats::adf::AdfPrimitive::InlineArray as u32,
+                                #element_size * (#length as u64),
+                                #element_alignment,
+                            )
+                            .as_bytes(),
+                        ),
+                        #element_hash,
+                        (#length as usize),
+                    )
+                    .as_bytes(),
+                )
+            },
+            name,
+            size: quote!(#element_size * (#length as u64)),
+            alignment: element_alignment,
+        };
+    }
+
+    AdfMemberType {
+        name: quote!(<#ty as ::mm_file_formats::adf::AdfTypeInfo>::NAME),
+        hash: quote!(<#ty as ::mm_file_formats::adf::AdfTypeInfo>::HASH),
+        size: quote!(<#ty as ::mm_file_formats::adf::AdfTypeInfo>::SIZE),
+        alignment: quote!(<#ty as ::mm_file_formats::adf::AdfTypeInfo>::ALIGN),
+    }
+}
+
+// The element of `outer<inner<T>>`, like `Arc<Vec<T>>`
+fn container<'a>(ty: &'a Type, outer: &str, inner: &str) -> Option<&'a Type> {
+    argument(argument(ty, outer)?, inner)
+}
+
+fn argument<'a>(ty: &'a Type, name: &str) -> Option<&'a Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match arguments.args.first() {
+        Some(GenericArgument::Type(argument))
+            if segment.ident == name && arguments.args.len() == 1 =>
+        {
+            Some(argument)
+        }
+        _ => None,
+    }
+}
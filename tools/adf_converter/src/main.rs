@@ -1,48 +1,284 @@
-use std::io::Write;
+use std::{io::Write, path::PathBuf};
 
 use anyhow::{bail, Context};
-use binrw::{BinRead, BinWrite};
-use clap::Parser;
+use binrw::BinWrite;
+use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 
-use mm_file_formats::adf::{AdfFile, AdfReflectionContext, AdfXml};
+use mm_file_formats::{
+    adf::{
+        AdfChange, AdfDiffOptions, AdfDifference, AdfFile, AdfLayout, AdfReflectionContext, AdfXml,
+        AdfXmlOverlay,
+    },
+    common::endian_from_big,
+};
+use mm_hashing::HashList;
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    if !args.file.is_file() {
-        bail!("{:?} is not a file", args.file);
+    match &args.command {
+        Some(Command::Merge { files, output }) => merge(&args, files, output),
+        Some(Command::Extract {
+            file,
+            instances,
+            output,
+        }) => extract(&args, file, instances, output),
+        Some(Command::Diff {
+            old,
+            new,
+            tolerance,
+            xml,
+        }) => diff(old, new, *tolerance, *xml),
+        Some(Command::Overlay {
+            overlays,
+            base,
+            output,
+        }) => overlay(&args, overlays, base.as_ref(), output),
+        None => convert(&args),
+    }
+}
+
+fn merge(args: &Args, files: &[PathBuf], output: &PathBuf) -> anyhow::Result<()> {
+    // Merge each file in order, failing on conflicting types or instances
+    let mut result = AdfFile::default();
+    for file in files {
+        let adf = read_adf(file)?;
+        result
+            .merge(&adf)
+            .with_context(|| format!("Failed to merge {file:?}"))?;
+    }
+
+    write_adf(args, &result, output)
+}
+
+fn extract(
+    args: &Args,
+    file: &PathBuf,
+    instances: &[String],
+    output: &PathBuf,
+) -> anyhow::Result<()> {
+    let adf = read_adf(file)?;
+    let result = adf
+        .extract(instances.iter().map(String::as_str))
+        .context("Failed to extract instances")?;
+
+    write_adf(args, &result, output)
+}
+
+fn diff(old: &PathBuf, new: &PathBuf, tolerance: f64, xml: bool) -> anyhow::Result<()> {
+    let old_adf = read_adf(old)?;
+    let new_adf = read_adf(new)?;
+
+    // Load types based on extension, along with those embedded in either file
+    let extension = old
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .context("Failed to determine file extension")?;
+    let mut context = AdfReflectionContext::from_extension(extension)?;
+    context.load_types_from_file(&old_adf);
+    context.load_types_from_file(&new_adf);
+
+    let differences = context
+        .diff_files(&old_adf, &new_adf, &AdfDiffOptions { tolerance })
+        .context("Failed to diff ADFs")?;
+
+    if !xml {
+        for difference in &differences {
+            println!("{difference}");
+        }
+        return Ok(());
+    }
+
+    println!("{}", diff_xml(&differences)?);
+    Ok(())
+}
+
+// Each difference becomes a `change` element, with the old and new values as attributes
+fn diff_xml(differences: &[AdfDifference]) -> anyhow::Result<String> {
+    let changes = differences
+        .iter()
+        .map(|difference| {
+            let (kind, old, new) = match &difference.change {
+                AdfChange::AddedInstance { type_name } => ("added", None, Some(type_name.clone())),
+                AdfChange::RemovedInstance { type_name } => {
+                    ("removed", Some(type_name.clone()), None)
+                }
+                AdfChange::Scalar { old, new } => {
+                    ("scalar", Some(old.to_string()), Some(new.to_string()))
+                }
+                AdfChange::String { old, new } => ("string", Some(old.clone()), Some(new.clone())),
+                AdfChange::ArrayLength { old, new } => {
+                    ("length", Some(old.to_string()), Some(new.to_string()))
+                }
+                AdfChange::Presence { old, new } => {
+                    ("presence", Some(old.to_string()), Some(new.to_string()))
+                }
+                AdfChange::Type { old, new } => ("type", Some(old.clone()), Some(new.clone())),
+            };
+            DiffChange {
+                path: difference.path.to_string(),
+                kind,
+                old,
+                new,
+            }
+        })
+        .collect();
+
+    let mut buffer = String::new();
+    let mut serializer = quick_xml::se::Serializer::with_root(&mut buffer, Some("diff"))?;
+    serializer.indent('\t', 1);
+    DiffXml { changes }.serialize(serializer)?;
+    Ok(buffer)
+}
+
+#[derive(Serialize)]
+struct DiffXml {
+    #[serde(rename = "change")]
+    changes: Vec<DiffChange>,
+}
+
+#[derive(Serialize)]
+struct DiffChange {
+    #[serde(rename = "@path")]
+    path: String,
+    #[serde(rename = "@kind")]
+    kind: &'static str,
+    #[serde(rename = "@old", skip_serializing_if = "Option::is_none")]
+    old: Option<String>,
+    #[serde(rename = "@new", skip_serializing_if = "Option::is_none")]
+    new: Option<String>,
+}
+
+fn overlay(
+    args: &Args,
+    overlays: &[PathBuf],
+    base: Option<&PathBuf>,
+    output: &PathBuf,
+) -> anyhow::Result<()> {
+    let overlays = overlays
+        .iter()
+        .map(|path| {
+            let file =
+                std::fs::File::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+            let mut deserializer =
+                quick_xml::de::Deserializer::from_reader(std::io::BufReader::new(file));
+            let overlay = AdfXmlOverlay::deserialize(&mut deserializer)
+                .with_context(|| format!("Failed to parse {path:?}"))?;
+            Ok((path, overlay))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Unless given, the base is the one named by the overlays, relative to each of them
+    let base = if let Some(base) = base {
+        base.clone()
+    } else {
+        let (path, overlay) = overlays.first().context("No overlays specified")?;
+        let base = path.with_file_name(&overlay.base);
+        for (other_path, other) in &overlays[1..] {
+            if other_path.with_file_name(&other.base) != base {
+                bail!("{path:?} and {other_path:?} name different bases, use --base to pick one");
+            }
+        }
+        base
+    };
+    let extension = base
+        .extension()
+        .and_then(std::ffi::OsStr::to_str)
+        .context("Failed to determine file extension")?;
+
+    let mut adf = read_adf(&base)?;
+    let mut context = AdfReflectionContext::from_extension(extension)?;
+    context.load_types_from_file(&adf);
+
+    // Overlays are applied in order, so later ones win where they overlap
+    for (path, overlay) in &overlays {
+        adf = overlay
+            .apply(&adf, &context)
+            .with_context(|| format!("Failed to apply {path:?}"))?;
+    }
+
+    write_adf(args, &adf, output)
+}
+
+fn read_adf(file: &PathBuf) -> anyhow::Result<AdfFile> {
+    let bytes = std::fs::read(file).with_context(|| format!("Failed to open {file:?}"))?;
+    AdfFile::from_bytes(bytes).with_context(|| format!("Failed to parse {file:?}"))
+}
+
+// Hash lists are plain text, with one string per line
+fn read_hashes(path: &PathBuf) -> anyhow::Result<HashList> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to open {path:?}"))?;
+    let mut hashes = HashList::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        hashes.insert_string(line);
+    }
+    Ok(hashes)
+}
+
+fn write_adf(args: &Args, adf: &AdfFile, output: &PathBuf) -> anyhow::Result<()> {
+    let file = std::fs::File::create(output)?;
+    let mut writer = std::io::BufWriter::new(file);
+    adf.write_options(
+        &mut writer,
+        endian_from_big(args.big_endian),
+        (AdfLayout::from_faithful(args.faithful),),
+    )?;
+    Ok(())
+}
+
+fn convert(args: &Args) -> anyhow::Result<()> {
+    let path = args.file.as_ref().context("No file specified")?;
+    if !path.is_file() {
+        bail!("{:?} is not a file", path);
     }
 
-    let extension = args
-        .file
+    let extension = path
         .extension()
         .and_then(std::ffi::OsStr::to_str)
         .context("Failed to determine file extension")?;
 
-    // Open the file
-    let file = std::fs::File::open(args.file.clone()).context("Failed to open file")?;
-    let mut reader = std::io::BufReader::new(file);
+    // Read the file
+    let bytes = std::fs::read(path).context("Failed to open file")?;
+
+    if args.validate {
+        // Load types based on extension, so types that aren't embedded can be resolved
+        let context = AdfReflectionContext::from_extension(extension)?;
+
+        // Report every problem, rather than failing on the first
+        let report = AdfFile::validate(&bytes, &context);
+        for issue in &report.issues {
+            println!("{issue}");
+        }
+        if !report.is_valid() {
+            bail!("{:?} has {} issue(s)", path, report.issues.len());
+        }
+        return Ok(());
+    }
 
     if extension == "xml" {
         // Parse the XML
-        let mut deserializer = quick_xml::de::Deserializer::from_reader(reader);
+        let mut deserializer = quick_xml::de::Deserializer::from_reader(bytes.as_slice());
         let adf = AdfXml::deserialize(&mut deserializer)?;
 
         // Load types based on extension
         let context = AdfReflectionContext::from_extension(&adf.extension)?;
 
         // Write ADF
-        let output = adf.convert(&context);
-        let file = std::fs::File::create(args.file.with_extension(""))?;
+        let output = adf.convert(&context).context("Failed to convert XML")?;
+        let file = std::fs::File::create(path.with_extension(""))?;
         let mut writer = std::io::BufWriter::new(file);
-        output.write_le(&mut writer)?;
+        output.write_options(
+            &mut writer,
+            endian_from_big(args.big_endian),
+            (AdfLayout::from_faithful(args.faithful),),
+        )?;
     } else {
         // Load types based on extension
         let context = AdfReflectionContext::from_extension(extension)?;
 
         // Parse the ADF, intentionally not loading additional types
-        let adf = AdfFile::read_le(&mut reader).context("Failed to parse ADF")?;
+        let adf = AdfFile::from_bytes(bytes).context("Failed to parse ADF")?;
 
         // Configure XML serializer
         let mut buffer = String::new();
@@ -50,9 +286,17 @@ fn main() -> anyhow::Result<()> {
         serializer.indent('\t', 1);
         serializer.expand_empty_elements(true);
 
-        // Write XML
-        AdfXml::new(&adf, &context, extension).serialize(serializer)?;
-        let mut file = std::fs::File::create(args.file.with_extension(format!("{extension}.xml")))?;
+        // Write XML, resolving string hashes if we have a hash list
+        let xml = match &args.hashes {
+            Some(hashes) => {
+                let hashes = read_hashes(hashes)?;
+                AdfXml::with_hashes(&adf, &context, extension, args.omit_defaults, &hashes)
+            }
+            None => AdfXml::new(&adf, &context, extension, args.omit_defaults),
+        };
+        xml.context("Failed to reflect ADF")?
+            .serialize(serializer)?;
+        let mut file = std::fs::File::create(path.with_extension(format!("{extension}.xml")))?;
         file.write_all(buffer.as_bytes())?;
     }
 
@@ -60,7 +304,130 @@ fn main() -> anyhow::Result<()> {
 }
 
 #[derive(Parser)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    #[arg()]
-    file: std::path::PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    file: Option<PathBuf>,
+    #[arg(long, global = true)]
+    faithful: bool,
+    #[arg(long, global = true)]
+    big_endian: bool,
+    #[arg(long)]
+    validate: bool,
+    #[arg(long)]
+    omit_defaults: bool,
+    #[arg(long)]
+    hashes: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Combines the types and instances of several ADFs into one
+    Merge {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Copies instances, and the types they use, into a new ADF
+    Extract {
+        #[arg()]
+        file: PathBuf,
+        #[arg(required = true)]
+        instances: Vec<String>,
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Reports the values which differ between two ADFs, matching instances by name
+    Diff {
+        #[arg()]
+        old: PathBuf,
+        #[arg()]
+        new: PathBuf,
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f64,
+        #[arg(long)]
+        xml: bool,
+    },
+    /// Applies partial edits to a base ADF
+    Overlay {
+        #[arg(required = true)]
+        overlays: Vec<PathBuf>,
+        #[arg(long)]
+        base: Option<PathBuf>,
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use mm_file_formats::adf::AdfReflectedScalar;
+
+    use super::*;
+
+    fn difference(path: &str, change: AdfChange) -> AdfDifference {
+        AdfDifference {
+            path: path.parse().unwrap(),
+            change,
+        }
+    }
+
+    #[test]
+    fn differences_are_written_as_xml() {
+        let differences = [
+            difference(
+                "Gears[2].Ratio",
+                AdfChange::Scalar {
+                    old: AdfReflectedScalar::F32(1.5),
+                    new: AdfReflectedScalar::F32(1.25),
+                },
+            ),
+            difference("Gears", AdfChange::ArrayLength { old: 3, new: 4 }),
+            difference(
+                "Engine",
+                AdfChange::Presence {
+                    old: false,
+                    new: true,
+                },
+            ),
+            difference(
+                "Name",
+                AdfChange::String {
+                    old: "A & B".into(),
+                    new: "C".into(),
+                },
+            ),
+            difference(
+                "Truck",
+                AdfChange::AddedInstance {
+                    type_name: "Vehicle".into(),
+                },
+            ),
+            difference(
+                "Car",
+                AdfChange::RemovedInstance {
+                    type_name: "Vehicle".into(),
+                },
+            ),
+        ];
+        assert_eq!(
+            diff_xml(&differences).unwrap(),
+            "<diff>\n\
+             \t<change path=\"Gears[2].Ratio\" kind=\"scalar\" old=\"1.5\" new=\"1.25\"/>\n\
+             \t<change path=\"Gears\" kind=\"length\" old=\"3\" new=\"4\"/>\n\
+             \t<change path=\"Engine\" kind=\"presence\" old=\"false\" new=\"true\"/>\n\
+             \t<change path=\"Name\" kind=\"string\" old=\"A &amp; B\" new=\"C\"/>\n\
+             \t<change path=\"Truck\" kind=\"added\" new=\"Vehicle\"/>\n\
+             \t<change path=\"Car\" kind=\"removed\" old=\"Vehicle\"/>\n\
+             </diff>"
+        );
+    }
+
+    #[test]
+    fn no_differences_are_an_empty_document() {
+        assert_eq!(diff_xml(&[]).unwrap(), "<diff/>");
+    }
 }
@@ -34,6 +34,7 @@ fn main() -> anyhow::Result<()> {
     out!("    sync::Arc,");
     out!("}};\n");
 
+    out!("use binrw::Endian;");
     out!("use mm_file_formats::adf::{{");
     out!("    AdfRead, AdfReadWriteError, AdfReaderReferences, AdfTypeInfo, AdfWrite, AdfWriterReferences,");
     out!("}};");
@@ -69,12 +70,13 @@ fn main() -> anyhow::Result<()> {
                 out!("    #[inline]");
                 out!("    fn read<R: Read + Seek>(");
                 out!("        reader: &mut R,");
+                out!("        endian: Endian,");
                 out!("        references: &mut AdfReaderReferences,");
                 out!("    ) -> Result<Self, AdfReadWriteError> {{");
                 out!("        Ok(Self {{");
                 for member in type_info.members.iter() {
                     out!(
-                        "            {}: AdfRead::read(reader, references)?,",
+                        "            {}: AdfRead::read(reader, endian, references)?,",
                         member.name.as_str().to_case(Case::Snake)
                     );
                 }
@@ -87,11 +89,12 @@ fn main() -> anyhow::Result<()> {
                 out!("    fn write<W: Write + Seek>(");
                 out!("        &self,");
                 out!("        writer: &mut W,");
+                out!("        endian: Endian,");
                 out!("        references: &mut AdfWriterReferences,");
                 out!("    ) -> Result<(), AdfReadWriteError> {{");
                 for member in type_info.members.iter() {
                     out!(
-                        "        self.{}.write(writer, references)?;",
+                        "        self.{}.write(writer, endian, references)?;",
                         member.name.as_str().to_case(Case::Snake)
                     );
                 }
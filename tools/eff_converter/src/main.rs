@@ -1,11 +1,11 @@
 use std::io::Write;
 
 use anyhow::{bail, Context};
-use binrw::{BinRead, BinWrite};
+use binrw::BinWrite;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
-use mm_file_formats::adf::AdfFile;
+use mm_file_formats::adf::{AdfFile, AdfLayout};
 
 mod adf;
 use adf::EffectRTSystem;
@@ -55,14 +55,13 @@ fn main() -> anyhow::Result<()> {
         .and_then(std::ffi::OsStr::to_str)
         .context("Failed to determine file extension")?;
 
-    // Open the file
-    let file = std::fs::File::open(args.file.clone()).context("Failed to open file")?;
-    let mut reader = std::io::BufReader::new(file);
+    // Read the file
+    let bytes = std::fs::read(&args.file).context("Failed to open file")?;
 
     match extension {
         "effc" => {
             // Parse the ADF
-            let adf = AdfFile::read_le(&mut reader).context("Failed to parse ADF")?;
+            let adf = AdfFile::from_bytes(bytes).context("Failed to parse ADF")?;
 
             // Find associated ADF instance
             let instance = adf
@@ -271,18 +270,17 @@ fn main() -> anyhow::Result<()> {
         }
         "xml" => {
             // Parse the XML
-            let mut deserializer = quick_xml::de::Deserializer::from_reader(reader);
+            let mut deserializer = quick_xml::de::Deserializer::from_reader(bytes.as_slice());
             let effect = XmlEffectRTSystem::deserialize(&mut deserializer)?;
 
             // Parse the ADF
-            let file = std::fs::File::open(args.file.with_extension("effc"))
-                .context("Failed to open effc")?;
-            let mut reader = std::io::BufReader::new(file);
-            let adf = AdfFile::read_le(&mut reader).context("Failed to parse ADF")?;
+            let bytes =
+                std::fs::read(args.file.with_extension("effc")).context("Failed to open effc")?;
+            let mut adf = AdfFile::from_bytes(bytes).context("Failed to parse ADF")?;
 
             // Find associated ADF instance
             let instance = adf
-                .get_instance_by_info::<EffectRTSystem>("RuntimeEffect")
+                .get_instance_mut_by_info::<EffectRTSystem>("RuntimeEffect")
                 .context("failed to find matching instance")?;
 
             // Read associated instance
@@ -370,13 +368,18 @@ fn main() -> anyhow::Result<()> {
             // Update params
             adf_effect.params = params.into();
 
-            // Write effect to existing instance
+            // Write effect to existing instance, keeping its byte order
             instance.write(&adf_effect)?;
+            let endian = instance.endian;
 
             // Finally write it to disk
             let mut file = std::fs::File::create(args.file.with_extension("effc"))?;
             let mut writer = std::io::BufWriter::new(&mut file);
-            adf.write_le(&mut writer)?;
+            adf.write_options(
+                &mut writer,
+                endian,
+                (AdfLayout::from_faithful(args.faithful),),
+            )?;
         }
         extension => {
             bail!("This tool does not support the '{extension}' extension");
@@ -390,4 +393,6 @@ fn main() -> anyhow::Result<()> {
 struct Args {
     #[arg()]
     file: std::path::PathBuf,
+    #[arg(long)]
+    faithful: bool,
 }
@@ -1,10 +1,9 @@
 use std::sync::Arc;
 
-use mm_file_formats::adf::{
-    AdfRead, AdfReaderReferences, AdfTypeInfo, AdfWrite, AdfWriterReferences,
-};
+use mm_file_formats::adf::{AdfRead, AdfTypeInfo, AdfWrite};
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, PartialEq, AdfTypeInfo, AdfRead, AdfWrite)]
+#[adf(name = "XLSBook", hash = 192098653)]
 pub struct XlsBook {
     pub sheet: Arc<Vec<XlsSheet>>,
     pub cell: Arc<Vec<XlsCell>>,
@@ -16,50 +15,8 @@ pub struct XlsBook {
     pub attribute: Arc<Vec<XlsAttribute>>,
 }
 
-impl AdfTypeInfo for XlsBook {
-    const NAME: &str = "XLSBook";
-    const HASH: u32 = 192098653;
-    const SIZE: u64 = 128;
-    const ALIGN: u64 = 8;
-}
-
-impl AdfRead for XlsBook {
-    fn read<R: std::io::Read + std::io::Seek>(
-        reader: &mut R,
-        references: &mut AdfReaderReferences,
-    ) -> Result<Self, mm_file_formats::adf::AdfReadWriteError> {
-        Ok(Self {
-            sheet: AdfRead::read(reader, references)?,
-            cell: AdfRead::read(reader, references)?,
-            string_data: AdfRead::read(reader, references)?,
-            value_data: AdfRead::read(reader, references)?,
-            bool_data: AdfRead::read(reader, references)?,
-            date_data: AdfRead::read(reader, references)?,
-            color_data: AdfRead::read(reader, references)?,
-            attribute: AdfRead::read(reader, references)?,
-        })
-    }
-}
-
-impl AdfWrite for XlsBook {
-    fn write<W: std::io::Write + std::io::Seek>(
-        &self,
-        writer: &mut W,
-        references: &mut AdfWriterReferences,
-    ) -> Result<(), mm_file_formats::adf::AdfReadWriteError> {
-        self.sheet.write(writer, references)?;
-        self.cell.write(writer, references)?;
-        self.string_data.write(writer, references)?;
-        self.value_data.write(writer, references)?;
-        self.bool_data.write(writer, references)?;
-        self.date_data.write(writer, references)?;
-        self.color_data.write(writer, references)?;
-        self.attribute.write(writer, references)?;
-        Ok(())
-    }
-}
-
-#[derive(Default, Debug)]
+#[derive(Default, Debug, PartialEq, AdfTypeInfo, AdfRead, AdfWrite)]
+#[adf(name = "XLSSheet", hash = 3649567627)]
 pub struct XlsSheet {
     pub cols: u32,
     pub rows: u32,
@@ -67,114 +24,275 @@ pub struct XlsSheet {
     pub name: Arc<String>,
 }
 
-impl AdfTypeInfo for XlsSheet {
-    const NAME: &str = "XLSSheet";
-    const HASH: u32 = 3649567627;
-    const SIZE: u64 = 32;
-    const ALIGN: u64 = 8;
-}
-
-impl AdfRead for XlsSheet {
-    fn read<R: std::io::Read + std::io::Seek>(
-        reader: &mut R,
-        references: &mut AdfReaderReferences,
-    ) -> Result<Self, mm_file_formats::adf::AdfReadWriteError> {
-        Ok(Self {
-            cols: AdfRead::read(reader, references)?,
-            rows: AdfRead::read(reader, references)?,
-            cell_index: AdfRead::read(reader, references)?,
-            name: AdfRead::read(reader, references)?,
-        })
-    }
-}
-
-impl AdfWrite for XlsSheet {
-    fn write<W: std::io::Write + std::io::Seek>(
-        &self,
-        writer: &mut W,
-        references: &mut AdfWriterReferences,
-    ) -> Result<(), mm_file_formats::adf::AdfReadWriteError> {
-        self.cols.write(writer, references)?;
-        self.rows.write(writer, references)?;
-        self.cell_index.write(writer, references)?;
-        self.name.write(writer, references)?;
-        Ok(())
-    }
-}
-
-#[derive(Default, Debug, Clone, Hash, Eq, PartialEq)]
+#[derive(Default, Debug, Clone, Hash, Eq, PartialEq, AdfTypeInfo, AdfRead, AdfWrite)]
+#[adf(name = "XLSCell", hash = 2598569309)]
 pub struct XlsCell {
     pub kind: u16,
     pub data_index: u32,
     pub attribute_index: u32,
 }
 
-impl AdfTypeInfo for XlsCell {
-    const NAME: &str = "XLSCell";
-    const HASH: u32 = 2598569309;
-    const SIZE: u64 = 12;
-    const ALIGN: u64 = 4;
+#[derive(Default, Debug, Clone, Hash, Eq, PartialEq, AdfTypeInfo, AdfRead, AdfWrite)]
+#[adf(name = "XLSAttribute", hash = 2397202994)]
+pub struct XlsAttribute {
+    pub fg_color_index: u8,
+    pub bg_color_index: u8,
 }
 
-impl AdfRead for XlsCell {
-    fn read<R: std::io::Read + std::io::Seek>(
-        reader: &mut R,
-        references: &mut AdfReaderReferences,
-    ) -> Result<Self, mm_file_formats::adf::AdfReadWriteError> {
-        Ok(Self {
-            kind: AdfRead::read(reader, references)?,
-            data_index: AdfRead::read(reader, references)?,
-            attribute_index: AdfRead::read(reader, references)?,
-        })
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use binrw::Endian;
+    use mm_file_formats::adf::{
+        AdfFile, AdfInstance, AdfLayout, AdfRead, AdfReflectionContext, AdfReflectionErrorKind,
+        AdfWrite, TYPE_LIBRARIES,
+    };
+
+    use super::*;
+
+    fn library() -> AdfFile {
+        TYPE_LIBRARIES
+            .iter()
+            .find(|lib| lib.extension == "xlsc")
+            .unwrap()
+            .load()
+            .unwrap()
     }
-}
 
-impl AdfWrite for XlsCell {
-    fn write<W: std::io::Write + std::io::Seek>(
-        &self,
-        writer: &mut W,
-        references: &mut AdfWriterReferences,
-    ) -> Result<(), mm_file_formats::adf::AdfReadWriteError> {
-        self.kind.write(writer, references)?;
-        self.data_index.write(writer, references)?;
-        self.attribute_index.write(writer, references)?;
-        Ok(())
+    fn layout<T: AdfTypeInfo>() -> (&'static str, u32, u64, u64) {
+        (T::NAME, T::HASH, T::SIZE, T::ALIGN)
     }
-}
 
-#[derive(Default, Debug, Clone, Hash, Eq, PartialEq)]
-pub struct XlsAttribute {
-    pub fg_color_index: u8,
-    pub bg_color_index: u8,
-}
+    // The layouts these structures had when written by hand, before being derived
+    #[test]
+    fn layouts_match_hand_written() {
+        assert_eq!(layout::<XlsBook>(), ("XLSBook", 192098653, 128, 8));
+        assert_eq!(layout::<XlsSheet>(), ("XLSSheet", 3649567627, 32, 8));
+        assert_eq!(layout::<XlsCell>(), ("XLSCell", 2598569309, 12, 4));
+        assert_eq!(layout::<XlsAttribute>(), ("XLSAttribute", 2397202994, 2, 1));
+    }
 
-impl AdfTypeInfo for XlsAttribute {
-    const NAME: &str = "XLSAttribute";
-    const HASH: u32 = 2397202994;
-    const SIZE: u64 = 2;
-    const ALIGN: u64 = 1;
-}
+    #[test]
+    fn layouts_match_type_library() {
+        let library = library();
+        for (hash, size, alignment) in [
+            (XlsBook::HASH, XlsBook::SIZE, XlsBook::ALIGN),
+            (XlsSheet::HASH, XlsSheet::SIZE, XlsSheet::ALIGN),
+            (XlsCell::HASH, XlsCell::SIZE, XlsCell::ALIGN),
+            (XlsAttribute::HASH, XlsAttribute::SIZE, XlsAttribute::ALIGN),
+        ] {
+            let type_def = library.get_type_by_hash(hash).unwrap();
+            assert_eq!(
+                (type_def.size as u64, type_def.alignment as u64),
+                (size, alignment)
+            );
+        }
+    }
+
+    #[test]
+    fn cells_are_written_as_hand_written() {
+        let cell = XlsCell {
+            kind: 1,
+            data_index: 2,
+            attribute_index: 3,
+        };
+        let mut buffer = Cursor::new(vec![]);
+        cell.write(&mut buffer, Endian::Little, &mut Default::default())
+            .unwrap();
+        assert_eq!(buffer.get_ref(), &[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0]);
 
-impl AdfRead for XlsAttribute {
-    fn read<R: std::io::Read + std::io::Seek>(
-        reader: &mut R,
-        references: &mut AdfReaderReferences,
-    ) -> Result<Self, mm_file_formats::adf::AdfReadWriteError> {
-        Ok(Self {
-            fg_color_index: AdfRead::read(reader, references)?,
-            bg_color_index: AdfRead::read(reader, references)?,
-        })
+        buffer.set_position(0);
+        let read = XlsCell::read(&mut buffer, Endian::Little, &mut Default::default()).unwrap();
+        assert_eq!(read, cell);
     }
-}
 
-impl AdfWrite for XlsAttribute {
-    fn write<W: std::io::Write + std::io::Seek>(
-        &self,
-        writer: &mut W,
-        references: &mut AdfWriterReferences,
-    ) -> Result<(), mm_file_formats::adf::AdfReadWriteError> {
-        self.fg_color_index.write(writer, references)?;
-        self.bg_color_index.write(writer, references)?;
-        Ok(())
+    fn book() -> XlsBook {
+        let name = Arc::new("Sheet".to_owned());
+        XlsBook {
+            sheet: vec![XlsSheet {
+                cols: 2,
+                rows: 1,
+                cell_index: vec![0, 1].into(),
+                name: name.clone(),
+            }]
+            .into(),
+            cell: vec![
+                XlsCell {
+                    kind: 1,
+                    data_index: 0,
+                    attribute_index: 0,
+                },
+                XlsCell {
+                    kind: 2,
+                    data_index: 0,
+                    attribute_index: 1,
+                },
+            ]
+            .into(),
+            string_data: vec![name].into(),
+            value_data: vec![1.5].into(),
+            color_data: vec![0xFF0000FF, 0xFFFFFFFF].into(),
+            attribute: vec![
+                XlsAttribute {
+                    fg_color_index: 0,
+                    bg_color_index: 1,
+                },
+                XlsAttribute {
+                    fg_color_index: 1,
+                    bg_color_index: 0,
+                },
+            ]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn book_round_trips() {
+        let book = book();
+        let mut instance = AdfInstance {
+            type_hash: XlsBook::HASH,
+            ..Default::default()
+        };
+        instance.write(&book).unwrap();
+        let read: XlsBook = instance.read().unwrap();
+        assert_eq!(read, book);
+    }
+
+    // Ends with padding the hand-written structures didn't account for
+    #[derive(Debug, PartialEq, AdfTypeInfo, AdfRead, AdfWrite)]
+    struct Padded {
+        value: u32,
+        flag: u8,
+    }
+
+    #[test]
+    fn structures_take_up_their_size() {
+        assert_eq!(Padded::SIZE, 8);
+
+        let value = Padded { value: 1, flag: 2 };
+        let mut buffer = Cursor::new(vec![]);
+        let mut references = Default::default();
+        value
+            .write(&mut buffer, Endian::Little, &mut references)
+            .unwrap();
+        assert_eq!(buffer.position(), Padded::SIZE);
+        3u8.write(&mut buffer, Endian::Little, &mut references)
+            .unwrap();
+        assert_eq!(buffer.get_ref(), &[1, 0, 0, 0, 2, 0, 0, 0, 3]);
+
+        buffer.set_position(0);
+        let mut references = Default::default();
+        let read = Padded::read(&mut buffer, Endian::Little, &mut references).unwrap();
+        assert_eq!(buffer.position(), Padded::SIZE);
+        assert_eq!(read, value);
+        assert_eq!(
+            u8::read(&mut buffer, Endian::Little, &mut references).unwrap(),
+            3
+        );
+    }
+
+    // Big endian instances are read, reflected and written in their own byte order
+    #[test]
+    fn big_endian_books_convert() {
+        let mut adf = library();
+        adf.instances.clear();
+        adf.new_instance_from_info::<XlsBook>("XLSBook")
+            .unwrap()
+            .write(&book())
+            .unwrap();
+        let mut writer = Cursor::new(vec![]);
+        binrw::BinWrite::write_options(&adf, &mut writer, Endian::Big, (AdfLayout::Aligned,))
+            .unwrap();
+        let adf = AdfFile::from_bytes(writer.into_inner()).unwrap();
+        let instance = adf.get_instance_by_info::<XlsBook>("XLSBook").unwrap();
+        assert_eq!(instance.endian, Endian::Big);
+        assert_eq!(instance.read::<XlsBook>().unwrap(), book());
+
+        let mut context = AdfReflectionContext::from_extension("xlsc").unwrap();
+        context.load_types_from_file(&adf);
+        let value = context.read_instance(&instance).unwrap();
+        assert_eq!(context.to_typed::<XlsBook>(&value).unwrap(), book());
+        assert_eq!(context.from_typed(&book()).unwrap(), value);
+
+        let mut instance = (*instance).clone();
+        context.write_instance_value(&value, &mut instance).unwrap();
+        assert_eq!(instance.endian, Endian::Big);
+        assert_eq!(instance.read::<XlsBook>().unwrap(), book());
+    }
+
+    #[test]
+    fn typed_books_round_trip() {
+        let context = AdfReflectionContext::from_extension("xlsc").unwrap();
+        let value = context.from_typed(&book()).unwrap();
+        assert_eq!(value.0, XlsBook::HASH);
+        assert_eq!(context.to_typed::<XlsBook>(&value).unwrap(), book());
+
+        // The same as reading an instance the book was written to
+        let mut instance = AdfInstance {
+            type_hash: XlsBook::HASH,
+            ..Default::default()
+        };
+        instance.write(&book()).unwrap();
+        assert_eq!(context.read_instance(&instance).unwrap(), value);
+
+        // Values within the book convert to their own types
+        let sheet = context.get(&value, &"Sheet[0]".parse().unwrap()).unwrap();
+        assert_eq!(
+            context.to_typed::<XlsSheet>(sheet).unwrap(),
+            book().sheet[0]
+        );
+    }
+
+    #[test]
+    fn typed_values_must_be_of_the_type() {
+        let context = AdfReflectionContext::from_extension("xlsc").unwrap();
+        let value = context.from_typed(&book()).unwrap();
+        let error = context.to_typed::<XlsSheet>(&value).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            AdfReflectionErrorKind::TypeMismatch { expected, found }
+                if expected == XlsSheet::HASH && found == XlsBook::HASH
+        ));
+        assert_eq!(error.type_name, "XLSSheet");
+    }
+
+    #[test]
+    fn typed_layouts_must_match_the_loaded_type() {
+        let context = AdfReflectionContext::from_extension("xlsc").unwrap();
+        let value = context.from_typed(&book()).unwrap();
+
+        // A library whose book has grown since the structure was derived
+        let mut library = library();
+        let book_type = library
+            .types
+            .iter_mut()
+            .find(|x| x.type_hash == XlsBook::HASH)
+            .unwrap();
+        book_type.size += 8;
+        let mut grown = AdfReflectionContext::from_extension("").unwrap();
+        grown.load_types_from_file(&library);
+
+        let is_layout = |kind: &AdfReflectionErrorKind| {
+            matches!(
+                kind,
+                AdfReflectionErrorKind::TypedLayout {
+                    size: 128,
+                    alignment: 8
+                }
+            )
+        };
+        let error = grown.from_typed(&book()).unwrap_err();
+        assert!(is_layout(&error.kind), "from_typed: {error}");
+        let error = grown.to_typed::<XlsBook>(&value).unwrap_err();
+        assert!(is_layout(&error.kind), "to_typed: {error}");
+
+        // Without the type at all there's nothing to check the layout against
+        let empty = AdfReflectionContext::from_extension("").unwrap();
+        let error = empty.from_typed(&book()).unwrap_err();
+        assert!(
+            matches!(error.kind, AdfReflectionErrorKind::MissingType(hash) if hash == XlsBook::HASH)
+        );
     }
 }
@@ -1,11 +1,14 @@
 use std::{collections::HashMap, hash::Hash, io::Write, sync::Arc};
 
 use anyhow::{bail, Context};
-use binrw::{BinRead, BinWrite};
+use binrw::BinWrite;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
-use mm_file_formats::adf::{AdfFile, TYPE_LIBRARIES};
+use mm_file_formats::{
+    adf::{AdfFile, AdfLayout, TYPE_LIBRARIES},
+    common::endian_from_big,
+};
 
 mod adf;
 use adf::{XlsAttribute, XlsBook, XlsCell, XlsSheet};
@@ -26,14 +29,13 @@ fn main() -> anyhow::Result<()> {
         .and_then(std::ffi::OsStr::to_str)
         .context("Failed to determine file extension")?;
 
-    // Open the file
-    let file = std::fs::File::open(args.file.clone()).context("Failed to open file")?;
-    let mut reader = std::io::BufReader::new(file);
+    // Read the file
+    let bytes = std::fs::read(&args.file).context("Failed to open file")?;
 
     match extension {
         "xlsc" => {
             // Parse the ADF
-            let adf = AdfFile::read_le(&mut reader).context("Failed to parse ADF")?;
+            let adf = AdfFile::from_bytes(bytes).context("Failed to parse ADF")?;
 
             // Find associated ADF instance
             let instance = adf
@@ -135,7 +137,7 @@ fn main() -> anyhow::Result<()> {
         }
         "xml" => {
             // Parse the XML
-            let mut deserializer = quick_xml::de::Deserializer::from_reader(reader);
+            let mut deserializer = quick_xml::de::Deserializer::from_reader(bytes.as_slice());
             let xml_book = XmlBook::deserialize(&mut deserializer)?;
 
             let mut cells = Collection::<XlsCell>::default();
@@ -235,7 +237,11 @@ fn main() -> anyhow::Result<()> {
             // Finally write it to disk
             let mut file = std::fs::File::create(args.file.with_extension("xlsc"))?;
             let mut writer = std::io::BufWriter::new(&mut file);
-            adf.write_le(&mut writer)?;
+            adf.write_options(
+                &mut writer,
+                endian_from_big(args.big_endian),
+                (AdfLayout::from_faithful(args.faithful),),
+            )?;
         }
         extension => {
             bail!("This tool does not support the '{extension}' extension");
@@ -249,6 +255,10 @@ fn main() -> anyhow::Result<()> {
 struct Args {
     #[arg()]
     file: std::path::PathBuf,
+    #[arg(long)]
+    faithful: bool,
+    #[arg(long)]
+    big_endian: bool,
 }
 
 #[derive(Default)]